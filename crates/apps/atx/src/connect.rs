@@ -0,0 +1,181 @@
+use clap::ArgMatches;
+use linq::error::*;
+use linq::http::{Fingerprint, TlsConfig};
+use linq::io::Io;
+use linq::{CurveConfig, UsbMetadata};
+use std::io::Write;
+
+/// How devices are verified over https and encrypted over zmtps. (From --ca,
+/// --pin, --insecure, --curve-key, --curve-public and --curve-secret)
+pub struct Security {
+    tls: TlsConfig,
+    curve: CurveConfig,
+}
+
+/// Split KEY=VALUE
+fn pair<'a>(name: &str, arg: &'a str) -> Result<(&'a str, &'a str)> {
+    arg.split_once('=').ok_or_else(|| {
+        let e = format!("--{} expects KEY=VALUE [{}]", name, arg);
+        LinqError::InvalidArgument(e)
+    })
+}
+
+impl Security {
+    pub fn from_args(cli: &ArgMatches) -> Result<Self> {
+        let mut tls = TlsConfig::new().insecure(cli.is_present("insecure"));
+        if let Some(file) = cli.value_of("ca") {
+            tls = tls.ca_bundle(file)?;
+        }
+        for arg in cli.values_of("pin").into_iter().flatten() {
+            let (key, fingerprint) = pair("pin", arg)?;
+            tls = tls.pin(key, fingerprint.parse::<Fingerprint>()?);
+        }
+        let mut curve = CurveConfig::new();
+        for arg in cli.values_of("curve-key").into_iter().flatten() {
+            let (key, public) = pair("curve-key", arg)?;
+            curve = curve.server_key(key, public)?;
+        }
+        let keypair =
+            (cli.value_of("curve-public"), cli.value_of("curve-secret"));
+        if let (Some(public), Some(secret)) = keypair {
+            curve = curve.keypair(public, secret)?;
+        }
+        Ok(Security { tls, curve })
+    }
+
+    pub fn apply(self, linq: &mut Io) {
+        linq.set_tls(self.tls);
+        linq.set_curve(self.curve);
+    }
+}
+
+/// A new Io that verifies (and encrypts) network devices as the command line
+/// says
+pub fn open(cli: &ArgMatches) -> Result<Io> {
+    let security = Security::from_args(cli)?;
+    let mut linq = Io::new();
+    security.apply(&mut linq);
+    Ok(linq)
+}
+
+/// Open a channel to the device the command line points at over the chosen
+/// protocol. Returns the serial number (or address) to send requests to
+pub async fn connect(linq: &mut Io, cli: &ArgMatches<'_>) -> Result<String> {
+    let protocol = cli.value_of("protocol").unwrap_or("usb");
+    let serial = cli.value_of("serial");
+    let address = cli.value_of("address");
+    match (protocol, serial, address) {
+        ("usb", _, _) => select(linq.scan().await?, cli),
+        ("http", _, Some(address)) => {
+            linq.connect_http(address);
+            Ok(address.to_owned())
+        }
+        ("https", _, Some(address)) => {
+            linq.connect_https(address);
+            Ok(address.to_owned())
+        }
+        ("zmtp", Some(serial), Some(address)) => {
+            linq.connect_zmtp(serial, address);
+            Ok(serial.to_owned())
+        }
+        ("zmtps", Some(serial), Some(address)) => {
+            linq.connect_zmtps(serial, address);
+            Ok(serial.to_owned())
+        }
+        ("zmtp", None, _) | ("zmtps", None, _) => {
+            let e = format!("protocol {} requires --serial", protocol);
+            Err(LinqError::InvalidArgument(e))
+        }
+        (protocol, _, None) => {
+            let e = format!("protocol {} requires --address", protocol);
+            Err(LinqError::InvalidArgument(e))
+        }
+        (protocol, _, _) => {
+            let e = format!("protocol {} not supported", protocol);
+            Err(LinqError::InvalidArgument(e))
+        }
+    }
+}
+
+/// Pick one of the usb devices by --serial and --product. When more than one
+/// device matches the user is asked to pick one (with --pick)
+fn select(devices: Vec<UsbMetadata>, cli: &ArgMatches) -> Result<String> {
+    let serial = cli.value_of("serial");
+    let product = cli.value_of("product");
+    let found: Vec<UsbMetadata> = devices
+        .into_iter()
+        .filter(|x| match serial {
+            Some(s) => x.serial == s,
+            None => true,
+        })
+        .filter(|x| match (product, &x.about) {
+            (Some(p), Some(a)) => a.product.eq_ignore_ascii_case(p),
+            (Some(_), None) => false,
+            (None, _) => true,
+        })
+        .collect();
+    match (found.len(), serial) {
+        (0, Some(s)) => Err(IoError::DeviceNotFound(s.to_owned()).into()),
+        (0, None) => Err(LinqError::NoDevice),
+        (1, _) => Ok(found[0].serial.clone()),
+        _ if cli.is_present("pick") => pick(&found),
+        _ => {
+            let serials: Vec<&str> =
+                found.iter().map(|x| x.serial.as_str()).collect();
+            Err(LinqError::AmbiguousDevice(serials.join(", ")))
+        }
+    }
+}
+
+/// Ask the user which device to use
+fn pick(devices: &[UsbMetadata]) -> Result<String> {
+    for (i, x) in devices.iter().enumerate() {
+        let product = x.about.as_ref().map_or("", |a| &a.product);
+        eprintln!("[{}] {} {}", i, x.serial, product);
+    }
+    eprint!("pick a device: ");
+    std::io::stderr().flush()?;
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line)?;
+    line.trim()
+        .parse::<usize>()
+        .ok()
+        .and_then(|i| devices.get(i))
+        .map(|x| x.serial.clone())
+        .ok_or_else(|| {
+            let e = format!("no device [{}]", line.trim());
+            LinqError::InvalidArgument(e)
+        })
+}
+
+/// Open a channel to a network device described by a url IE:
+/// http://10.0.0.1, https://10.0.0.1 or zmtp://SERIAL@10.0.0.2:33455 (or
+/// zmtps://...). Returns the serial number (or address) of the device
+pub fn connect_url(linq: &mut Io, url: &str) -> Result<String> {
+    let (scheme, rest) = match url.find("://") {
+        Some(i) => (&url[..i], &url[i + 3..]),
+        None => ("", url),
+    };
+    match (scheme, rest.split_once('@')) {
+        ("http", _) => {
+            linq.connect_http(url);
+            Ok(url.to_owned())
+        }
+        ("https", _) => {
+            linq.connect_https(url);
+            Ok(url.to_owned())
+        }
+        ("zmtp", Some((serial, address))) => {
+            linq.connect_zmtp(serial, address);
+            Ok(serial.to_owned())
+        }
+        ("zmtps", Some((serial, address))) => {
+            linq.connect_zmtps(serial, address);
+            Ok(serial.to_owned())
+        }
+        _ => {
+            let e = format!("unsupported address [{}]", url);
+            Err(LinqError::InvalidArgument(e))
+        }
+    }
+}
@@ -1,3 +1,6 @@
+#[cfg(test)]
+mod tests;
+
 #[macro_use]
 extern crate clap;
 extern crate futures;
@@ -10,43 +13,84 @@ extern crate slog_scope;
 extern crate slog_stdlog;
 extern crate slog_term;
 
+mod connect;
 mod logger;
+mod output;
+mod process_apply;
 mod process_cmd;
+mod process_config;
 mod process_ipconfig;
+mod process_list;
+mod process_rollout;
+mod process_serve;
+mod process_shell;
+mod process_trace;
 mod process_update;
 
-/// logging
-use log::error;
-
 /// Cli
-use clap::{load_yaml, App};
+use clap::{load_yaml, App, ErrorKind};
+use output::{Format, EXIT_USAGE};
 
 /// App
+use process_apply::*;
 use process_cmd::*;
+use process_config::*;
 use process_ipconfig::*;
+use process_list::*;
+use process_rollout::*;
+use process_serve::*;
+use process_shell::*;
+use process_trace::*;
 use process_update::*;
 
 fn main() {
     // parse cli
     let yaml = load_yaml!("cli.yaml");
-    let m = App::from(yaml).get_matches();
+    let m = match App::from(yaml).get_matches_safe() {
+        Ok(m) => m,
+        Err(e) => match e.kind {
+            ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed => e.exit(),
+            _ => {
+                eprintln!("{}", e.message);
+                std::process::exit(EXIT_USAGE)
+            }
+        },
+    };
+    let code = run(&m);
+    std::process::exit(code)
+}
 
+/// Run a subcommand and return the exit code. (Loggers are flushed when they
+/// are dropped so we must return before exit)
+fn run(m: &clap::ArgMatches) -> i32 {
     // Init logger
     let logger = logger::init(m.is_present("verbose"));
     let _scope_guard = slog_scope::set_global_logger(logger);
     let _log_guard = slog_stdlog::init().unwrap();
 
-    let result = if let Some(cmd) = m.subcommand_matches("cmd") {
+    let format = Format::from_arg(m.value_of("output"));
+    let result = if let Some(cmd) = m.subcommand_matches("apply") {
+        process_apply(cmd)
+    } else if let Some(cmd) = m.subcommand_matches("cmd") {
         process_cmd(cmd)
+    } else if let Some(cmd) = m.subcommand_matches("config") {
+        process_config(cmd)
     } else if let Some(cmd) = m.subcommand_matches("ipconfig") {
         process_ipconfig(cmd)
     } else if let Some(cmd) = m.subcommand_matches("update") {
         process_update(cmd)
+    } else if let Some(cmd) = m.subcommand_matches("list") {
+        process_list(cmd)
+    } else if let Some(cmd) = m.subcommand_matches("rollout") {
+        process_rollout(cmd)
+    } else if let Some(cmd) = m.subcommand_matches("serve") {
+        process_serve(cmd)
+    } else if let Some(cmd) = m.subcommand_matches("shell") {
+        process_shell(cmd)
+    } else if let Some(cmd) = m.subcommand_matches("trace") {
+        process_trace(cmd)
     } else {
-        Err(linq::error::LinqError::Unknown)
+        Err(linq::error::LinqError::Unknown.into())
     };
-    match result {
-        Ok(result) => println!("{}", result),
-        Err(e) => error!("{}", e),
-    }
+    output::print(format, result)
 }
@@ -0,0 +1,167 @@
+use linq::error::*;
+use log::error;
+use serde_json::{json, Value};
+
+/// Exit codes so scripts can tell failures apart
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_UNKNOWN: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_API: i32 = 3;
+pub const EXIT_TRANSPORT: i32 = 4;
+
+/// How results are printed (see --output)
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+impl Format {
+    pub fn from_arg(arg: Option<&str>) -> Self {
+        match arg {
+            Some("json") => Format::Json,
+            _ => Format::Text,
+        }
+    }
+}
+
+/// What a command did, and to which device
+#[derive(Clone, Debug, Default)]
+pub struct Report {
+    pub serial: Option<String>,
+    pub path: Option<String>,
+    pub body: String,
+}
+
+impl Report {
+    pub fn new<S: Into<String>>(body: S) -> Self {
+        Report {
+            body: body.into(),
+            ..Report::default()
+        }
+    }
+
+    pub fn serial(self, serial: &str) -> Self {
+        Report {
+            serial: Some(serial.to_owned()),
+            ..self
+        }
+    }
+
+    pub fn path(self, path: &str) -> Self {
+        Report {
+            path: Some(path.to_owned()),
+            ..self
+        }
+    }
+
+    /// Same as self except with a [body]
+    pub fn body<S: Into<String>>(self, body: S) -> Self {
+        Report {
+            body: body.into(),
+            ..self
+        }
+    }
+
+    /// The command failed after getting as far as this report
+    pub fn fail<E: Into<LinqError>>(self, error: E) -> Box<Failure> {
+        Box::new(Failure {
+            report: self,
+            error: error.into(),
+        })
+    }
+}
+
+/// A command failed. (The report says how far it got)
+#[derive(Debug)]
+pub struct Failure {
+    pub report: Report,
+    pub error: LinqError,
+}
+
+impl From<LinqError> for Box<Failure> {
+    fn from(e: LinqError) -> Self {
+        Report::default().fail(e)
+    }
+}
+
+impl From<IoError> for Box<Failure> {
+    fn from(e: IoError) -> Self {
+        Report::default().fail(e)
+    }
+}
+
+impl From<std::io::Error> for Box<Failure> {
+    fn from(e: std::io::Error) -> Self {
+        Report::default().fail(e)
+    }
+}
+
+pub type Outcome = std::result::Result<Report, Box<Failure>>;
+
+/// Which exit code a failure is reported with
+pub fn exit_code(e: &LinqError) -> i32 {
+    match e {
+        LinqError::Io(IoError::ApiError(_)) | LinqError::Verify(_) => EXIT_API,
+        LinqError::Io(_) | LinqError::StdIo(_) | LinqError::NoDevice => {
+            EXIT_TRANSPORT
+        }
+        LinqError::InvalidArgument(_)
+        | LinqError::AmbiguousDevice(_)
+        | LinqError::Parser(_) => EXIT_USAGE,
+        LinqError::Unknown => EXIT_UNKNOWN,
+    }
+}
+
+/// Print the outcome of a command and return the exit code
+pub fn print(format: Format, outcome: Outcome) -> i32 {
+    match (format, outcome) {
+        (Format::Text, Ok(report)) => {
+            if !report.body.is_empty() {
+                println!("{}", report.body);
+            }
+            EXIT_OK
+        }
+        (Format::Text, Err(failure)) => {
+            if !failure.report.body.is_empty() {
+                println!("{}", failure.report.body);
+            }
+            error!("{}", failure.error);
+            exit_code(&failure.error)
+        }
+        (Format::Json, Ok(report)) => {
+            println!("{}", json(&report, None, None));
+            EXIT_OK
+        }
+        (Format::Json, Err(failure)) => {
+            let Failure { report, error } = *failure;
+            let status = match &error {
+                LinqError::Io(IoError::ApiError(e)) => Some(e.code),
+                _ => None,
+            };
+            println!("{}", json(&report, status, Some(&error)));
+            exit_code(&error)
+        }
+    }
+}
+
+/// {serial, path, status, body, error}. The body is embedded as JSON when
+/// it is JSON. The status is the error code the device answered with (null
+/// when it did not report one)
+fn json(
+    report: &Report,
+    status: Option<i64>,
+    error: Option<&LinqError>,
+) -> Value {
+    let body = match report.body.as_str() {
+        "" => Value::Null,
+        s => serde_json::from_str(s).unwrap_or(Value::String(s.to_owned())),
+    };
+    json!({
+        "serial": report.serial,
+        "path": report.path,
+        "status": status,
+        "body": body,
+        "error": error.map(|e| e.to_string()),
+    })
+}
@@ -0,0 +1,373 @@
+use crate::connect::{connect_url, open};
+use crate::output::{Outcome, Report};
+use crate::process_config::*;
+use clap::ArgMatches;
+use futures::executor::block_on;
+use linq::error::*;
+use linq::io::Io;
+use linq::Request;
+use linq::UsbMetadata;
+use serde_json::{json, Map, Number, Value};
+use yaml_rust::{Yaml, YamlLoader};
+
+/// A site file. IE:
+///
+/// ```yaml
+/// reboot: true
+/// settings:               # every device gets these
+///   /ATX/about:
+///     siteId: site-a
+/// devices:
+///   - product: LINQ2      # every usb LINQ2
+///   - serial: N4A10001    # a usb device (with its own settings)
+///     settings:
+///       /ATX/network:
+///         ipConfig:
+///           ip: 192.168.0.10
+///   - address: zmtp://N4A10002@10.0.0.2:33455
+/// ```
+///
+/// A device named by more than one entry is provisioned once. Its settings
+/// are the site settings, then those of every product entry, then those of
+/// the entries naming the device itself (by serial or address)
+pub struct Site {
+    pub reboot: bool,
+    pub settings: Map<String, Value>,
+    pub devices: Vec<Target>,
+}
+
+/// Which devices an entry in the site file describes
+pub struct Target {
+    serial: Option<String>,
+    product: Option<String>,
+    address: Option<String>,
+    settings: Map<String, Value>,
+}
+
+/// What happened to one device
+struct Outline {
+    serial: String,
+    changes: Vec<Change>,
+    error: Option<LinqError>,
+}
+
+fn invalid<S: Into<String>>(e: S) -> LinqError {
+    LinqError::InvalidArgument(e.into())
+}
+
+/// Site files are YAML (so JSON works too)
+fn to_json(yaml: Yaml) -> Result<Value> {
+    match yaml {
+        Yaml::Null => Ok(Value::Null),
+        Yaml::Boolean(b) => Ok(Value::Bool(b)),
+        Yaml::Integer(i) => Ok(Value::from(i)),
+        Yaml::String(s) => Ok(Value::String(s)),
+        Yaml::Real(s) => s
+            .parse::<f64>()
+            .ok()
+            .and_then(Number::from_f64)
+            .map(Value::Number)
+            .ok_or_else(|| invalid(format!("invalid number [{}]", s))),
+        Yaml::Array(x) => x
+            .into_iter()
+            .map(to_json)
+            .collect::<Result<Vec<Value>>>()
+            .map(Value::Array),
+        Yaml::Hash(x) => x
+            .into_iter()
+            .map(|(k, v)| match k {
+                Yaml::String(k) => Ok((k, to_json(v)?)),
+                Yaml::Integer(k) => Ok((k.to_string(), to_json(v)?)),
+                _ => Err(invalid("keys must be strings")),
+            })
+            .collect::<Result<Map<String, Value>>>()
+            .map(Value::Object),
+        _ => Err(invalid("unsupported yaml")),
+    }
+}
+
+/// Settings are keyed by the resources atx knows how to restore
+fn settings(value: Option<&Value>) -> Result<Map<String, Value>> {
+    let settings = match value {
+        Some(Value::Object(x)) => x.clone(),
+        Some(Value::Null) | None => Map::new(),
+        Some(_) => return Err(invalid("settings must be a map")),
+    };
+    match settings
+        .keys()
+        .find(|k| !RESOURCES.iter().any(|(path, _)| path == k))
+    {
+        Some(k) => Err(invalid(format!("unknown resource [{}]", k))),
+        None => Ok(settings),
+    }
+}
+
+/// Copy [from] onto [into]. Maps are merged so a device only has to say what
+/// differs from the site
+fn merge(into: &mut Value, from: &Value) {
+    match (into, from) {
+        (Value::Object(into), Value::Object(from)) => {
+            for (k, v) in from {
+                match into.get_mut(k) {
+                    Some(x) => merge(x, v),
+                    None => {
+                        into.insert(k.clone(), v.clone());
+                    }
+                }
+            }
+        }
+        (into, from) => *into = from.clone(),
+    }
+}
+
+pub fn parse(text: &str) -> Result<Site> {
+    let yaml = YamlLoader::load_from_str(text)
+        .map_err(|e| LinqError::Parser(e.to_string()))?
+        .into_iter()
+        .next()
+        .ok_or_else(|| invalid("site file is empty"))?;
+    let site = to_json(yaml)?;
+    let text =
+        |x: &Value, k| x.get(k).and_then(Value::as_str).map(String::from);
+    let devices = match site.get("devices") {
+        Some(Value::Array(x)) => x
+            .iter()
+            .map(|x| {
+                let target = Target {
+                    serial: text(x, "serial"),
+                    product: text(x, "product"),
+                    address: text(x, "address"),
+                    settings: settings(x.get("settings"))?,
+                };
+                match target {
+                    Target {
+                        serial: None,
+                        product: None,
+                        address: None,
+                        ..
+                    } => {
+                        Err(invalid("device needs serial, product or address"))
+                    }
+                    target => Ok(target),
+                }
+            })
+            .collect::<Result<Vec<Target>>>()?,
+        _ => return Err(invalid("site file has no devices")),
+    };
+    Ok(Site {
+        reboot: site.get("reboot").and_then(Value::as_bool).unwrap_or(false),
+        settings: settings(site.get("settings"))?,
+        devices,
+    })
+}
+
+/// The serial numbers a target describes
+fn resolve(
+    linq: &mut Io,
+    usb: &[UsbMetadata],
+    target: &Target,
+) -> Result<Vec<String>> {
+    if let Some(address) = &target.address {
+        return Ok(vec![connect_url(linq, address)?]);
+    }
+    let found: Vec<String> = usb
+        .iter()
+        .filter(|x| match &target.serial {
+            Some(s) => &x.serial == s,
+            None => true,
+        })
+        .filter(|x| match (&target.product, &x.about) {
+            (Some(p), Some(a)) => a.product.eq_ignore_ascii_case(p),
+            (Some(_), None) => false,
+            (None, _) => true,
+        })
+        .map(|x| x.serial.clone())
+        .collect();
+    match (found.len(), &target.serial) {
+        (0, Some(s)) => Err(IoError::DeviceNotFound(s.to_owned()).into()),
+        (0, None) => Err(LinqError::NoDevice),
+        _ => Ok(found),
+    }
+}
+
+/// Product entries are merged before the entries naming one device
+fn specific(target: &Target) -> bool {
+    target.serial.is_some() || target.address.is_some()
+}
+
+/// The settings of every device the [resolved] targets describe (in the order
+/// the devices are first named). Each target is paired with its serials
+pub fn plan(
+    site: &Map<String, Value>,
+    resolved: &[(&Target, Vec<String>)],
+) -> Vec<(String, Map<String, Value>)> {
+    let mut serials: Vec<&String> = vec![];
+    for serial in resolved.iter().flat_map(|(_, x)| x) {
+        if !serials.contains(&serial) {
+            serials.push(serial);
+        }
+    }
+    serials
+        .into_iter()
+        .map(|serial| {
+            let mut targets: Vec<&Target> = resolved
+                .iter()
+                .filter(|(_, x)| x.contains(serial))
+                .map(|(target, _)| *target)
+                .collect();
+            targets.sort_by_key(|x| specific(x));
+            let mut settings = Value::Object(site.clone());
+            for target in targets {
+                merge(&mut settings, &Value::Object(target.settings.clone()));
+            }
+            match settings {
+                Value::Object(x) => (serial.clone(), x),
+                _ => (serial.clone(), Map::new()),
+            }
+        })
+        .collect()
+}
+
+/// Write the changes [settings] needs, adding each to [applied] once it is
+/// written. Nothing is saved (or rebooted) unless something changed
+async fn write_all(
+    linq: &Io,
+    cli: &ArgMatches<'_>,
+    serial: &str,
+    settings: &Map<String, Value>,
+    reboot: bool,
+    applied: &mut Vec<Change>,
+) -> Result<()> {
+    let current = read_all(linq, serial).await?;
+    let changes = diff(settings, &current);
+    if cli.is_present("dry-run") || changes.is_empty() {
+        applied.extend(changes);
+        return Ok(());
+    }
+    for change in changes {
+        write(linq, serial, &change).await?;
+        applied.push(change);
+    }
+    let save = Request::post_raw("/ATX/exe/save", "{\"save\":1}");
+    linq.request(serial, save).await?;
+    if reboot {
+        let r = Request::post_raw("/ATX/exe/reboot", "{\"reboot\":1}");
+        linq.request(serial, r).await?;
+    }
+    Ok(())
+}
+
+/// Bring one device to the desired state. A device that fails part way
+/// reports the changes written before the failure
+async fn provision(
+    linq: &Io,
+    cli: &ArgMatches<'_>,
+    serial: &str,
+    settings: &Map<String, Value>,
+    reboot: bool,
+) -> Outline {
+    let mut changes = vec![];
+    let result =
+        write_all(linq, cli, serial, settings, reboot, &mut changes).await;
+    Outline {
+        serial: serial.to_owned(),
+        changes,
+        error: result.err(),
+    }
+}
+
+/// Provision every device in the site. A device that fails does not stop the
+/// others
+async fn apply(
+    linq: &mut Io,
+    cli: &ArgMatches<'_>,
+    site: Site,
+) -> Result<Vec<Outline>> {
+    let reboot = site.reboot || cli.is_present("reboot");
+    let usb = match site.devices.iter().any(|x| x.address.is_none()) {
+        true => linq.scan().await?,
+        false => vec![],
+    };
+    let mut outlines: Vec<Outline> = vec![];
+    let mut resolved = vec![];
+    for target in site.devices.iter() {
+        match resolve(linq, &usb, target) {
+            Ok(serials) => resolved.push((target, serials)),
+            Err(error) => outlines.push(Outline {
+                serial: target
+                    .serial
+                    .as_ref()
+                    .or(target.product.as_ref())
+                    .or(target.address.as_ref())
+                    .cloned()
+                    .unwrap_or_default(),
+                changes: vec![],
+                error: Some(error),
+            }),
+        }
+    }
+    for (serial, settings) in plan(&site.settings, &resolved) {
+        outlines.push(provision(linq, cli, &serial, &settings, reboot).await);
+    }
+    Ok(outlines)
+}
+
+/// One line per device followed by its changes, or a JSON array of
+/// {serial, changes, error}
+fn summarize(outlines: &[Outline], cli: &ArgMatches) -> String {
+    let dry_run = cli.is_present("dry-run");
+    match cli.value_of("output") {
+        Some("json") => Value::from(
+            outlines
+                .iter()
+                .map(|x| {
+                    let changes: Vec<Value> = x
+                        .changes
+                        .iter()
+                        .map(|x| json!({"path": x.path, "from": x.from, "to": x.to}))
+                        .collect();
+                    json!({
+                        "serial": x.serial,
+                        "changes": changes,
+                        "error": x.error.as_ref().map(|e| e.to_string()),
+                    })
+                })
+                .collect::<Vec<Value>>(),
+        )
+        .to_string(),
+        _ => outlines
+            .iter()
+            .flat_map(|x| {
+                let status = match (&x.error, x.changes.len()) {
+                    (Some(e), 0) => format!("failed => {}", e),
+                    (Some(e), n) => {
+                        format!("failed after {} changes applied => {}", n, e)
+                    }
+                    (None, 0) => "up to date".to_owned(),
+                    (None, n) if dry_run => format!("{} changes planned", n),
+                    (None, n) => format!("{} changes applied", n),
+                };
+                let changes = describe(&x.changes, cli);
+                std::iter::once(format!("{}: {}", x.serial, status))
+                    .chain(changes.lines().map(|c| format!("  {}", c)))
+                    .collect::<Vec<String>>()
+            })
+            .collect::<Vec<String>>()
+            .join("\n"),
+    }
+}
+
+pub fn process_apply(cli: &ArgMatches) -> Outcome {
+    let file = cli.value_of("file").unwrap();
+    let text = std::fs::read_to_string(file)?;
+    let site = parse(&text)?;
+    let mut linq = open(cli)?;
+    let result = block_on(apply(&mut linq, cli, site));
+    linq.close()?;
+    let mut outlines = result?;
+    let body = summarize(&outlines, cli);
+    match outlines.iter_mut().find_map(|x| x.error.take()) {
+        Some(error) => Err(Report::new(body).fail(error)),
+        None => Ok(Report::new(body)),
+    }
+}
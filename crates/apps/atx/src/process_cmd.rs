@@ -1,36 +1,73 @@
+use crate::connect::{connect, open};
+use crate::output::{Outcome, Report};
 use clap::ArgMatches;
 use futures::executor::block_on;
-use futures::prelude::*;
 use linq::error::*;
 use linq::io::Io;
 use linq::Request;
+use std::io::Read;
 
-async fn process(request: Request) -> Result<String> {
-    let mut linq = Io::new();
-    let metadata = linq.scan().await?;
-    let result = linq.request(&metadata[0].serial, request).await;
-    linq.close().unwrap();
-    result.map_err(|x| LinqError::from(x))
-    // result.into()
+async fn process(
+    linq: &mut Io,
+    cli: &ArgMatches<'_>,
+    request: Request,
+) -> Outcome {
+    let report = Report::default().path(&request.path);
+    let serial = match connect(linq, cli).await {
+        Ok(serial) => serial,
+        Err(e) => return Err(report.fail(e)),
+    };
+    let report = report.serial(&serial);
+    match linq.request(&serial, request).await {
+        Ok(body) => Ok(report.body(body)),
+        Err(e) => Err(report.fail(e)),
+    }
+}
+
+/// Read the body of the request from -d, -d @- (stdin) or --file. The body
+/// must be JSON
+fn body(cli: &ArgMatches) -> Result<Option<String>> {
+    let body = match (cli.value_of("data"), cli.value_of("file")) {
+        (Some("@-"), _) => {
+            let mut data = String::new();
+            std::io::stdin().read_to_string(&mut data)?;
+            Some(data)
+        }
+        (Some(data), _) => Some(data.to_owned()),
+        (None, Some(file)) => Some(std::fs::read_to_string(file)?),
+        (None, None) => None,
+    };
+    if let Some(data) = &body {
+        serde_json::from_str::<serde_json::Value>(data)
+            .map_err(|e| LinqError::Parser(format!("invalid body => {}", e)))?;
+    }
+    Ok(body)
 }
 
-pub fn process_cmd(cli: &ArgMatches) -> Result<String> {
+/// The request the command line describes. (POST and PUT require a body)
+pub fn request(cli: &ArgMatches) -> Result<Request> {
     let path = cli.value_of("path").unwrap();
     let method = cli.value_of("method").unwrap();
-    let protocol = cli.value_of("protocol").unwrap();
-    // let data = m
-    // .value_of("file")
-    // .map_or(cli.value_of("data"), |f| f.unwrap());
-
-    let request = match method {
-        // "POST" => Request::Post(path.into(), data.into()),
-        "DELETE" => linq::Request::Delete(path.into()),
-        _ => linq::Request::Get(path.into()),
-    };
-    let proto = cli.value_of("protocol").unwrap();
-    if !(proto == "usb") {
-        panic!("protocol {} not supported", proto);
-    };
+    match (method, body(cli)?) {
+        ("POST", Some(data)) => Ok(Request::post_raw(path, data)),
+        ("PUT", Some(data)) => Ok(Request::put_raw(path, data)),
+        ("POST", None) | ("PUT", None) => {
+            let e = format!("{} requires a body (--data or --file)", method);
+            Err(LinqError::InvalidArgument(e))
+        }
+        (_, Some(_)) => {
+            let e = format!("{} does not take a body", method);
+            Err(LinqError::InvalidArgument(e))
+        }
+        ("DELETE", None) => Ok(Request::delete(path)),
+        (_, None) => Ok(Request::get(path)),
+    }
+}
 
-    block_on(process(request))
+pub fn process_cmd(cli: &ArgMatches) -> Outcome {
+    let request = request(cli)?;
+    let mut linq = open(cli)?;
+    let result = block_on(process(&mut linq, cli, request));
+    linq.close()?;
+    result
 }
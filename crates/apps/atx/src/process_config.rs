@@ -0,0 +1,211 @@
+use crate::connect::{connect, open};
+use crate::output::{Outcome, Report};
+use clap::ArgMatches;
+use futures::executor::block_on;
+use linq::error::*;
+use linq::io::Io;
+use linq::Request;
+use serde_json::{json, Map, Value};
+use std::io::Read;
+
+/// The resources that make up the configuration of a device, in the order
+/// they are restored. [keys] limits a resource to the fields that can be
+/// written. (IE: the serial number in about can not be changed)
+pub const RESOURCES: &[(&str, Option<&[&str]>)] =
+    &[("/ATX/about", Some(&["siteId"])), ("/ATX/network", None)];
+
+/// A setting that differs between a snapshot and a device
+pub struct Change {
+    pub path: String,
+    pub from: Option<Value>,
+    pub to: Value,
+}
+
+/// Read one resource. Resources the product does not have are skipped
+async fn read(linq: &Io, serial: &str, path: &str) -> Result<Option<Value>> {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    match linq.request(serial, Request::get(path)).await {
+        Ok(response) => serde_json::from_str::<Value>(&response)
+            .map(|mut x| x.get_mut(name).map(Value::take))
+            .map_err(|e| LinqError::Parser(e.to_string())),
+        Err(IoError::ApiError(e)) if e.kind == ApiErrorKind::Linq404 => {
+            Ok(None)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Read every known resource into a map of path to value
+pub async fn read_all(linq: &Io, serial: &str) -> Result<Map<String, Value>> {
+    let mut config = Map::new();
+    for (path, keys) in RESOURCES {
+        let value = match (read(linq, serial, path).await?, keys) {
+            (Some(Value::Object(map)), Some(keys)) => map
+                .into_iter()
+                .filter(|(k, _)| keys.contains(&k.as_str()))
+                .collect(),
+            (Some(value), _) => value,
+            (None, _) => continue,
+        };
+        config.insert(path.to_string(), value);
+    }
+    Ok(config)
+}
+
+/// Flatten a resource into its settings IE:
+/// ("/ATX/network", {"ipConfig":{"ip":..}}) => [("/ATX/network/ipConfig/ip", ..)]
+pub fn leaves(path: &str, value: &Value, out: &mut Vec<(String, Value)>) {
+    match value {
+        Value::Object(map) => map
+            .iter()
+            .for_each(|(k, v)| leaves(&format!("{}/{}", path, k), v, out)),
+        value => out.push((path.to_owned(), value.clone())),
+    }
+}
+
+/// Every setting in [snapshot] that the device does not already have
+pub fn diff(
+    snapshot: &Map<String, Value>,
+    current: &Map<String, Value>,
+) -> Vec<Change> {
+    let mut now = vec![];
+    current
+        .iter()
+        .for_each(|(path, x)| leaves(path, x, &mut now));
+    RESOURCES
+        .iter()
+        .filter_map(|(path, _)| snapshot.get(*path).map(|x| (path, x)))
+        .flat_map(|(path, x)| {
+            let mut want = vec![];
+            leaves(path, x, &mut want);
+            want
+        })
+        .filter_map(|(path, to)| {
+            let from = now.iter().find(|x| x.0 == path).map(|x| x.1.clone());
+            match from.as_ref() == Some(&to) {
+                true => None,
+                false => Some(Change { path, from, to }),
+            }
+        })
+        .collect()
+}
+
+/// Write a single setting. IE: POST /ATX/network/ipConfig/ip {"ip":".."}
+pub async fn write(linq: &Io, serial: &str, change: &Change) -> Result<()> {
+    let name = change.path.rsplit('/').next().unwrap_or("");
+    let body = json!({ name: change.to }).to_string();
+    linq.request(serial, Request::post_raw(&change.path, body))
+        .await?;
+    Ok(())
+}
+
+async fn dump(linq: &mut Io, cli: &ArgMatches<'_>) -> Outcome {
+    let serial = connect(linq, cli).await?;
+    let report = Report::default().serial(&serial);
+    let config = match read_all(linq, &serial).await {
+        Ok(config) => config,
+        Err(e) => return Err(report.fail(e)),
+    };
+    let about = linq.about(&serial)?.unwrap_or_default();
+    let snapshot = json!({
+        "serial": serial,
+        "product": about.product,
+        "prjVersion": about.prjVersion,
+        "atxVersion": about.atxVersion,
+        "config": config,
+    });
+    let text = serde_json::to_string_pretty(&snapshot)
+        .map_err(|e| LinqError::Parser(e.to_string()))?;
+    match cli.value_of("file") {
+        Some(file) => {
+            std::fs::write(file, text)?;
+            Ok(report.body(format!("Saved {}", file)))
+        }
+        None => Ok(report.body(text)),
+    }
+}
+
+async fn restore(
+    linq: &mut Io,
+    cli: &ArgMatches<'_>,
+    snapshot: Map<String, Value>,
+) -> Outcome {
+    let serial = connect(linq, cli).await?;
+    let report = Report::default().serial(&serial);
+    let current = match read_all(linq, &serial).await {
+        Ok(current) => current,
+        Err(e) => return Err(report.fail(e)),
+    };
+    let changes = diff(&snapshot, &current);
+    if cli.is_present("dry-run") {
+        return Ok(report.body(describe(&changes, cli)));
+    }
+    for change in changes.iter() {
+        if let Err(e) = write(linq, &serial, change).await {
+            return Err(report.path(&change.path).fail(e));
+        }
+    }
+    let save = Request::post_raw("/ATX/exe/save", "{\"save\":1}");
+    if let Err(e) = linq.request(&serial, save).await {
+        return Err(report.path("/ATX/exe/save").fail(e));
+    }
+    Ok(report.body(format!("Restored {} settings", changes.len())))
+}
+
+/// Print the changes a restore would make
+pub fn describe(changes: &[Change], cli: &ArgMatches) -> String {
+    match cli.value_of("output") {
+        Some("json") => Value::from(
+            changes
+                .iter()
+                .map(|x| json!({"path": x.path, "from": x.from, "to": x.to}))
+                .collect::<Vec<Value>>(),
+        )
+        .to_string(),
+        _ => changes
+            .iter()
+            .map(|x| match &x.from {
+                Some(from) => format!("~ {} {} -> {}", x.path, from, x.to),
+                None => format!("+ {} {}", x.path, x.to),
+            })
+            .collect::<Vec<String>>()
+            .join("\n"),
+    }
+}
+
+/// Read a snapshot from a file. ("-" reads from stdin)
+fn snapshot(file: &str) -> Result<Map<String, Value>> {
+    let text = match file {
+        "-" => {
+            let mut text = String::new();
+            std::io::stdin().read_to_string(&mut text)?;
+            text
+        }
+        file => std::fs::read_to_string(file)?,
+    };
+    let parsed = serde_json::from_str::<Value>(&text)
+        .map_err(|e| LinqError::Parser(e.to_string()))?;
+    match parsed {
+        Value::Object(mut x) => match x.remove("config") {
+            Some(Value::Object(config)) => Ok(config),
+            _ => Err(LinqError::Parser("snapshot has no config".into())),
+        },
+        _ => Err(LinqError::Parser("snapshot is not an object".into())),
+    }
+}
+
+pub fn process_config(cli: &ArgMatches) -> Outcome {
+    let mut linq = open(cli)?;
+    let result = match cli.subcommand() {
+        ("dump", Some(cli)) => block_on(dump(&mut linq, cli)),
+        ("restore", Some(cli)) => {
+            let file = cli.value_of("file").unwrap();
+            snapshot(file)
+                .map_err(Into::into)
+                .and_then(|x| block_on(restore(&mut linq, cli, x)))
+        }
+        _ => Err(LinqError::InvalidArgument("dump or restore".into()).into()),
+    };
+    linq.close()?;
+    result
+}
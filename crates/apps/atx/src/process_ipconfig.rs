@@ -1,47 +1,147 @@
+use crate::connect::{connect, open};
+use crate::output::{Format, Outcome, Report};
+use crate::process_config::leaves;
 use clap::ArgMatches;
 use futures::executor::block_on;
-use linq;
-use linq::io::Io;
 use linq::error::*;
+use linq::io::Io;
 use linq::Request;
+use serde_json::{json, Map, Value};
+use std::net::Ipv4Addr;
+
+const NETWORK: &str = "/ATX/network";
+const IPCONFIG: &str = "/ATX/network/ipConfig";
 
-async fn process(linq: &mut Io, request: Vec<Request>) -> Result<String> {
-    let metadata = linq.scan().await?;
-    if metadata.len() > 0 {
-        for r in request.into_iter() {
-            let _response = linq.request(&metadata[0].serial, r).await?;
+/// The settings on the command line IE: {"dhcp":false,"ip":"10.0.0.2"}
+fn settings(cli: &ArgMatches) -> Result<Map<String, Value>> {
+    let address = |s: &str| match s.parse::<Ipv4Addr>() {
+        Ok(_) => Ok(Value::from(s)),
+        Err(_) => {
+            let e = format!("invalid address [{}]", s);
+            Err(LinqError::InvalidArgument(e))
+        }
+    };
+    let mut map = Map::new();
+    if cli.is_present("dhcp") {
+        map.insert("dhcp".into(), Value::Bool(true));
+    }
+    if cli.is_present("static") {
+        map.insert("dhcp".into(), Value::Bool(false));
+    }
+    for key in &["ip", "sn", "gw"] {
+        if let Some(arg) = cli.value_of(key) {
+            map.insert(key.to_string(), address(arg)?);
         }
     }
-    Ok("Complete!".to_owned())
+    if let Some(args) = cli.values_of("dns") {
+        let dns = args.map(address).collect::<Result<Vec<Value>>>()?;
+        map.insert("dns".into(), Value::Array(dns));
+    }
+    if let Some(arg) = cli.value_of("hostname") {
+        map.insert("hostname".into(), Value::from(arg));
+    }
+    Ok(map)
 }
 
-pub fn process_ipconfig(cli: &ArgMatches) -> Result<String> {
-    let mut requests: Vec<Request> = vec![];
-    if let Some(arg) = cli.value_of("ip") {
-        let data = format!("{{\"ip\":\"{}\"}}", arg);
-        requests.push(Request::post_raw("/ATX/network/ipConfig/ip", data))
+/// GET /ATX/network
+async fn network(linq: &Io, serial: &str) -> Result<Value> {
+    let response = linq.request(serial, Request::get(NETWORK)).await?;
+    serde_json::from_str::<Value>(&response)
+        .map(|mut x| x["network"].take())
+        .map_err(|e| LinqError::Parser(e.to_string()))
+}
+
+/// Send every setting in one request. Devices that only accept a setting at
+/// a time (they do not have the route, 404 or 405) are sent one request per
+/// setting instead. A rejected setting (400) is an error
+async fn write(
+    linq: &Io,
+    serial: &str,
+    settings: &Map<String, Value>,
+) -> Result<()> {
+    let body = Value::Object(settings.clone()).to_string();
+    match linq
+        .request(serial, Request::post_raw(IPCONFIG, body))
+        .await
+    {
+        Ok(_) => Ok(()),
+        Err(IoError::ApiError(e))
+            if e.kind == ApiErrorKind::Linq404 || e.code == 405 =>
+        {
+            for (key, value) in settings {
+                let path = format!("{}/{}", IPCONFIG, key);
+                let body = json!({ key: value }).to_string();
+                linq.request(serial, Request::post_raw(&path, body)).await?;
+            }
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
     }
-    if let Some(arg) = cli.value_of("sn") {
-        let data = format!("{{\"sn\":\"{}\"}}", arg);
-        requests.push(Request::post_raw("/ATX/network/ipConfig/sn", data))
+}
+
+/// Each setting of /ATX/network before and after. (IE: ipConfig/ip a -> b)
+fn compare(before: &Value, after: &Value, format: Format) -> String {
+    if format == Format::Json {
+        return json!({"before": before, "after": after}).to_string();
     }
-    if let Some(arg) = cli.value_of("gw") {
-        let data = format!("{{\"gw\":\"{}\"}}", arg);
-        requests.push(Request::post_raw("/ATX/network/ipConfig/gw", data))
+    let (mut old, mut new) = (vec![], vec![]);
+    leaves("", before, &mut old);
+    leaves("", after, &mut new);
+    new.iter()
+        .map(|(path, to)| {
+            let path = path.trim_start_matches('/');
+            match old.iter().find(|x| x.0.trim_start_matches('/') == path) {
+                Some((_, from)) if from != to => {
+                    format!("{} {} -> {}", path, from, to)
+                }
+                Some(_) => format!("{} {}", path, to),
+                None => format!("{} -> {}", path, to),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+async fn process(
+    linq: &mut Io,
+    cli: &ArgMatches<'_>,
+    settings: Map<String, Value>,
+) -> Outcome {
+    let serial = connect(linq, cli).await?;
+    let report = Report::default().serial(&serial);
+    let before = match network(linq, &serial).await {
+        Ok(before) => before,
+        Err(e) => return Err(report.path(NETWORK).fail(e)),
+    };
+    if !settings.is_empty() {
+        if let Err(e) = write(linq, &serial, &settings).await {
+            return Err(report.path(IPCONFIG).fail(e));
+        }
+        if !cli.is_present("no-save") {
+            let r = Request::post_raw("/ATX/exe/save", "{\"save\":1}");
+            if let Err(e) = linq.request(&serial, r).await {
+                return Err(report.path("/ATX/exe/save").fail(e));
+            }
+        }
     }
-    requests.push(Request::post_raw("/ATX/exe/save", "{\"save\":1}"));
+    let after = match network(linq, &serial).await {
+        Ok(after) => after,
+        Err(e) => return Err(report.path(NETWORK).fail(e)),
+    };
     if cli.is_present("reboot") {
-        requests.push(Request::post_raw("/ATX/exe/reboot", "{\"reboot\":1}"));
+        let r = Request::post_raw("/ATX/exe/reboot", "{\"reboot\":1}");
+        if let Err(e) = linq.request(&serial, r).await {
+            return Err(report.path("/ATX/exe/reboot").fail(e));
+        }
     }
+    let format = Format::from_arg(cli.value_of("output"));
+    Ok(report.body(compare(&before, &after, format)))
+}
 
-    // Currently only USB supported at the moment
-    let proto = cli.value_of("protocol").unwrap();
-    if !(proto == "usb") {
-        panic!("protocol {} not supported", proto);
-    };
-
-    let mut linq = Io::new();
-    let result = block_on(process(&mut linq, requests));
-    linq.close().unwrap();
+pub fn process_ipconfig(cli: &ArgMatches) -> Outcome {
+    let settings = settings(cli)?;
+    let mut linq = open(cli)?;
+    let result = block_on(process(&mut linq, cli, settings));
+    linq.close()?;
     result
 }
@@ -0,0 +1,162 @@
+use crate::connect::{connect_url, open};
+use crate::output::{Outcome, Report};
+use clap::ArgMatches;
+use futures::executor::block_on;
+use linq::error::*;
+use linq::io::Io;
+use linq::{DeviceMetadata, Location};
+use log::warn;
+use std::time::Duration;
+
+/// How the device list is printed
+#[derive(Copy, Clone)]
+enum Format {
+    Table,
+    Json,
+    Ndjson,
+}
+
+/// Every device we can reach. Usb devices describe themselves when scanned
+/// while network devices are asked for /ATX/about
+async fn list(
+    linq: &mut Io,
+    network: &[String],
+) -> Result<Vec<DeviceMetadata>> {
+    linq.scan().await?;
+    refresh(linq, network).await
+}
+
+/// Ask the network devices for /ATX/about again. (Usb devices are described
+/// as they were last scanned)
+async fn refresh(
+    linq: &mut Io,
+    network: &[String],
+) -> Result<Vec<DeviceMetadata>> {
+    for serial in network {
+        if let Err(e) = linq.refresh(serial).await {
+            warn!("[{}] failed to read about => {}", serial, e);
+        }
+    }
+    Ok(linq.meta())
+}
+
+fn table(devices: &[DeviceMetadata]) -> String {
+    let header = ["SERIAL", "PRODUCT", "VID:PID", "PRJ", "ATX", "TRANSPORT"];
+    let rows: Vec<[String; 6]> = devices
+        .iter()
+        .map(|x| {
+            let ids = match &x.location {
+                Location::Usb { vid, pid, .. } => {
+                    format!("{:04x}:{:04x}", vid, pid)
+                }
+                _ => "-".to_owned(),
+            };
+            let text = |s: &Option<String>| s.clone().unwrap_or("-".into());
+            [
+                x.serial.clone(),
+                text(&x.product),
+                ids,
+                text(&x.prj_version),
+                text(&x.atx_version),
+                x.transport().to_owned(),
+            ]
+        })
+        .collect();
+    let widths: Vec<usize> = (0..header.len())
+        .map(|i| {
+            rows.iter()
+                .map(|r| r[i].len())
+                .chain(std::iter::once(header[i].len()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let line = |cells: Vec<&str>| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(c, w)| format!("{:<w$}", c, w = w))
+            .collect::<Vec<String>>()
+            .join("  ")
+            .trim_end()
+            .to_owned()
+    };
+    std::iter::once(line(header.to_vec()))
+        .chain(
+            rows.iter()
+                .map(|r| line(r.iter().map(|x| &x[..]).collect())),
+        )
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn print(devices: &[DeviceMetadata], format: Format) -> Result<String> {
+    let json = serde_json::to_value(devices)
+        .map_err(|e| LinqError::Parser(e.to_string()))?;
+    match (format, json) {
+        (Format::Table, _) => Ok(table(devices)),
+        (Format::Ndjson, serde_json::Value::Array(v)) => Ok(v
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<String>>()
+            .join("\n")),
+        (_, json) => Ok(json.to_string()),
+    }
+}
+
+/// Reprint the device list whenever it changes. The usb binding does not
+/// report hotplug events so the bus is polled. Polling only lists the bus,
+/// usb devices are opened again (and asked for /ATX/about) when the set of
+/// devices on the bus changes. Network devices are asked every [interval]
+async fn watch(
+    linq: &mut Io,
+    network: &[String],
+    format: Format,
+    interval: Duration,
+) -> Result<String> {
+    let mut bus = None;
+    let mut last: Option<Vec<DeviceMetadata>> = None;
+    loop {
+        let summary = Some(linq.summary().await?);
+        let devices = match bus == summary {
+            true => refresh(linq, network).await?,
+            false => list(linq, network).await?,
+        };
+        bus = summary;
+        if last.as_ref() != Some(&devices) {
+            println!("{}", print(&devices, format)?);
+            last = Some(devices);
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+pub fn process_list(cli: &ArgMatches) -> Outcome {
+    let json = cli.is_present("json") || cli.value_of("output") == Some("json");
+    let format = match (json, cli.is_present("ndjson")) {
+        (_, true) => Format::Ndjson,
+        (true, _) => Format::Json,
+        _ => Format::Table,
+    };
+    let interval = cli
+        .value_of("interval")
+        .unwrap_or("1")
+        .parse::<u64>()
+        .map(Duration::from_secs)
+        .map_err(|e| LinqError::InvalidArgument(e.to_string()))?;
+
+    let mut linq = open(cli)?;
+    let result = cli
+        .values_of("address")
+        .into_iter()
+        .flatten()
+        .map(|x| connect_url(&mut linq, x))
+        .collect::<Result<Vec<String>>>()
+        .and_then(|network| match cli.is_present("watch") {
+            true => block_on(watch(&mut linq, &network, format, interval)),
+            false => block_on(list(&mut linq, &network))
+                .and_then(|x| print(&x, format)),
+        });
+    linq.close()?;
+    Ok(Report::new(result?))
+}
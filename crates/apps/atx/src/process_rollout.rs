@@ -0,0 +1,354 @@
+use crate::connect::{connect_url, open};
+use crate::output::{Format, Outcome, Report};
+use clap::ArgMatches;
+use futures::executor::block_on;
+use futures::prelude::*;
+use linq::error::*;
+use linq::io::Io;
+use linq::{About, Location};
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+/// How far a device got
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Status {
+    Pending,
+    Updated,
+    Failed,
+    Skipped,
+}
+
+impl Status {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Status::Pending => "pending",
+            Status::Updated => "updated",
+            Status::Failed => "failed",
+            Status::Skipped => "skipped",
+        }
+    }
+}
+
+/// A device taking part in the rollout
+struct Device {
+    serial: String,
+    usb: bool,
+    old: Option<String>,
+    new: Option<String>,
+    status: Status,
+    error: Option<LinqError>,
+}
+
+impl Device {
+    fn fail(&mut self, error: LinqError) {
+        eprintln!("[{}] failed => {}", self.serial, error);
+        self.status = Status::Failed;
+        self.error = Some(error);
+    }
+}
+
+/// Rollout settings from the command line
+struct Plan<'a> {
+    file: &'a str,
+    image: u8,
+    canary: usize,
+    wave: usize,
+    max_failures: usize,
+    timeout: Duration,
+    expect: Option<&'a str>,
+}
+
+impl Plan<'_> {
+    /// The version at /ATX/about that [image] replaces: prjVersion for the
+    /// firmware and atxVersion for the website
+    fn version(&self, about: About) -> String {
+        match self.image {
+            0 => about.prjVersion,
+            _ => about.atxVersion,
+        }
+    }
+}
+
+fn number(cli: &ArgMatches, name: &str) -> Result<usize> {
+    let arg = cli.value_of(name).unwrap_or("0");
+    arg.parse::<usize>().map_err(|_| {
+        let e = format!("--{} expects a number [{}]", name, arg);
+        LinqError::InvalidArgument(e)
+    })
+}
+
+/// Every usb device (with --product and --serial), and every --address
+async fn devices(
+    linq: &mut Io,
+    cli: &ArgMatches<'_>,
+    plan: &Plan<'_>,
+) -> Result<Vec<Device>> {
+    let serials: Vec<&str> =
+        cli.values_of("serial").into_iter().flatten().collect();
+    let product = cli.value_of("product");
+    let mut found: Vec<String> = linq
+        .scan()
+        .await?
+        .into_iter()
+        .filter(|x| serials.is_empty() || serials.contains(&x.serial.as_str()))
+        .filter(|x| match (product, &x.about) {
+            (Some(p), Some(a)) => a.product.eq_ignore_ascii_case(p),
+            (Some(_), None) => false,
+            (None, _) => true,
+        })
+        .map(|x| x.serial)
+        .collect();
+    if let Some(serial) =
+        serials.iter().find(|x| !found.iter().any(|s| s == *x))
+    {
+        return Err(IoError::DeviceNotFound(serial.to_string()).into());
+    }
+    for address in cli.values_of("address").into_iter().flatten() {
+        found.push(connect_url(linq, address)?);
+    }
+    let mut devices = vec![];
+    for serial in found {
+        let usb = match linq.meta().into_iter().find(|x| x.serial == serial) {
+            Some(meta) => matches!(meta.location, Location::Usb { .. }),
+            None => false,
+        };
+        let old = match linq.refresh(&serial).await {
+            Ok(about) => Some(plan.version(about)),
+            Err(_) => None,
+        };
+        devices.push(Device {
+            serial,
+            usb,
+            old,
+            new: None,
+            status: Status::Pending,
+            error: None,
+        });
+    }
+    match devices.is_empty() {
+        true => Err(LinqError::NoDevice),
+        false => Ok(devices),
+    }
+}
+
+/// Send the update to one device
+async fn flash(linq: &Io, serial: &str, plan: &Plan<'_>) -> Result<()> {
+    eprintln!("[{}] updating", serial);
+    linq.update_file_path(serial, plan.file, plan.image)?
+        .try_for_each(|_| future::ready(Ok(())))
+        .await?;
+    Ok(())
+}
+
+/// Wait for the device to report the new version of the image at
+/// /ATX/about: --expect when given, else any version but the one it had
+/// before the update. (usb devices are looked for again when the bus changes
+/// as they leave it when they restart). Returns the new version
+async fn verify(
+    linq: &mut Io,
+    device: &Device,
+    plan: &Plan<'_>,
+) -> Result<String> {
+    let start = Instant::now();
+    let updated = |version: &str| match plan.expect {
+        Some(v) => version == v,
+        None => device.old.as_deref() != Some(version),
+    };
+    let mut bus = None;
+    loop {
+        let error = match linq.refresh(&device.serial).await {
+            Ok(about) => {
+                let version = plan.version(about);
+                if updated(&version) {
+                    eprintln!("[{}] verified {}", device.serial, version);
+                    return Ok(version);
+                }
+                LinqError::Verify(match plan.expect {
+                    Some(v) => {
+                        format!("expected version {} found {}", v, version)
+                    }
+                    None => format!("version is still {}", version),
+                })
+            }
+            Err(e) => e.into(),
+        };
+        if start.elapsed() > plan.timeout {
+            return Err(error);
+        }
+        std::thread::sleep(Duration::from_secs(1));
+        if device.usb {
+            let summary = linq.summary().await.ok();
+            if bus != summary {
+                let _ = linq.scan().await;
+                bus = summary;
+            }
+        }
+    }
+}
+
+/// Update every device in [wave] at the same time, then verify each one
+async fn wave(linq: &mut Io, wave: &mut [Device], plan: &Plan<'_>) {
+    let results = {
+        let linq: &Io = linq;
+        let updates = wave.iter().map(|x| flash(linq, &x.serial, plan));
+        future::join_all(updates).await
+    };
+    for (device, result) in wave.iter_mut().zip(results) {
+        if let Err(e) = result {
+            device.fail(e);
+            continue;
+        }
+        match verify(linq, device, plan).await {
+            Ok(version) => {
+                device.new = Some(version);
+                device.status = Status::Updated;
+            }
+            Err(e) => device.fail(e),
+        }
+    }
+}
+
+/// Update the canaries one at a time, then the rest in waves. Stops when a
+/// canary fails or when more than [max_failures] devices have failed
+async fn rollout(linq: &mut Io, devices: &mut [Device], plan: &Plan<'_>) {
+    let canary = plan.canary.min(devices.len());
+    for i in 0..canary {
+        wave(linq, &mut devices[i..i + 1], plan).await;
+        if devices[i].status == Status::Failed {
+            eprintln!("canary {} failed, stopping", devices[i].serial);
+            return;
+        }
+    }
+    let mut start = canary;
+    while start < devices.len() {
+        let end = (start + plan.wave.max(1)).min(devices.len());
+        wave(linq, &mut devices[start..end], plan).await;
+        let failed = devices
+            .iter()
+            .filter(|x| x.status == Status::Failed)
+            .count();
+        if failed > plan.max_failures {
+            eprintln!("{} devices failed, stopping", failed);
+            return;
+        }
+        start = end;
+    }
+}
+
+/// A row per device with its old and new versions, or a JSON array of
+/// {serial, status, old, new, error}
+fn summarize(devices: &[Device], format: Format) -> String {
+    let rows: Vec<Value> = devices
+        .iter()
+        .map(|x| {
+            json!({
+                "serial": x.serial,
+                "status": x.status.as_str(),
+                "old": x.old,
+                "new": x.new,
+                "error": x.error.as_ref().map(|e| e.to_string()),
+            })
+        })
+        .collect();
+    if format == Format::Json {
+        return Value::Array(rows).to_string();
+    }
+    let width = devices.iter().map(|x| x.serial.len()).max().unwrap_or(0);
+    devices
+        .iter()
+        .map(|x| {
+            let version = |v: &Option<String>| v.clone().unwrap_or("-".into());
+            let line = format!(
+                "{:<w$}  {:<8}  {} -> {}",
+                x.serial,
+                x.status.as_str(),
+                version(&x.old),
+                version(&x.new),
+                w = width
+            );
+            match &x.error {
+                Some(e) => format!("{}  {}", line, e),
+                None => line,
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+async fn process(
+    linq: &mut Io,
+    cli: &ArgMatches<'_>,
+    plan: Plan<'_>,
+) -> Outcome {
+    let mut devices = devices(linq, cli, &plan).await?;
+    // Only usb drivers find out what a device can do when it is opened. Network
+    // devices are tried
+    for device in devices.iter_mut().filter(|x| x.usb) {
+        let capabilities = linq.capabilities(&device.serial)?;
+        let supported = match plan.image {
+            0 => capabilities.update,
+            _ => capabilities.update && capabilities.website,
+        };
+        if !supported {
+            device.status = Status::Skipped;
+            device.error =
+                Some(LinqError::InvalidArgument("update not supported".into()));
+        }
+    }
+    // Without --expect a device is updated once its version changes, which
+    // can't be told when the version before the update is unknown
+    if plan.expect.is_none() {
+        for device in devices
+            .iter_mut()
+            .filter(|x| x.status == Status::Pending && x.old.is_none())
+        {
+            device.fail(LinqError::InvalidArgument(
+                "version before the update is unknown, use --expect".into(),
+            ));
+        }
+    }
+    let (mut ready, skipped): (Vec<Device>, Vec<Device>) = devices
+        .into_iter()
+        .partition(|x| x.status == Status::Pending);
+    rollout(linq, &mut ready, &plan).await;
+    ready.iter_mut().for_each(|x| {
+        if x.status == Status::Pending {
+            x.status = Status::Skipped;
+        }
+    });
+    ready.extend(skipped);
+
+    let format = Format::from_arg(cli.value_of("output"));
+    let body = summarize(&ready, format);
+    let report = Report::new(body);
+    if let Some(file) = cli.value_of("summary") {
+        std::fs::write(file, summarize(&ready, Format::Json))?;
+    }
+    match ready
+        .iter_mut()
+        .filter(|x| x.status == Status::Failed)
+        .find_map(|x| x.error.take())
+    {
+        Some(error) => Err(report.fail(error)),
+        None => Ok(report),
+    }
+}
+
+pub fn process_rollout(cli: &ArgMatches) -> Outcome {
+    let plan = Plan {
+        file: cli.value_of("file").unwrap(),
+        image: match cli.value_of("image") {
+            Some("website") => 1,
+            _ => 0,
+        },
+        canary: number(cli, "canary")?,
+        wave: number(cli, "wave")?,
+        max_failures: number(cli, "max-failures")?,
+        timeout: Duration::from_secs(number(cli, "timeout")? as u64),
+        expect: cli.value_of("expect"),
+    };
+    let mut linq = open(cli)?;
+    let result = block_on(process(&mut linq, cli, plan));
+    linq.close()?;
+    result
+}
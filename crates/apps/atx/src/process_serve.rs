@@ -0,0 +1,384 @@
+use crate::connect::Security;
+use crate::output::{Outcome, Report};
+use clap::ArgMatches;
+use futures::executor::block_on;
+use futures::prelude::*;
+use linq::error::*;
+use linq::http::{self, Response};
+use linq::io::Io;
+use linq::{DashboardUpdatePackets, DeviceMetadata, Request};
+use log::{info, warn};
+use serde_json::json;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// How long a client may take to send its whole request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Largest request body accepted on every route but the update. (The update
+/// takes an update.json, up to [http::MAX_BODY])
+const MAX_BODY: usize = 0x10000;
+
+/// Most clients served at the same time. Others are turned away with a 503
+const MAX_CLIENTS: usize = 64;
+
+type Reply<T> = Sender<std::result::Result<T, IoError>>;
+
+/// Every device as the thread that owns Io last described them. (Clients
+/// read this without waiting on that thread, which may be busy updating)
+type Devices = Arc<RwLock<Vec<DeviceMetadata>>>;
+
+/// Reads a request from a client until [deadline]. (A read timeout alone
+/// would let a client hold a thread by sending a byte at a time)
+struct Deadline<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.deadline.checked_duration_since(Instant::now()) {
+            Some(left) if left > Duration::from_millis(0) => {
+                self.stream.set_read_timeout(Some(left))?;
+                self.stream.read(buf)
+            }
+            _ => Err(std::io::ErrorKind::TimedOut.into()),
+        }
+    }
+}
+
+/// Work for the thread that owns Io
+enum Job {
+    /// Scan usb again and describe every device
+    Scan { reply: Reply<Vec<DeviceMetadata>> },
+    /// Forward a request to a device
+    Request {
+        serial: String,
+        request: Request,
+        reply: Reply<String>,
+    },
+    /// Update a device and report each packet sent
+    Update {
+        serial: String,
+        pack: DashboardUpdatePackets,
+        image: u8,
+        progress: Sender<Progress>,
+    },
+}
+
+/// Events sent back while updating a device
+enum Progress {
+    Packet(usize, usize),
+    Done,
+    Failed(String),
+}
+
+/// Own Io and run jobs one at a time. (Only one process may claim a usb
+/// device so every client shares this one, and a request waits while a device
+/// is updated.) Usb is scanned again every [rescan] when there is nothing to
+/// do. [devices] is kept up to date after every job
+fn run(
+    jobs: Receiver<Job>,
+    rescan: Option<Duration>,
+    security: Security,
+    devices: Devices,
+) {
+    let mut linq = Io::new();
+    security.apply(&mut linq);
+    if let Err(e) = block_on(linq.scan()) {
+        warn!("failed to scan usb => {}", e);
+    }
+    loop {
+        *devices.write().unwrap() = linq.meta();
+        let job = match rescan {
+            Some(rescan) => match jobs.recv_timeout(rescan) {
+                Ok(job) => job,
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Err(e) = block_on(linq.scan()) {
+                        warn!("failed to scan usb => {}", e);
+                    }
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            },
+            None => match jobs.recv() {
+                Ok(job) => job,
+                Err(_) => break,
+            },
+        };
+        match job {
+            Job::Scan { reply } => {
+                let result = block_on(linq.scan()).map(|_| linq.meta());
+                let _ = reply.send(result);
+            }
+            Job::Request {
+                serial,
+                request,
+                reply,
+            } => {
+                let _ = reply.send(block_on(linq.request(&serial, request)));
+            }
+            Job::Update {
+                serial,
+                pack,
+                image,
+                progress,
+            } => {
+                let result = block_on(
+                    linq.update(&serial, pack, image).try_for_each(|x| {
+                        let _ = progress.send(Progress::Packet(x.0, x.1));
+                        future::ready(Ok(()))
+                    }),
+                );
+                let _ = progress.send(match result {
+                    Ok(()) => Progress::Done,
+                    Err(e) => Progress::Failed(e.to_string()),
+                });
+            }
+        }
+    }
+    if let Err(e) = linq.close() {
+        warn!("failed to close => {}", e);
+    }
+}
+
+fn respond(status: i64, body: String) -> Response {
+    Response {
+        status,
+        headers: vec![("Content-Type".into(), "application/json".into())],
+        body: body.into_bytes(),
+    }
+}
+
+fn failure(status: i64, message: &str) -> Response {
+    respond(status, json!({ "error": message }).to_string())
+}
+
+/// Device errors keep their status. Anything else is the gateway failing
+fn io_failure(e: IoError) -> Response {
+    match e {
+        IoError::ApiError(e) if (100..600).contains(&e.code) => {
+            failure(e.code, &e.to_string())
+        }
+        IoError::DeviceNotFound(_) => failure(404, &e.to_string()),
+        e => failure(502, &e.to_string()),
+    }
+}
+
+/// Send a job and wait for the answer
+fn ask<T, F>(jobs: &Sender<Job>, job: F) -> std::result::Result<T, Response>
+where
+    F: FnOnce(Reply<T>) -> Job,
+{
+    let (reply, answer) = channel();
+    jobs.send(job(reply))
+        .map_err(|_| failure(500, "gateway is shutting down"))?;
+    answer
+        .recv()
+        .map_err(|_| failure(500, "gateway is shutting down"))?
+        .map_err(io_failure)
+}
+
+fn list(devices: &Devices) -> Response {
+    respond(200, json!(*devices.read().unwrap()).to_string())
+}
+
+fn scan(jobs: &Sender<Job>) -> Response {
+    match ask(jobs, |reply| Job::Scan { reply }) {
+        Ok(meta) => respond(200, json!(meta).to_string()),
+        Err(response) => response,
+    }
+}
+
+fn device(devices: &Devices, serial: &str) -> Response {
+    match devices.read().unwrap().iter().find(|x| x.serial == serial) {
+        Some(meta) => respond(200, json!(meta).to_string()),
+        None => failure(404, &format!("device not found => {}", serial)),
+    }
+}
+
+/// Pass a request through to the device. (Only the method, path, query and
+/// body are forwarded)
+fn forward(
+    jobs: &Sender<Job>,
+    serial: &str,
+    r: Request,
+    path: &str,
+) -> Response {
+    let mut request = Request::new(r.method, path);
+    request.query = r.query;
+    request.body = r.body;
+    let serial = serial.to_owned();
+    match ask(jobs, |reply| Job::Request {
+        serial,
+        request,
+        reply,
+    }) {
+        Ok(body) => respond(200, body),
+        Err(response) => response,
+    }
+}
+
+/// Update a device with the update.json in the body. Progress is streamed
+/// back as server sent events IE: "event: progress\ndata: {..}\n\n"
+fn update(
+    stream: &mut TcpStream,
+    jobs: &Sender<Job>,
+    devices: &Devices,
+    serial: &str,
+    r: Request,
+) -> std::io::Result<()> {
+    let text = String::from_utf8_lossy(r.body.as_bytes()).into_owned();
+    let pack = match DashboardUpdatePackets::parse(&text) {
+        Ok(pack) => pack,
+        Err(e) => {
+            let response = failure(400, &e.to_string());
+            return stream.write_all(&response.to_bytes());
+        }
+    };
+    if !devices.read().unwrap().iter().any(|x| x.serial == serial) {
+        let e = format!("device not found => {}", serial);
+        return stream.write_all(&failure(404, &e).to_bytes());
+    }
+    let image = match r.query.iter().find(|(k, _)| k == "image") {
+        Some((_, v)) if v == "website" => 1,
+        _ => 0,
+    };
+    let (progress, events) = channel();
+    let job = Job::Update {
+        serial: serial.to_owned(),
+        pack,
+        image,
+        progress,
+    };
+    if jobs.send(job).is_err() {
+        let response = failure(500, "gateway is shutting down");
+        return stream.write_all(&response.to_bytes());
+    }
+    stream.write_all(
+        b"HTTP/1.1 200 OK\r\n\
+          Content-Type: text/event-stream\r\n\
+          Cache-Control: no-cache\r\n\
+          Connection: close\r\n\
+          \r\n",
+    )?;
+    for event in events {
+        let (name, data) = match event {
+            Progress::Packet(count, total) => {
+                ("progress", json!({"count": count, "total": total}))
+            }
+            Progress::Done => ("done", json!({})),
+            Progress::Failed(e) => ("error", json!({ "error": e })),
+        };
+        write!(stream, "event: {}\ndata: {}\n\n", name, data)?;
+        stream.flush()?;
+    }
+    Ok(())
+}
+
+fn segments(path: &str) -> Vec<&str> {
+    path.split('/').filter(|x| !x.is_empty()).collect()
+}
+
+/// Only an update may send a large body
+fn max_body(r: &Request) -> usize {
+    match (r.method.as_str(), &segments(&r.path)[..]) {
+        ("POST", ["devices", _, "update"]) => http::MAX_BODY,
+        _ => MAX_BODY,
+    }
+}
+
+/// Serve one connection. Routes:
+///
+/// GET  /devices                        every device
+/// POST /devices/scan                   scan usb again, then every device
+/// GET  /devices/{serial}               one device
+/// POST /devices/{serial}/update        update (?image=website) with progress
+/// *    /devices/{serial}/ATX/...       passed through to the device
+fn serve(mut stream: TcpStream, jobs: Sender<Job>, devices: Devices) {
+    let mut reader = Deadline {
+        stream: &stream,
+        deadline: Instant::now() + REQUEST_TIMEOUT,
+    };
+    let request = match http::deserialize_limited(&mut reader, max_body) {
+        Ok(request) => request,
+        Err(e) => {
+            let _ = stream.write_all(&failure(400, &e.to_string()).to_bytes());
+            return;
+        }
+    };
+    info!("{}", request);
+    let path = request.path.clone();
+    let segments = segments(&path);
+    let method = request.method.as_str();
+    let response = match (method, &segments[..]) {
+        ("GET", ["devices"]) => list(&devices),
+        ("POST", ["devices", "scan"]) => scan(&jobs),
+        ("GET", ["devices", serial]) => device(&devices, serial),
+        ("POST", ["devices", serial, "update"]) => {
+            let result = update(&mut stream, &jobs, &devices, serial, request);
+            if let Err(e) = result {
+                warn!("update of {} interrupted => {}", serial, e);
+            }
+            return;
+        }
+        (_, ["devices", serial, "ATX", rest @ ..]) => {
+            let path = ["", "ATX"].iter().chain(rest).copied();
+            let path = path.collect::<Vec<&str>>().join("/");
+            forward(&jobs, serial, request, &path)
+        }
+        (_, ["devices", ..]) => failure(405, "method not allowed"),
+        _ => failure(404, "not found"),
+    };
+    if let Err(e) = stream.write_all(&response.to_bytes()) {
+        warn!("failed to respond => {}", e);
+    }
+}
+
+pub fn process_serve(cli: &ArgMatches) -> Outcome {
+    let listen = cli.value_of("listen").unwrap_or("127.0.0.1:33480");
+    let rescan = match cli.value_of("rescan").unwrap_or("0").parse::<u64>() {
+        Ok(0) => None,
+        Ok(n) => Some(Duration::from_secs(n)),
+        Err(_) => {
+            let e = "--rescan expects a number of seconds".to_owned();
+            return Err(LinqError::InvalidArgument(e).into());
+        }
+    };
+    let security = Security::from_args(cli)?;
+    let listener = TcpListener::bind(listen)?;
+    let (jobs, receiver) = channel();
+    let devices = Devices::default();
+    let io = {
+        let devices = devices.clone();
+        std::thread::spawn(move || run(receiver, rescan, security, devices))
+    };
+    let clients = Arc::new(AtomicUsize::new(0));
+    eprintln!("serving devices on http://{}", listen);
+    for stream in listener.incoming() {
+        match stream {
+            Ok(mut stream) => {
+                if clients.fetch_add(1, Ordering::SeqCst) >= MAX_CLIENTS {
+                    clients.fetch_sub(1, Ordering::SeqCst);
+                    let response = failure(503, "too many clients");
+                    let _ = stream.write_all(&response.to_bytes());
+                    continue;
+                }
+                let (jobs, devices) = (jobs.clone(), devices.clone());
+                let clients = clients.clone();
+                std::thread::spawn(move || {
+                    serve(stream, jobs, devices);
+                    clients.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+            Err(e) => warn!("failed to accept => {}", e),
+        }
+    }
+    drop(jobs);
+    let _ = io.join();
+    Ok(Report::default())
+}
@@ -0,0 +1,250 @@
+use crate::connect::{connect, connect_url, open};
+use crate::output::{Outcome, Report};
+use clap::ArgMatches;
+use futures::executor::block_on;
+use linq::error::*;
+use linq::io::Io;
+use linq::Request;
+use linq::PATHS;
+use log::{error, warn};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use serde_json::Value;
+use std::path::PathBuf;
+
+const COMMANDS: &[&str] = &[
+    "get", "post", "put", "delete", "use", "devices", "scan", "about", "help",
+    "exit",
+];
+
+const HELP: &str = "\
+get PATH             read a resource IE: get /ATX/network
+post PATH JSON       write a resource IE: post /ATX/about/siteId {\"siteId\":\"a\"}
+put PATH JSON        create a resource
+delete PATH          remove a resource
+use SERIAL|URL       talk to another device IE: use zmtp://SERIAL@10.0.0.2:33455
+devices              list the devices (* is the one in use)
+scan                 scan usb again
+about                read /ATX/about of the device in use
+help                 print this message
+exit                 leave the shell";
+
+/// Completes command names, and known /ATX paths after a request
+struct Complete;
+
+impl Completer for Complete {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let line = &line[..pos];
+        let start = line.rfind(' ').map_or(0, |i| i + 1);
+        let word = &line[start..];
+        let candidates: &[&str] = match line[..start].split_whitespace().count()
+        {
+            0 => COMMANDS,
+            1 if line.starts_with("get")
+                || line.starts_with("post")
+                || line.starts_with("put")
+                || line.starts_with("delete") =>
+            {
+                PATHS
+            }
+            _ => &[],
+        };
+        let found = candidates
+            .iter()
+            .filter(|x| x.starts_with(word))
+            .map(|x| x.to_string())
+            .collect();
+        Ok((start, found))
+    }
+}
+
+impl Hinter for Complete {
+    type Hint = String;
+}
+
+impl Highlighter for Complete {}
+
+impl Validator for Complete {}
+
+impl Helper for Complete {}
+
+/// One Io for the whole session so devices are only opened once
+struct Shell {
+    linq: Io,
+    serial: Option<String>,
+}
+
+impl Shell {
+    fn prompt(&self) -> String {
+        match &self.serial {
+            Some(serial) => format!("atx:{}> ", serial),
+            None => "atx> ".to_owned(),
+        }
+    }
+
+    fn serial(&self) -> Result<&str> {
+        self.serial.as_deref().ok_or_else(|| {
+            LinqError::InvalidArgument("no device (see use)".into())
+        })
+    }
+
+    fn request(&self, request: Request) -> Result<String> {
+        let serial = self.serial()?;
+        let response = block_on(self.linq.request(serial, request))?;
+        Ok(pretty(&response))
+    }
+
+    fn devices(&self) -> String {
+        self.linq
+            .meta()
+            .iter()
+            .map(|x| {
+                let mark = match Some(&x.serial) == self.serial.as_ref() {
+                    true => "*",
+                    false => " ",
+                };
+                let product = x.product.as_deref().unwrap_or("-");
+                format!("{} {} {} {}", mark, x.serial, product, x.transport())
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn scan(&mut self) -> Result<String> {
+        let found = block_on(self.linq.scan())?;
+        if self.serial.is_none() && found.len() == 1 {
+            self.serial = Some(found[0].serial.clone());
+        }
+        Ok(self.devices())
+    }
+
+    fn switch(&mut self, to: &str) -> Result<String> {
+        let serial = match to.contains("://") {
+            true => connect_url(&mut self.linq, to)?,
+            false => match self.linq.meta().iter().any(|x| x.serial == to) {
+                true => to.to_owned(),
+                false => return Err(IoError::DeviceNotFound(to.into()).into()),
+            },
+        };
+        self.serial = Some(serial.clone());
+        Ok(serial)
+    }
+
+    /// Run one line. Returns None when the user is done
+    fn run(&mut self, line: &str) -> Option<Result<String>> {
+        let line = line.trim();
+        let (command, rest) = match line.split_once(' ') {
+            Some((command, rest)) => (command, rest.trim()),
+            None => (line, ""),
+        };
+        let (path, body) = match rest.split_once(' ') {
+            Some((path, body)) => (path, body.trim()),
+            None => (rest, ""),
+        };
+        let result = match (command, path, body) {
+            ("", _, _) => Ok(String::new()),
+            ("exit", _, _) | ("quit", _, _) => return None,
+            ("help", _, _) => Ok(HELP.to_owned()),
+            ("get", path, "") if !path.is_empty() => {
+                self.request(Request::get(path))
+            }
+            ("post", path, body) if !body.is_empty() => {
+                self.request(Request::post_raw(path, body.to_owned()))
+            }
+            ("put", path, body) if !body.is_empty() => {
+                self.request(Request::put_raw(path, body.to_owned()))
+            }
+            ("delete", path, "") if !path.is_empty() => {
+                self.request(Request::delete(path))
+            }
+            ("use", to, "") if !to.is_empty() => self.switch(to),
+            ("devices", "", _) => Ok(self.devices()),
+            ("scan", "", _) => self.scan(),
+            ("about", "", _) => self.request(Request::get("/ATX/about")),
+            _ => {
+                Err(LinqError::InvalidArgument(format!("{} (try help)", line)))
+            }
+        };
+        Some(result)
+    }
+}
+
+/// Responses are printed as indented JSON when they are JSON
+fn pretty(response: &str) -> String {
+    serde_json::from_str::<Value>(response)
+        .ok()
+        .and_then(|x| serde_json::to_string_pretty(&x).ok())
+        .unwrap_or_else(|| response.to_owned())
+}
+
+/// ~/.atx_history
+fn history() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".atx_history"))
+}
+
+pub fn process_shell(cli: &ArgMatches) -> Outcome {
+    let mut linq = open(cli)?;
+    let serial = match cli.value_of("protocol") {
+        Some(_) => match block_on(connect(&mut linq, cli)) {
+            Ok(serial) => Some(serial),
+            Err(e) => {
+                linq.close()?;
+                return Err(e.into());
+            }
+        },
+        None => None,
+    };
+    let mut shell = Shell { linq, serial };
+    if shell.serial.is_none() {
+        if let Err(e) = shell.scan() {
+            warn!("failed to scan usb => {}", e);
+        }
+    }
+
+    let mut editor = Editor::<Complete>::new();
+    editor.set_helper(Some(Complete));
+    let history = history();
+    if let Some(path) = &history {
+        let _ = editor.load_history(path);
+    }
+    loop {
+        let line = match editor.readline(&shell.prompt()) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                error!("{}", e);
+                break;
+            }
+        };
+        if !line.trim().is_empty() {
+            editor.add_history_entry(line.as_str());
+        }
+        match shell.run(&line) {
+            Some(Ok(text)) if text.is_empty() => (),
+            Some(Ok(text)) => println!("{}", text),
+            Some(Err(e)) => eprintln!("error: {}", e),
+            None => break,
+        }
+    }
+    if let Some(path) = &history {
+        if let Err(e) = editor.save_history(path) {
+            warn!("failed to save history => {}", e);
+        }
+    }
+    shell.linq.close()?;
+    Ok(Report::default())
+}
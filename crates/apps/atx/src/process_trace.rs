@@ -0,0 +1,21 @@
+use crate::output::{Outcome, Report};
+use clap::ArgMatches;
+use linq::capture;
+use linq::k64::packet;
+
+pub fn process_trace(cli: &ArgMatches) -> Outcome {
+    let frames = capture::load(cli.value_of("file").unwrap())?;
+    let trace = packet::decode(&frames);
+    if cli.is_present("violations") {
+        Ok(Report::new(
+            trace
+                .violations()
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<String>>()
+                .join("\n"),
+        ))
+    } else {
+        Ok(Report::new(trace.to_string()))
+    }
+}
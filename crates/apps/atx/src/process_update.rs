@@ -1,16 +1,18 @@
+use crate::connect::{connect, open};
+use crate::output::{Outcome, Report};
 use clap::ArgMatches;
 use futures::executor::block_on;
 use futures::prelude::*;
-use linq::error::*;
 use linq::io::Io;
 use std::{io, io::prelude::*};
 
 fn print_bar(bar: &Vec<char>) {
     for c in bar {
-        print!("{}", c);
+        eprint!("{}", c);
     }
 }
 
+/// Draw the progress bar on stderr (stdout is for the report)
 fn print_status(count: usize, total: usize) {
     let weight: f32 = 50 as f32 / total as f32;
     let distance = weight * count as f32;
@@ -23,41 +25,46 @@ fn print_status(count: usize, total: usize) {
     print_bar(&progress);
     print_bar(&remaining);
     print_bar(&end);
-    print!("{esc}[0G", esc = 27 as char);
-    print!("{:>3.3}:{:<3.3}  ", count, total);
-    io::stdout().flush().ok().expect("cloud not flush stdout");
+    eprint!("{esc}[0G", esc = 27 as char);
+    eprint!("{:>3.3}:{:<3.3}  ", count, total);
+    let _ = io::stderr().flush();
 }
 
-async fn process(linq: &mut Io, f: &str, image: u8) -> Result<String> {
-    let metadata = linq.scan().await?;
-    if metadata.len() > 0 {
-        linq.update_file_path(&metadata[0].serial, f, image)?
-            .try_for_each(|x| {
-                let (count, total) = (x.0, x.1);
-                print_status(count, total);
-                future::ready(Ok(()))
-            })
-            .await?;
+async fn process(
+    linq: &mut Io,
+    cli: &ArgMatches<'_>,
+    f: &str,
+    image: u8,
+) -> Outcome {
+    let serial = connect(linq, cli).await?;
+    let report = Report::default().serial(&serial);
+    let update = match linq.update_file_path(&serial, f, image) {
+        Ok(update) => update,
+        Err(e) => return Err(report.fail(e)),
+    };
+    let result = update
+        .try_for_each(|x| {
+            let (count, total) = (x.0, x.1);
+            print_status(count, total);
+            future::ready(Ok(()))
+        })
+        .await;
+    match result {
+        Ok(()) => Ok(report.body("Complete!")),
+        Err(e) => Err(report.fail(e)),
     }
-    Ok("Complete!".to_owned())
 }
-pub fn process_update(cli: &ArgMatches) -> Result<String> {
+pub fn process_update(cli: &ArgMatches) -> Outcome {
     let p = cli.value_of("file").unwrap();
 
-    // Currently only USB supported at the moment
-    let proto = cli.value_of("protocol").unwrap();
-    if !(proto == "usb") {
-        panic!("protocol {} not supported", proto);
-    };
-
     let image = if cli.value_of("image").unwrap() == "firmware" {
         0
     } else {
         1
     };
 
-    let mut linq = Io::new();
-    let result = block_on(process(&mut linq, p, image));
-    linq.close().unwrap();
+    let mut linq = open(cli)?;
+    let result = block_on(process(&mut linq, cli, p, image));
+    linq.close()?;
     result
 }
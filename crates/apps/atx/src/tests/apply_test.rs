@@ -0,0 +1,93 @@
+use crate::process_apply::{parse, plan};
+use serde_json::{json, Value};
+
+/// The example from the site file docs
+const SITE: &str = r#"
+reboot: true
+settings:               # every device gets these
+  /ATX/about:
+    siteId: site-a
+devices:
+  - product: LINQ2      # every usb LINQ2
+  - serial: N4A10001    # a usb device (with its own settings)
+    settings:
+      /ATX/network:
+        ipConfig:
+          ip: 192.168.0.10
+  - address: zmtp://N4A10002@10.0.0.2:33455
+"#;
+
+#[test]
+fn test_apply_plan() {
+    let site = parse(SITE).unwrap();
+    assert!(site.reboot);
+    let resolved: Vec<_> = site
+        .devices
+        .iter()
+        .zip(vec![
+            vec!["N4A10001".to_string(), "N4A10003".to_string()],
+            vec!["N4A10001".to_string()],
+            vec!["N4A10002".to_string()],
+        ])
+        .collect();
+
+    // Each device once, with the settings of every entry that names it
+    let planned = plan(&site.settings, &resolved);
+    let serials: Vec<&str> = planned.iter().map(|(s, _)| &s[..]).collect();
+    assert_eq!(serials, vec!["N4A10001", "N4A10003", "N4A10002"]);
+    let about = json!({"siteId": "site-a"});
+    let expect = json!({
+        "/ATX/about": about,
+        "/ATX/network": {"ipConfig": {"ip": "192.168.0.10"}},
+    });
+    assert_eq!(Value::Object(planned[0].1.clone()), expect);
+    assert_eq!(
+        Value::Object(planned[1].1.clone()),
+        json!({"/ATX/about": about})
+    );
+    assert_eq!(
+        Value::Object(planned[2].1.clone()),
+        json!({"/ATX/about": about})
+    );
+}
+
+#[test]
+fn test_apply_plan_serial_over_product() {
+    // The serial entry wins even when the product entry comes after it
+    let site = parse(
+        r#"
+settings:
+  /ATX/about:
+    siteId: site-a
+devices:
+  - serial: N4A10001
+    settings:
+      /ATX/about:
+        siteId: bench
+  - product: LINQ2
+    settings:
+      /ATX/about:
+        siteId: site-b
+"#,
+    )
+    .unwrap();
+    let resolved: Vec<_> = site
+        .devices
+        .iter()
+        .zip(vec![
+            vec!["N4A10001".to_string()],
+            vec!["N4A10003".to_string(), "N4A10001".to_string()],
+        ])
+        .collect();
+    let planned = plan(&site.settings, &resolved);
+    let site_id = |i: usize| planned[i].1["/ATX/about"]["siteId"].clone();
+    assert_eq!(planned.len(), 2);
+    assert_eq!(
+        (&planned[0].0[..], site_id(0)),
+        ("N4A10001", json!("bench"))
+    );
+    assert_eq!(
+        (&planned[1].0[..], site_id(1)),
+        ("N4A10003", json!("site-b"))
+    );
+}
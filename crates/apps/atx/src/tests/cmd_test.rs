@@ -0,0 +1,47 @@
+use crate::process_cmd::request;
+use clap::{load_yaml, App};
+use linq::error::*;
+use linq::Request;
+
+/// Build the request for "atx cmd [args]"
+fn cmd(args: &[&str]) -> Result<Request> {
+    let yaml = load_yaml!("../cli.yaml");
+    let argv = ["atx", "cmd", "-x", "usb", "-p", "/ATX/about"];
+    let matches = App::from(yaml)
+        .get_matches_from_safe(argv.iter().chain(args))
+        .unwrap();
+    request(matches.subcommand_matches("cmd").unwrap())
+}
+
+#[test]
+fn test_cmd_methods() {
+    let body = r#"{"siteId":"a"}"#;
+    let expect = [
+        (vec!["-m", "GET"], Request::get("/ATX/about")),
+        (vec!["-m", "DELETE"], Request::delete("/ATX/about")),
+        (
+            vec!["-m", "POST", "-d", body],
+            Request::post_raw("/ATX/about", body),
+        ),
+        (
+            vec!["-m", "PUT", "-d", body],
+            Request::put_raw("/ATX/about", body),
+        ),
+    ];
+    for (args, request) in expect.iter() {
+        let built = cmd(args).unwrap();
+        assert_eq!(built.method, request.method);
+        assert_eq!(built.body, request.body);
+    }
+}
+
+#[test]
+fn test_cmd_body_required() {
+    for method in ["POST", "PUT"] {
+        match cmd(&["-m", method]) {
+            Err(LinqError::InvalidArgument(e)) => assert!(e.contains(method)),
+            _ => panic!("expected {} to require a body", method),
+        }
+    }
+    assert!(cmd(&["-m", "GET", "-d", "{}"]).is_err());
+}
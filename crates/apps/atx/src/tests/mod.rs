@@ -0,0 +1,2 @@
+mod apply_test;
+mod cmd_test;
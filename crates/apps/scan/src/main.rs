@@ -11,7 +11,7 @@ extern crate slog_term;
 
 ///
 use futures::executor::block_on;
-use log::info;
+use log::{info, warn};
 use slog::Drain;
 
 fn main() {
@@ -26,9 +26,33 @@ fn main() {
 
     let mut l = linq::io::Io::new();
     let future = async {
-        let result = l.scan().await.unwrap();
-        let result = l.get(&result[0].serial, "/ATX/network").await.unwrap();
-        info!("{}", result);
+        let result = match l.scan().await {
+            Ok(result) => result,
+            Err(e) => return warn!("scan failed => {}", e),
+        };
+        for device in &result {
+            // NOTE about is read when the device is opened
+            match &device.about {
+                Some(a) => info!(
+                    "{} {} {} prj:{} atx:{} site:{}",
+                    device.serial,
+                    a.product,
+                    a.mac,
+                    a.prjVersion,
+                    a.atxVersion,
+                    a.siteId
+                ),
+                None => info!("{}", device.serial),
+            }
+        }
+        let serial = match result.first() {
+            Some(device) => &device.serial,
+            None => return info!("no devices found"),
+        };
+        match l.get(serial, "/ATX/network").await {
+            Ok(network) => info!("{}", network),
+            Err(e) => warn!("[{}] failed to read network => {}", serial, e),
+        }
     };
     block_on(future);
     l.close().unwrap();
@@ -4,5 +4,11 @@ mod tests;
 pub mod about;
 pub use about::*;
 
+pub mod network;
+pub use network::*;
+
+pub mod paths;
+pub use paths::*;
+
 pub mod update;
 pub use update::*;
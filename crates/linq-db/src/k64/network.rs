@@ -0,0 +1,27 @@
+use serde::{Deserialize, Serialize};
+
+/// How the device finds its address. (dhcp, dns and hostname are missing on
+/// older firmware)
+#[derive(Clone, Default, Debug, PartialEq, Deserialize, Serialize)]
+pub struct IpConfig {
+    #[serde(default)]
+    pub dhcp: bool,
+    pub ip: String,
+    pub sn: String,
+    pub gw: String,
+    #[serde(default)]
+    pub dns: Vec<String>,
+    #[serde(default)]
+    pub hostname: String,
+}
+
+#[derive(Clone, Default, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Network {
+    pub ip_config: IpConfig,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct NetworkResponse {
+    pub network: Network,
+}
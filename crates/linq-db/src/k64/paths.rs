@@ -0,0 +1,24 @@
+/// Every resource a K64 serves. (Paths to each setting are included so
+/// tools can offer them IE: to complete what a user is typing)
+pub const PATHS: &[&str] = &[
+    "/ATX/about",
+    "/ATX/about/siteId",
+    "/ATX/about/prjVersion",
+    "/ATX/about/prjVersionRc",
+    "/ATX/about/atxVersion",
+    "/ATX/about/atxVersionRc",
+    "/ATX/about/sid",
+    "/ATX/about/mac",
+    "/ATX/about/product",
+    "/ATX/network",
+    "/ATX/network/ipConfig",
+    "/ATX/network/ipConfig/dhcp",
+    "/ATX/network/ipConfig/ip",
+    "/ATX/network/ipConfig/sn",
+    "/ATX/network/ipConfig/gw",
+    "/ATX/network/ipConfig/dns",
+    "/ATX/network/ipConfig/hostname",
+    "/ATX/exe/save",
+    "/ATX/exe/reboot",
+    "/ATX/exe/update",
+];
@@ -1,2 +1,4 @@
 mod mock_data;
+mod network_test;
+mod paths_test;
 mod update_test;
@@ -0,0 +1,46 @@
+use crate::k64::network::*;
+use crate::k64::paths::*;
+use serde_json::{self, json};
+
+#[test]
+fn test_network_paths() {
+    let ip_config = serde_json::to_value(IpConfig::default()).unwrap();
+    for key in ip_config.as_object().unwrap().keys() {
+        let path = format!("/ATX/network/ipConfig/{}", key);
+        assert!(PATHS.contains(&path.as_str()), "missing {}", path);
+    }
+}
+
+#[test]
+fn test_network_parse() {
+    let response = json!({
+        "network": {
+            "ipConfig": {
+                "dhcp": true,
+                "ip": "10.0.0.2",
+                "sn": "255.255.255.0",
+                "gw": "10.0.0.1",
+                "dns": ["10.0.0.1", "8.8.8.8"],
+                "hostname": "linq"
+            }
+        }
+    });
+    let parsed: NetworkResponse =
+        serde_json::from_value(response.clone()).unwrap();
+    let ip_config = &parsed.network.ip_config;
+    assert!(ip_config.dhcp);
+    assert_eq!(ip_config.dns, vec!["10.0.0.1", "8.8.8.8"]);
+    assert_eq!(ip_config.hostname, "linq");
+    assert_eq!(serde_json::to_value(&parsed).unwrap(), response);
+
+    // Older firmware only reports a static address
+    let response = json!({
+        "network": {
+            "ipConfig": {"ip": "10.0.0.2", "sn": "255.0.0.0", "gw": "10.0.0.1"}
+        }
+    });
+    let parsed: NetworkResponse = serde_json::from_value(response).unwrap();
+    let ip_config = parsed.network.ip_config;
+    assert_eq!((ip_config.dhcp, ip_config.dns.len()), (false, 0));
+    assert_eq!(ip_config.hostname, "");
+}
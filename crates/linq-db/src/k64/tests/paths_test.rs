@@ -0,0 +1,20 @@
+use crate::k64::about::*;
+use crate::k64::paths::*;
+use serde_json;
+
+#[test]
+fn test_paths_about() {
+    let about = serde_json::to_value(About::default()).unwrap();
+    for key in about.as_object().unwrap().keys() {
+        let path = format!("/ATX/about/{}", key);
+        assert!(PATHS.contains(&path.as_str()), "missing {}", path);
+    }
+}
+
+#[test]
+fn test_paths_unique() {
+    for (i, path) in PATHS.iter().enumerate() {
+        assert!(path.starts_with("/ATX/"));
+        assert!(!PATHS[i + 1..].contains(path), "duplicate {}", path);
+    }
+}
@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+
+/// What a device (and the driver or channel speaking to it) can do. Tools
+/// should hide actions a device does not support instead of trying them.
+/// Anything a device does not report is assumed unsupported.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Capabilities {
+    /// Accepts firmware updates (POST /ATX/exe/update)
+    pub update: bool,
+    /// Accepts website images with an update
+    pub website: bool,
+    /// Network configuration is available (/ATX/network)
+    pub network: bool,
+    /// Can be rebooted (POST /ATX/exe/reboot)
+    pub reboot: bool,
+    /// Largest request that can be carried to the device. (0 if unknown)
+    pub max_payload: usize,
+}
+
+impl Capabilities {
+    /// Same as self except a request is never larger than [max_payload]
+    pub fn limit(self, max_payload: usize) -> Self {
+        let max_payload = match self.max_payload {
+            0 => max_payload,
+            n => n.min(max_payload),
+        };
+        Capabilities {
+            max_payload,
+            ..self
+        }
+    }
+}
@@ -2,19 +2,86 @@
 /// entirely necessary except that it is only useful to stub out a concret
 /// implementation in order to facilitate testing.
 use crate::error::*;
+use crate::metadata::DeviceMetadata;
 use crate::request::Request;
 use core::pin::Pin;
 use futures::stream::BoxStream;
+use linq_db::k64::{About, AboutResponse, Update};
 use serde::de::DeserializeOwned;
 use std::future::Future;
+use std::sync::{Arc, RwLock};
 
 /// A Channel is able to make async requests and describe it self
 pub trait Channel: AsyncRequester + Meta {}
 
-/// A Meta trait is able to describe itself as a JSON string
+/// A Meta trait is able to describe the device behind the channel
 pub trait Meta {
-    /// Implementing this trait must provide a JSON string
-    fn meta(&self) -> String;
+    /// Where the device with serial number [serial] is (and what the
+    /// transport knows about it)
+    fn describe(&self, serial: &str) -> DeviceMetadata;
+
+    /// Where the channel keeps what the device reported at /ATX/about
+    fn cache(&self) -> &AboutCache;
+
+    /// Describe the device with serial number [serial]
+    fn meta(&self, serial: &str) -> DeviceMetadata {
+        self.cache().describe(self.describe(serial))
+    }
+
+    /// What the device last reported at /ATX/about. (None until read)
+    fn about(&self) -> Option<About> {
+        self.cache().get()
+    }
+
+    /// Remember what the device reported at /ATX/about
+    fn set_about(&self, about: About) {
+        self.cache().set(about)
+    }
+}
+
+/// What a device last reported at /ATX/about. (Shared so that a channel can
+/// fill it in from a thread of its own)
+#[derive(Clone, Default)]
+pub struct AboutCache(Arc<RwLock<Option<About>>>);
+
+impl AboutCache {
+    pub fn new(about: Option<About>) -> Self {
+        AboutCache(Arc::new(RwLock::new(about)))
+    }
+
+    pub fn get(&self) -> Option<About> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn set(&self, about: About) {
+        *self.0.write().unwrap() = Some(about);
+    }
+
+    /// Fill in the fields of [meta] the device reports in /ATX/about
+    pub fn describe(&self, meta: DeviceMetadata) -> DeviceMetadata {
+        match &*self.0.read().unwrap() {
+            Some(about) => meta.with_about(about),
+            None => meta,
+        }
+    }
+
+    /// Read /ATX/about with the blocking [send] on a thread of its own. (A
+    /// device that does not answer is described without it)
+    pub fn read<F>(&self, send: F)
+    where
+        F: FnOnce(Request) -> Result<Vec<u8>> + Send + 'static,
+    {
+        let cache = self.clone();
+        std::thread::spawn(move || {
+            let about = send(Request::get("/ATX/about")).and_then(|x| {
+                serde_json::from_slice::<AboutResponse>(&x)
+                    .map_err(|e| IoError::Parser(e.to_string()))
+            });
+            if let Ok(response) = about {
+                cache.set(response.about);
+            }
+        });
+    }
 }
 
 /// An AsyncRequester is same as a SyncRequest excepts returns a Future!
@@ -39,11 +106,37 @@ pub trait AsyncRequester {
     }
     */
 
+    /// Make a request where the response is expected to be text
     fn request_raw<'a>(
         &'a self,
         serial: &'a str,
         r: Request,
-    ) -> Pin<Box<dyn Future<Output = Result<String>> + 'a>>;
+    ) -> Pin<Box<dyn Future<Output = Result<String>> + 'a>> {
+        let response = self.request_bytes(serial, r);
+        Box::pin(async move {
+            String::from_utf8(response.await?)
+                .map(|s| s.trim_matches(char::from(0)).to_owned())
+                .map_err(|_| IoError::Parser("bad utf8".to_string()))
+        })
+    }
+
+    /// Make a request where the response may be binary
+    fn request_bytes<'a>(
+        &'a self,
+        serial: &'a str,
+        r: Request,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>>> + 'a>>;
+
+    /// Send one chunk of an update to the device
+    fn update<'a>(
+        &'a self,
+        serial: &'a str,
+        update: &Update,
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + 'a>> {
+        let r = Request::post("/ATX/exe/update", update);
+        let response = self.request_bytes(serial, r);
+        Box::pin(async move { response.await.map(|_| ()) })
+    }
 }
 
 pub trait AsyncUpdater {
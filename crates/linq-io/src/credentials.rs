@@ -0,0 +1,261 @@
+use crate::error::*;
+use crate::request::{Auth, Request};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// Key of the entry used when no entry matches a device
+const ANY: &str = "*";
+
+/// A CredentialProvider hands out credentials for a device. Devices are looked
+/// up by serial number or by network address (whichever the transport knows).
+pub trait CredentialProvider: Send + Sync {
+    /// Find credentials for a device. (Only an exact match, see fallback)
+    fn credentials(&self, key: &str) -> Option<Auth>;
+
+    /// Credentials for a device no entry matches (IE: the "*" entry). Only
+    /// used once every key of a device has been tried
+    fn fallback(&self) -> Option<Auth> {
+        None
+    }
+
+    /// Called after a device rejected our credentials (403). Providers that
+    /// are able to fetch fresh credentials should do so here.
+    fn refresh(&self, key: &str) -> Option<Auth> {
+        self.credentials(key)
+    }
+}
+
+/// Find credentials for the first key the provider knows about, else the
+/// fallback
+pub fn lookup<'a, I>(provider: &dyn CredentialProvider, keys: I) -> Option<Auth>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    keys.into_iter()
+        .find_map(|key| provider.credentials(key))
+        .or_else(|| provider.fallback())
+}
+
+/// Same as lookup except asks the provider to refresh its credentials
+pub fn refresh<'a, I>(
+    provider: &dyn CredentialProvider,
+    keys: I,
+) -> Option<Auth>
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    keys.into_iter()
+        .find_map(|key| provider.refresh(key))
+        .or_else(|| provider.fallback())
+}
+
+/// Transports route every request through here. Attaches credentials to the
+/// request (unless the caller already did) and if the device responds with
+/// 403 we refresh the credentials and try once more.
+pub fn authenticate<F>(
+    provider: Option<&dyn CredentialProvider>,
+    keys: &[&str],
+    mut request: Request,
+    mut send: F,
+) -> Result<Vec<u8>>
+where
+    F: FnMut(&Request) -> Result<Vec<u8>>,
+{
+    let provider = match provider {
+        Some(provider) => provider,
+        None => return send(&request),
+    };
+    if request.auth.is_none() {
+        request.auth = lookup(provider, keys.iter().cloned());
+    }
+    match send(&request) {
+        Err(IoError::ApiError(ApiError {
+            kind: ApiErrorKind::Linq403,
+            ..
+        })) if refresh_auth(provider, keys, &mut request) => send(&request),
+        result => result,
+    }
+}
+
+/// Helper to swap in fresh credentials. Returns false when there is nothing
+/// new to try (IE: the provider handed back the credentials that were just
+/// rejected)
+fn refresh_auth(
+    provider: &dyn CredentialProvider,
+    keys: &[&str],
+    request: &mut Request,
+) -> bool {
+    match refresh(provider, keys.iter().cloned()) {
+        Some(auth) if request.auth.as_ref() != Some(&auth) => {
+            request.auth = Some(auth);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Credentials kept in memory. (Useful for tests and for applications that
+/// prompt the user)
+#[derive(Default)]
+pub struct MemoryCredentials {
+    map: RwLock<HashMap<String, Auth>>,
+}
+
+impl MemoryCredentials {
+    pub fn new() -> Self {
+        MemoryCredentials::default()
+    }
+
+    /// Add credentials for a device. Use "*" to match any device
+    pub fn insert(&self, key: &str, auth: Auth) -> Option<Auth> {
+        self.map.write().unwrap().insert(key.to_owned(), auth)
+    }
+
+    /// Forget credentials for a device
+    pub fn remove(&self, key: &str) -> Option<Auth> {
+        self.map.write().unwrap().remove(key)
+    }
+}
+
+impl CredentialProvider for MemoryCredentials {
+    fn credentials(&self, key: &str) -> Option<Auth> {
+        self.map.read().unwrap().get(key).cloned()
+    }
+
+    fn fallback(&self) -> Option<Auth> {
+        self.credentials(ANY)
+    }
+}
+
+/// Credentials read from environment variables. For a device with serial
+/// "ABC-123" and the default prefix we look for...
+///
+/// LINQ_AUTH_ABC_123_TOKEN
+/// LINQ_AUTH_ABC_123_USER and LINQ_AUTH_ABC_123_PASSWORD
+///
+/// ...and then fall back to LINQ_AUTH_TOKEN, LINQ_AUTH_USER and
+/// LINQ_AUTH_PASSWORD.
+pub struct EnvCredentials {
+    prefix: String,
+}
+
+impl Default for EnvCredentials {
+    fn default() -> Self {
+        EnvCredentials::new("LINQ_AUTH")
+    }
+}
+
+impl EnvCredentials {
+    pub fn new(prefix: &str) -> Self {
+        EnvCredentials {
+            prefix: prefix.to_owned(),
+        }
+    }
+
+    /// Name of a variable. Characters not allowed in a variable name (IE: the
+    /// dots of an ip address) are replaced with an underscore
+    pub fn var<'a>(&self, key: Option<&'a str>, name: &'a str) -> String {
+        let key = key.map_or(String::new(), |key| {
+            let key: String = key
+                .chars()
+                .map(|c| match c.is_ascii_alphanumeric() {
+                    true => c.to_ascii_uppercase(),
+                    false => '_',
+                })
+                .collect();
+            format!("_{}", key)
+        });
+        format!("{}{}_{}", self.prefix, key, name)
+    }
+
+    fn read(&self, key: Option<&str>) -> Option<Auth> {
+        let var = |name| std::env::var(self.var(key, name)).ok();
+        match (var("TOKEN"), var("USER"), var("PASSWORD")) {
+            (Some(token), _, _) => Some(Auth::Token(token)),
+            (None, Some(user), Some(password)) => {
+                Some(Auth::Basic { user, password })
+            }
+            _ => None,
+        }
+    }
+}
+
+impl CredentialProvider for EnvCredentials {
+    fn credentials(&self, key: &str) -> Option<Auth> {
+        self.read(Some(key))
+    }
+
+    fn fallback(&self) -> Option<Auth> {
+        self.read(None)
+    }
+}
+
+/// What an entry in a credentials file looks like
+#[derive(Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum Entry {
+    Basic { user: String, password: String },
+    Token { token: String },
+}
+
+impl From<Entry> for Auth {
+    fn from(entry: Entry) -> Auth {
+        match entry {
+            Entry::Basic { user, password } => Auth::Basic { user, password },
+            Entry::Token { token } => Auth::Token(token),
+        }
+    }
+}
+
+/// Credentials read from a JSON file IE:
+///
+/// {
+///   "ABC-123": { "user": "admin", "password": "admin" },
+///   "192.168.0.10:80": { "token": "..." },
+///   "*": { "user": "admin", "password": "admin" }
+/// }
+///
+/// The file is read again when a device rejects our credentials so that a
+/// file can be edited while a program is running.
+pub struct FileCredentials {
+    path: PathBuf,
+    map: RwLock<HashMap<String, Auth>>,
+}
+
+impl FileCredentials {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let map = RwLock::new(FileCredentials::read(&path)?);
+        Ok(FileCredentials { path, map })
+    }
+
+    /// Parse the credentials from a string
+    pub fn parse(s: &str) -> Result<HashMap<String, Auth>> {
+        serde_json::from_str::<HashMap<String, Entry>>(s)
+            .map(|x| x.into_iter().map(|(k, v)| (k, v.into())).collect())
+            .map_err(|x| IoError::Parser(x.to_string()))
+    }
+
+    fn read(path: &Path) -> Result<HashMap<String, Auth>> {
+        FileCredentials::parse(&std::fs::read_to_string(path)?)
+    }
+}
+
+impl CredentialProvider for FileCredentials {
+    fn credentials(&self, key: &str) -> Option<Auth> {
+        self.map.read().unwrap().get(key).cloned()
+    }
+
+    fn fallback(&self) -> Option<Auth> {
+        self.credentials(ANY)
+    }
+
+    fn refresh(&self, key: &str) -> Option<Auth> {
+        if let Ok(map) = FileCredentials::read(&self.path) {
+            *self.map.write().unwrap() = map;
+        }
+        self.credentials(key)
+    }
+}
@@ -1,4 +1,15 @@
 pub use super::usb::error::{Result as UsbResult, UsbError};
+use linq_sys::{
+    E_LINQ_ERROR_LINQ_ERROR_400, E_LINQ_ERROR_LINQ_ERROR_403,
+    E_LINQ_ERROR_LINQ_ERROR_404, E_LINQ_ERROR_LINQ_ERROR_500,
+    E_LINQ_ERROR_LINQ_ERROR_504, E_LINQ_ERROR_LINQ_ERROR_BAD_ARGS,
+    E_LINQ_ERROR_LINQ_ERROR_DEVICE_NOT_FOUND, E_LINQ_ERROR_LINQ_ERROR_IO,
+    E_LINQ_ERROR_LINQ_ERROR_LIBUSB, E_LINQ_ERROR_LINQ_ERROR_OOM,
+    E_LINQ_ERROR_LINQ_ERROR_PROTOCOL, E_LINQ_ERROR_LINQ_ERROR_SHUTTING_DOWN,
+    E_LINQ_ERROR_LINQ_ERROR_TIMEOUT,
+};
+use serde::Deserialize;
+use std::convert::TryFrom;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -21,6 +32,12 @@ pub enum IoError {
     #[error("io error => {0}")]
     Io(#[from] std::io::Error),
 
+    #[error("zmtp communication failure => {0}")]
+    Zmtp(#[from] zmq::Error),
+
+    #[error("security failure => {0}")]
+    Security(String),
+
     #[error("\"impossible\" error => {0}")]
     Impossible(String),
 
@@ -28,30 +45,173 @@ pub enum IoError {
     Unknown,
 }
 
+/// The kinds of API errors a device can respond with. NOTE each kind maps
+/// one-to-one with an E_LINQ_ERROR code from our C binding.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ApiErrorKind {
+    LinqOom,
+    LinqBadArgs,
+    LinqProtocol,
+    LinqIo,
+    LinqDeviceNotFound,
+    LinqTimeout,
+    LinqShuttingDown,
+    LinqLibusb,
+    Linq400,
+    Linq403,
+    Linq404,
+    Linq500,
+    Linq504,
+    LinqUnknown,
+}
+
+impl ApiErrorKind {
+    /// Map a response code onto an error kind
+    pub fn from_code(code: i64) -> Self {
+        let code = match i32::try_from(code) {
+            Ok(code) => code,
+            Err(_) => return ApiErrorKind::LinqUnknown,
+        };
+        match code {
+            E_LINQ_ERROR_LINQ_ERROR_OOM => ApiErrorKind::LinqOom,
+            E_LINQ_ERROR_LINQ_ERROR_BAD_ARGS => ApiErrorKind::LinqBadArgs,
+            E_LINQ_ERROR_LINQ_ERROR_PROTOCOL => ApiErrorKind::LinqProtocol,
+            E_LINQ_ERROR_LINQ_ERROR_IO => ApiErrorKind::LinqIo,
+            E_LINQ_ERROR_LINQ_ERROR_DEVICE_NOT_FOUND => {
+                ApiErrorKind::LinqDeviceNotFound
+            }
+            E_LINQ_ERROR_LINQ_ERROR_TIMEOUT => ApiErrorKind::LinqTimeout,
+            E_LINQ_ERROR_LINQ_ERROR_SHUTTING_DOWN => {
+                ApiErrorKind::LinqShuttingDown
+            }
+            E_LINQ_ERROR_LINQ_ERROR_LIBUSB => ApiErrorKind::LinqLibusb,
+            E_LINQ_ERROR_LINQ_ERROR_400 => ApiErrorKind::Linq400,
+            E_LINQ_ERROR_LINQ_ERROR_403 => ApiErrorKind::Linq403,
+            E_LINQ_ERROR_LINQ_ERROR_404 => ApiErrorKind::Linq404,
+            E_LINQ_ERROR_LINQ_ERROR_500 => ApiErrorKind::Linq500,
+            E_LINQ_ERROR_LINQ_ERROR_504 => ApiErrorKind::Linq504,
+            _ => ApiErrorKind::LinqUnknown,
+        }
+    }
+
+    /// The E_LINQ_ERROR code of this kind (if the binding knows about it)
+    pub fn code(&self) -> Option<i64> {
+        let code = match self {
+            ApiErrorKind::LinqOom => E_LINQ_ERROR_LINQ_ERROR_OOM,
+            ApiErrorKind::LinqBadArgs => E_LINQ_ERROR_LINQ_ERROR_BAD_ARGS,
+            ApiErrorKind::LinqProtocol => E_LINQ_ERROR_LINQ_ERROR_PROTOCOL,
+            ApiErrorKind::LinqIo => E_LINQ_ERROR_LINQ_ERROR_IO,
+            ApiErrorKind::LinqDeviceNotFound => {
+                E_LINQ_ERROR_LINQ_ERROR_DEVICE_NOT_FOUND
+            }
+            ApiErrorKind::LinqTimeout => E_LINQ_ERROR_LINQ_ERROR_TIMEOUT,
+            ApiErrorKind::LinqShuttingDown => {
+                E_LINQ_ERROR_LINQ_ERROR_SHUTTING_DOWN
+            }
+            ApiErrorKind::LinqLibusb => E_LINQ_ERROR_LINQ_ERROR_LIBUSB,
+            ApiErrorKind::Linq400 => E_LINQ_ERROR_LINQ_ERROR_400,
+            ApiErrorKind::Linq403 => E_LINQ_ERROR_LINQ_ERROR_403,
+            ApiErrorKind::Linq404 => E_LINQ_ERROR_LINQ_ERROR_404,
+            ApiErrorKind::Linq500 => E_LINQ_ERROR_LINQ_ERROR_500,
+            ApiErrorKind::Linq504 => E_LINQ_ERROR_LINQ_ERROR_504,
+            ApiErrorKind::LinqUnknown => return None,
+        };
+        Some(i64::from(code))
+    }
+}
+
+impl std::fmt::Display for ApiErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let s = match self {
+            ApiErrorKind::LinqOom => "out of memory",
+            ApiErrorKind::LinqBadArgs => "bad arguments",
+            ApiErrorKind::LinqProtocol => "protocol error",
+            ApiErrorKind::LinqIo => "internal io error",
+            ApiErrorKind::LinqDeviceNotFound => "device not found",
+            ApiErrorKind::LinqTimeout => "device timeout",
+            ApiErrorKind::LinqShuttingDown => "shutting down",
+            ApiErrorKind::LinqLibusb => "libusb error",
+            ApiErrorKind::Linq400 => "client api protocol error",
+            ApiErrorKind::Linq403 => "unauthorized",
+            ApiErrorKind::Linq404 => "resource not found",
+            ApiErrorKind::Linq500 => "critical server failure",
+            ApiErrorKind::Linq504 => "please try again later",
+            ApiErrorKind::LinqUnknown => "device submitted an unknown error",
+        };
+        write!(f, "{}", s)
+    }
+}
+
 /// API errors are unique from the library errors. API error is when a device is
 /// responding to a request with an error message. Error messages from the
 /// device are represented as API errors.
-#[derive(Error, Debug)]
-pub enum ApiError {
-    // #[error("ok")]
-    // Linq200,
-    #[error("[400] client api protocol error")]
-    Linq400,
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("[{code}] {kind} => {path} {message}")]
+pub struct ApiError {
+    pub kind: ApiErrorKind,
+    pub code: i64,
+    pub path: String,
+    pub message: String,
+}
 
-    #[error("[403] unauthorized")]
-    Linq403,
+/// What an error response from a device looks like
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ErrorBody {
+    error: i64,
+    #[serde(default)]
+    message: String,
+}
 
-    #[error("[404] resource not found")]
-    Linq404,
+impl ApiError {
+    pub fn new(code: i64, path: &str, message: &str) -> Self {
+        ApiError {
+            kind: ApiErrorKind::from_code(code),
+            code,
+            path: path.to_owned(),
+            message: message.to_owned(),
+        }
+    }
 
-    #[error("[500] critical server failure")]
-    Linq500,
+    /// Peek at a response and see if it is an {\"error\": code} object
+    pub fn from_body(path: &str, body: &[u8]) -> Option<Self> {
+        let len = body.iter().rposition(|x| *x != 0).map_or(0, |x| x + 1);
+        serde_json::from_slice::<ErrorBody>(&body[..len])
+            .ok()
+            .filter(|x| !is_success(x.error))
+            .map(|x| ApiError::new(x.error, path, &x.message))
+    }
+}
 
-    #[error("[504] please try again later")]
-    Linq504,
+/// 2xx codes are not errors
+fn is_success(code: i64) -> bool {
+    (200..300).contains(&code)
+}
 
-    #[error("device submitted an unknown error response")]
-    LinqUnknown,
+/// Every channel passes responses through here so that an error response
+/// is presented to the caller as an Err regardless of transport. [status] is
+/// the code reported out of band by the transport (IE: an HTTP status line).
+/// Some transports (IE: usb) can not report a status, so we also peek at the
+/// response to see if it is an {\"error\": code} object.
+pub fn translate_response(
+    path: &str,
+    status: Option<i64>,
+    body: Vec<u8>,
+) -> Result<Vec<u8>> {
+    match status {
+        Some(code) if !is_success(code) => {
+            Err(ApiError::from_body(path, &body)
+                .unwrap_or_else(|| {
+                    let message = String::from_utf8_lossy(&body);
+                    ApiError::new(code, path, message.trim())
+                })
+                .into())
+        }
+        _ => match ApiError::from_body(path, &body) {
+            Some(e) => Err(e.into()),
+            None => Ok(body),
+        },
+    }
 }
 
 pub type Result<T> = std::result::Result<T, IoError>;
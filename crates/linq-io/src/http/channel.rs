@@ -0,0 +1,172 @@
+use super::serialize;
+use super::{parse, Response, TlsConfig};
+use crate::channel::{AboutCache, AsyncRequester, Channel, Meta};
+use crate::credentials::{authenticate, CredentialProvider};
+use crate::error::*;
+use crate::metadata::{DeviceMetadata, Location};
+use crate::request::Request;
+use futures::channel::oneshot;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::io::{ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How long we wait on a device before giving up
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HttpMetadata {
+    /// Network location of the device (IE: 192.168.0.10:80)
+    pub address: String,
+}
+
+/// A device reachable over http
+pub struct HttpChannel {
+    pub meta: HttpMetadata,
+    credentials: Option<Arc<dyn CredentialProvider>>,
+    tls: Option<Arc<TlsConfig>>,
+    about: AboutCache,
+}
+
+/// Split an address into the value of the Host header and something we can
+/// connect to. The default port is 443 when [tls] else 80. IE: "http://10.0.0.1"
+/// => ("10.0.0.1", "10.0.0.1:80")
+pub fn host(address: &str, tls: bool) -> (&str, String) {
+    let port = match tls {
+        true => 443,
+        false => 80,
+    };
+    let host = address
+        .strip_prefix("https://")
+        .or_else(|| address.strip_prefix("http://"))
+        .unwrap_or(address);
+    let host = host.split('/').next().unwrap_or(host);
+    match host.contains(':') {
+        true => (host, host.to_owned()),
+        false => (host, format!("{}:{}", host, port)),
+    }
+}
+
+/// Write a request to a stream and read back the response. The server is
+/// asked to close the connection so that the response ends at EOF.
+pub fn exchange<S: Read + Write>(
+    stream: &mut S,
+    host: &str,
+    request: &Request,
+) -> Result<Response> {
+    let mut request = request.clone();
+    if !request
+        .headers
+        .iter()
+        .any(|(k, _)| k.eq_ignore_ascii_case("Connection"))
+    {
+        request.headers.push(("Connection".into(), "close".into()));
+    }
+    stream.write_all(&serialize(host, &request)?)?;
+    stream.flush()?;
+    let mut bytes = vec![];
+    // NOTE rustls reports the end of a TLS session as ConnectionAborted, and
+    //      some devices hang up without a close_notify (UnexpectedEof)
+    match stream.read_to_end(&mut bytes) {
+        Ok(_) => parse(&bytes),
+        Err(e) => match e.kind() {
+            ErrorKind::ConnectionAborted => parse(&bytes),
+            ErrorKind::UnexpectedEof if !bytes.is_empty() => parse(&bytes),
+            _ => Err(e.into()),
+        },
+    }
+}
+
+/// Make a blocking request to a device. When [tls] is given the request is
+/// made over https and the device is verified with the config. [keys] are
+/// the serial and address of the device (used to look up pins)
+pub fn send(
+    address: &str,
+    tls: Option<&TlsConfig>,
+    keys: &[&str],
+    request: &Request,
+) -> Result<Vec<u8>> {
+    let (host, addr) = host(address, tls.is_some());
+    let socket = TcpStream::connect(addr)?;
+    socket.set_read_timeout(Some(TIMEOUT))?;
+    socket.set_write_timeout(Some(TIMEOUT))?;
+    let response = match tls {
+        Some(tls) => {
+            exchange(&mut tls.connect(keys, host, socket), host, request)
+        }
+        None => exchange(&mut &socket, host, request),
+    }?;
+    translate_response(&request.path, Some(response.status), response.body)
+}
+
+impl HttpChannel {
+    pub fn new(
+        address: &str,
+        credentials: Option<Arc<dyn CredentialProvider>>,
+        tls: Option<Arc<TlsConfig>>,
+    ) -> Self {
+        let meta = HttpMetadata {
+            address: address.to_owned(),
+        };
+        HttpChannel {
+            meta,
+            credentials,
+            tls,
+            about: AboutCache::default(),
+        }
+    }
+
+    /// Read /ATX/about from the device with serial number [serial] in the
+    /// background. (The channel is usable right away)
+    pub fn open(self, serial: &str) -> Self {
+        self.about.read(self.sender(serial));
+        self
+    }
+
+    /// A blocking request to the device (for a thread of its own)
+    fn sender(
+        &self,
+        serial: &str,
+    ) -> impl FnOnce(Request) -> Result<Vec<u8>> + Send + 'static {
+        let credentials = self.credentials.clone();
+        let address = self.meta.address.clone();
+        let tls = self.tls.clone();
+        let serial = serial.to_owned();
+        move |r| {
+            let keys = [serial.as_str(), address.as_str()];
+            let provider = credentials.as_deref();
+            authenticate(provider, &keys, r, |r| {
+                send(&address, tls.as_deref(), &keys, r)
+            })
+        }
+    }
+}
+
+impl Channel for HttpChannel {}
+impl AsyncRequester for HttpChannel {
+    fn request_bytes<'a>(
+        &'a self,
+        serial: &'a str,
+        r: Request,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>>> + 'a>> {
+        // Sockets are blocking so we do the work on a thread of its own
+        let (tx, rx) = oneshot::channel::<Result<Vec<u8>>>();
+        let send = self.sender(serial);
+        std::thread::spawn(move || tx.send(send(r)));
+        Box::pin(async { rx.await.map_err(|_| IoError::Unknown)? })
+    }
+}
+
+impl Meta for HttpChannel {
+    fn describe(&self, serial: &str) -> DeviceMetadata {
+        let address = self.meta.address.clone();
+        DeviceMetadata::new(serial, Location::Http { address })
+    }
+
+    fn cache(&self) -> &AboutCache {
+        &self.about
+    }
+}
@@ -1 +1,12 @@
-// TODO
+#[cfg(test)]
+mod tests;
+
+mod channel;
+mod request;
+mod response;
+mod tls;
+
+pub use channel::*;
+pub use request::*;
+pub use response::*;
+pub use tls::*;
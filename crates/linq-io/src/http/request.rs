@@ -0,0 +1,152 @@
+use crate::error::*;
+use crate::request::{Auth, Body, Method, Request};
+use std::io::Read;
+
+/// Largest request head we accept when reading a request
+const MAX_HEAD: usize = 0x4000;
+
+/// Largest request body [deserialize] accepts. (Big enough for an
+/// update.json)
+pub const MAX_BODY: usize = 0x4000000;
+
+/// Value of the Authorization header for some credentials
+pub fn authorization(auth: &Auth) -> String {
+    match auth {
+        Auth::Basic { user, password } => {
+            let token = base64::encode(format!("{}:{}", user, password));
+            format!("Basic {}", token)
+        }
+        Auth::Token(token) => format!("Bearer {}", token),
+    }
+}
+
+/// Serialize a request into an HTTP/1.1 request. Headers supplied by the
+/// caller win over the defaults we would otherwise add. Headers (and paths)
+/// that would break out of their line are rejected.
+pub fn serialize(host: &str, r: &Request) -> Result<Vec<u8>> {
+    if r.method == Method::Raw {
+        let e = "raw requests are not supported over http";
+        return Err(IoError::Parser(e.to_string()));
+    }
+    let target = r.path_and_query();
+    if target.contains([' ', '\r', '\n']) {
+        let e = format!("bad request target [{:?}]", target);
+        return Err(IoError::Parser(e));
+    }
+    for (k, v) in r.headers.iter() {
+        let bad_name = k.is_empty() || k.contains([':', '\r', '\n', ' ']);
+        if bad_name || v.contains(['\r', '\n']) {
+            let e = format!("bad header [{:?}: {:?}]", k, v);
+            return Err(IoError::Parser(e));
+        }
+    }
+    let has = |name: &str| {
+        r.headers.iter().any(|(k, _)| k.eq_ignore_ascii_case(name))
+    };
+    let mut head =
+        format!("{} {} HTTP/1.1\r\nHost: {}\r\n", r.method, target, host);
+    if let (Some(auth), false) = (&r.auth, has("Authorization")) {
+        head.push_str(&format!("Authorization: {}\r\n", authorization(auth)));
+    }
+    let kind = match r.body {
+        Body::Empty => None,
+        Body::Text(_) => Some("application/json"),
+        Body::Binary(_) => Some("application/octet-stream"),
+    };
+    if let (Some(kind), false) = (kind, has("Content-Type")) {
+        head.push_str(&format!("Content-Type: {}\r\n", kind));
+    }
+    for (k, v) in r.headers.iter() {
+        head.push_str(&format!("{}: {}\r\n", k, v));
+    }
+    let empty_get = r.body.is_empty() && r.method == Method::Get;
+    if !empty_get && !has("Content-Length") {
+        head.push_str(&format!(
+            "Content-Length: {}\r\n",
+            r.body.as_bytes().len()
+        ));
+    }
+    head.push_str("\r\n");
+    let mut bytes = head.into_bytes();
+    bytes.extend_from_slice(r.body.as_bytes());
+    Ok(bytes)
+}
+
+fn bad<T>(e: &str) -> Result<T> {
+    Err(IoError::Parser(format!("bad http request => {}", e)))
+}
+
+/// Read an HTTP/1.1 request, IE: when serving a client. (The reverse of
+/// [serialize].) The body is read according to Content-Length
+pub fn deserialize<R: Read>(reader: &mut R) -> Result<Request> {
+    deserialize_limited(reader, |_| MAX_BODY)
+}
+
+/// [deserialize] with the largest body decided by [max_body] once the head
+/// is read, IE: to accept large bodies on some routes only
+pub fn deserialize_limited<R, F>(reader: &mut R, max_body: F) -> Result<Request>
+where
+    R: Read,
+    F: FnOnce(&Request) -> usize,
+{
+    let mut bytes = vec![];
+    let mut chunk = [0; 1024];
+    let end = loop {
+        if let Some(end) = bytes.windows(4).position(|x| x == b"\r\n\r\n") {
+            break end;
+        }
+        if bytes.len() > MAX_HEAD {
+            return bad("header too large");
+        }
+        match reader.read(&mut chunk)? {
+            0 => return bad("missing header terminator"),
+            n => bytes.extend_from_slice(&chunk[..n]),
+        }
+    };
+    let head = match std::str::from_utf8(&bytes[..end]) {
+        Ok(head) => head,
+        Err(_) => return bad("header is not utf8"),
+    };
+    let mut lines = head.split("\r\n");
+    let mut line = lines.next().unwrap_or("").split(' ');
+    let mut request = match (line.next(), line.next(), line.next()) {
+        (Some(method), Some(target), Some(_)) => match method.parse() {
+            Ok(Method::Raw) | Err(_) => return bad("method"),
+            Ok(method) => Request::with_target(method, target)?,
+        },
+        _ => return bad("request line"),
+    };
+    for line in lines {
+        let mut kv = line.splitn(2, ':');
+        match (kv.next(), kv.next()) {
+            (Some(k), Some(v)) => request
+                .headers
+                .push((k.trim().to_owned(), v.trim().to_owned())),
+            _ => return bad("header"),
+        }
+    }
+    let length = request
+        .headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("Content-Length"))
+        .map(|(_, v)| v.parse::<usize>());
+    let length = match length {
+        Some(Ok(n)) if n > max_body(&request) => return bad("body too large"),
+        Some(Ok(n)) => n,
+        Some(Err(_)) => return bad("content length"),
+        None => 0,
+    };
+    let mut body = bytes.split_off(end + 4);
+    if body.len() < length {
+        let mut rest = vec![0; length - body.len()];
+        reader.read_exact(&mut rest)?;
+        body.extend(rest);
+    }
+    body.truncate(length);
+    request.body = match String::from_utf8(body) {
+        Ok(s) if s.is_empty() => Body::Empty,
+        Ok(s) => Body::Text(s),
+        Err(e) => Body::Binary(e.into_bytes().into()),
+    };
+    Ok(request)
+}
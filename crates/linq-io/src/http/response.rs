@@ -0,0 +1,135 @@
+use crate::error::*;
+
+/// A parsed HTTP/1.1 response
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: i64,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Find a header (names are case insensitive)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Serialize into an HTTP/1.1 response, IE: when serving a client. (The
+    /// reverse of [parse].) We always close the connection afterwards
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut head =
+            format!("HTTP/1.1 {} {}\r\n", self.status, reason(self.status));
+        for (k, v) in self.headers.iter() {
+            head.push_str(&format!("{}: {}\r\n", k, v));
+        }
+        if self.header("Content-Length").is_none() {
+            head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+        if self.header("Connection").is_none() {
+            head.push_str("Connection: close\r\n");
+        }
+        head.push_str("\r\n");
+        let mut bytes = head.into_bytes();
+        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+}
+
+/// Reason phrase of a status code
+fn reason(status: i64) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        504 => "Gateway Timeout",
+        _ => "",
+    }
+}
+
+fn bad<T>(e: &str) -> Result<T> {
+    Err(IoError::Parser(format!("bad http response => {}", e)))
+}
+
+/// Parse a complete response. (We always ask the server to close the
+/// connection so the caller reads until EOF and hands us everything)
+pub fn parse(bytes: &[u8]) -> Result<Response> {
+    let end = match bytes.windows(4).position(|x| x == b"\r\n\r\n") {
+        Some(end) => end,
+        None => return bad("missing header terminator"),
+    };
+    let head = match std::str::from_utf8(&bytes[..end]) {
+        Ok(head) => head,
+        Err(_) => return bad("header is not utf8"),
+    };
+    let mut lines = head.split("\r\n");
+    let status = match lines.next().unwrap_or("").split(' ').nth(1) {
+        Some(status) => match status.parse::<i64>() {
+            Ok(status) => status,
+            Err(_) => return bad("status"),
+        },
+        None => return bad("status line"),
+    };
+    let mut headers = vec![];
+    for line in lines {
+        let mut kv = line.splitn(2, ':');
+        match (kv.next(), kv.next()) {
+            (Some(k), Some(v)) => {
+                headers.push((k.trim().to_owned(), v.trim().to_owned()))
+            }
+            _ => return bad("header"),
+        }
+    }
+    let mut response = Response {
+        status,
+        headers,
+        body: vec![],
+    };
+    let rest = &bytes[end + 4..];
+    let chunked = matches!(
+        response.header("Transfer-Encoding"),
+        Some(x) if x.eq_ignore_ascii_case("chunked")
+    );
+    let length = response.header("Content-Length").map(|x| x.parse());
+    response.body = match (chunked, length) {
+        (true, _) => dechunk(rest)?,
+        (false, Some(Ok(n))) if n <= rest.len() => rest[..n].to_vec(),
+        (false, Some(_)) => return bad("content length"),
+        (false, None) => rest.to_vec(),
+    };
+    Ok(response)
+}
+
+/// Helper to decode a "Transfer-Encoding: chunked" body
+fn dechunk(mut bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut body = vec![];
+    loop {
+        let line = match bytes.windows(2).position(|x| x == b"\r\n") {
+            Some(line) => line,
+            None => return bad("chunk size"),
+        };
+        let size = std::str::from_utf8(&bytes[..line])
+            .ok()
+            .and_then(|x| x.split(';').next())
+            .and_then(|x| usize::from_str_radix(x.trim(), 16).ok());
+        let size = match size {
+            Some(size) => size,
+            None => return bad("chunk size"),
+        };
+        bytes = &bytes[line + 2..];
+        if size == 0 {
+            return Ok(body);
+        }
+        match bytes.get(..size) {
+            Some(chunk) => body.extend_from_slice(chunk),
+            None => return bad("truncated chunk"),
+        }
+        bytes = bytes.get(size + 2..).unwrap_or(&[]);
+    }
+}
@@ -0,0 +1,118 @@
+use crate::channel::AsyncRequester;
+use crate::credentials::{CredentialProvider, MemoryCredentials};
+use crate::error::*;
+use crate::http::{self, HttpChannel};
+use crate::request::{Auth, Request};
+use futures::executor::block_on;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+/// Serve one canned response per connection. Returns the address of the
+/// server and a handle that yields every request the server received
+fn serve(responses: Vec<&'static str>) -> (String, JoinHandle<Vec<String>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let handle = std::thread::spawn(move || {
+        responses
+            .into_iter()
+            .map(|response| {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = vec![0; 4096];
+                let n = stream.read(&mut request).unwrap();
+                stream.write_all(response.as_bytes()).unwrap();
+                String::from_utf8_lossy(&request[..n]).to_string()
+            })
+            .collect()
+    });
+    (address, handle)
+}
+
+#[test]
+fn test_host() {
+    let host = |address| http::host(address, false);
+    assert_eq!(host("10.0.0.1"), ("10.0.0.1", "10.0.0.1:80".into()));
+    assert_eq!(
+        host("http://10.0.0.1:8080/ATX"),
+        ("10.0.0.1:8080", "10.0.0.1:8080".into())
+    );
+    let host = |address| http::host(address, true);
+    assert_eq!(host("10.0.0.1"), ("10.0.0.1", "10.0.0.1:443".into()));
+    assert_eq!(
+        host("https://10.0.0.1"),
+        ("10.0.0.1", "10.0.0.1:443".into())
+    );
+}
+
+#[test]
+fn test_channel_request() {
+    let (address, server) =
+        serve(vec!["HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}"]);
+    let channel = HttpChannel::new(&address, None, None);
+    let response = block_on(channel.request_raw(&address, Request::get("/a")));
+    assert_eq!(response.unwrap(), "{}");
+    let requests = server.join().unwrap();
+    assert!(requests[0].starts_with("GET /a HTTP/1.1\r\n"));
+    assert!(requests[0].contains("Connection: close\r\n"));
+    assert!(!requests[0].contains("Authorization"));
+}
+
+#[test]
+fn test_channel_api_error() {
+    let (address, server) =
+        serve(vec!["HTTP/1.1 404 Not Found\r\n\r\n{\"error\":404}"]);
+    let channel = HttpChannel::new(&address, None, None);
+    match block_on(channel.request_bytes(&address, Request::get("/a"))) {
+        Err(IoError::ApiError(e)) => {
+            assert_eq!(e.kind, ApiErrorKind::Linq404);
+            assert_eq!(e.path, "/a");
+        }
+        _ => panic!("expected 404"),
+    }
+    server.join().unwrap();
+}
+
+/// Hands out "old" until asked to refresh, then "new"
+struct Renewed;
+
+impl CredentialProvider for Renewed {
+    fn credentials(&self, _key: &str) -> Option<Auth> {
+        Some(Auth::Token("old".into()))
+    }
+
+    fn refresh(&self, _key: &str) -> Option<Auth> {
+        Some(Auth::Token("new".into()))
+    }
+}
+
+#[test]
+fn test_channel_reauthenticates_after_403() {
+    let (address, server) = serve(vec![
+        "HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n",
+        "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok",
+    ]);
+    let channel = HttpChannel::new(&address, Some(Arc::new(Renewed)), None);
+    let response = block_on(channel.request_raw("SERIAL", Request::get("/a")));
+    assert_eq!(response.unwrap(), "ok");
+    let requests = server.join().unwrap();
+    assert_eq!(requests.len(), 2);
+    assert!(requests[0].contains("Authorization: Bearer old\r\n"));
+    assert!(requests[1].contains("Authorization: Bearer new\r\n"));
+}
+
+#[test]
+fn test_channel_does_not_resend_rejected_credentials() {
+    let (address, server) =
+        serve(vec!["HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n"]);
+    let credentials = Arc::new(MemoryCredentials::new());
+    credentials.insert(&address, Auth::Token("abc".into()));
+    let channel = HttpChannel::new(&address, Some(credentials), None);
+    let response = block_on(channel.request_raw("SERIAL", Request::get("/a")));
+    match response {
+        Err(IoError::ApiError(e)) => assert_eq!(e.kind, ApiErrorKind::Linq403),
+        _ => panic!("expected 403"),
+    }
+    let requests = server.join().unwrap();
+    assert!(requests[0].contains("Authorization: Bearer abc\r\n"));
+}
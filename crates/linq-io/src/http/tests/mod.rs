@@ -0,0 +1,4 @@
+mod channel_test;
+mod request_test;
+mod response_test;
+mod tls_test;
@@ -0,0 +1,160 @@
+use crate::error::*;
+use crate::http;
+use crate::request::{Auth, Method, Request};
+
+fn to_string(bytes: Vec<u8>) -> String {
+    String::from_utf8(bytes).unwrap()
+}
+
+#[test]
+fn test_serialize_get() {
+    let request = Request::builder(Method::Get, "/ATX/about")
+        .query("a", "b c")
+        .build();
+    let bytes = http::serialize("192.168.0.1", &request).unwrap();
+    assert_eq!(
+        to_string(bytes),
+        "GET /ATX/about?a=b%20c HTTP/1.1\r\nHost: 192.168.0.1\r\n\r\n"
+    );
+}
+
+#[test]
+fn test_serialize_post() {
+    let request = Request::post_raw("/ATX/exe/save", "{\"save\":1}");
+    let bytes = http::serialize("localhost", &request).unwrap();
+    assert_eq!(
+        to_string(bytes),
+        "POST /ATX/exe/save HTTP/1.1\r\n\
+         Host: localhost\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: 10\r\n\
+         \r\n\
+         {\"save\":1}"
+    );
+}
+
+#[test]
+fn test_serialize_headers_and_auth() {
+    let request = Request::builder(Method::Put, "/ATX/about")
+        .auth(Auth::Basic {
+            user: "admin".into(),
+            password: "admin".into(),
+        })
+        .header("Content-Type", "text/plain")
+        .text("foo")
+        .build();
+    let s = to_string(http::serialize("localhost", &request).unwrap());
+    assert!(s.starts_with("PUT /ATX/about HTTP/1.1\r\n"));
+    assert!(s.contains("Authorization: Basic YWRtaW46YWRtaW4=\r\n"));
+    assert!(s.contains("Content-Type: text/plain\r\n"));
+    assert!(!s.contains("application/json"));
+    assert!(s.ends_with("Content-Length: 3\r\n\r\nfoo"));
+}
+
+#[test]
+fn test_serialize_rejects_header_injection() {
+    let request = Request::builder(Method::Get, "/ATX/about")
+        .header("X-Foo", "bar\r\nX-Evil: 1")
+        .build();
+    assert!(http::serialize("localhost", &request).is_err());
+    let request = Request::builder(Method::Get, "/ATX/about")
+        .header("X-Foo\r\nX-Evil", "1")
+        .build();
+    assert!(http::serialize("localhost", &request).is_err());
+    let request = Request::get("/ATX/about HTTP/1.1\r\nX-Evil: 1\r\n");
+    assert!(http::serialize("localhost", &request).is_err());
+}
+
+#[test]
+fn test_serialize_keeps_content_length() {
+    let request = Request::builder(Method::Post, "/ATX/exe/save")
+        .header("Content-Length", "10")
+        .text("{\"save\":1}")
+        .build();
+    let s = to_string(http::serialize("localhost", &request).unwrap());
+    assert_eq!(s.matches("Content-Length").count(), 1);
+    assert!(s.ends_with("Content-Length: 10\r\n\r\n{\"save\":1}"));
+}
+
+#[test]
+fn test_serialize_token() {
+    let request = Request::builder(Method::Delete, "/ATX/users/foo")
+        .auth(Auth::Token("abc".into()))
+        .build();
+    let s = to_string(http::serialize("localhost", &request).unwrap());
+    assert!(s.contains("Authorization: Bearer abc\r\n"));
+    assert!(s.ends_with("Content-Length: 0\r\n\r\n"));
+}
+
+#[test]
+fn test_serialize_raw() {
+    let request = Request::raw(&b"foo"[..]);
+    assert!(http::serialize("localhost", &request).is_err());
+}
+
+#[test]
+fn test_deserialize() {
+    let bytes = b"POST /ATX/about?a=b%20c HTTP/1.1\r\n\
+                  Host: localhost\r\n\
+                  content-length: 5\r\n\
+                  \r\n\
+                  {\"a\":1}";
+    let request = http::deserialize(&mut &bytes[..]).unwrap();
+    assert_eq!(request.method, Method::Post);
+    assert_eq!(request.path, "/ATX/about");
+    assert_eq!(request.query, vec![("a".to_owned(), "b c".to_owned())]);
+    assert_eq!(
+        request.headers[0],
+        ("Host".to_owned(), "localhost".to_owned())
+    );
+    assert_eq!(request.body.as_bytes(), b"{\"a\":");
+}
+
+#[test]
+fn test_deserialize_round_trip() {
+    let request = Request::builder(Method::Put, "/ATX/about")
+        .query("x", "1")
+        .text("{\"siteId\":\"a\"}")
+        .build();
+    let bytes = http::serialize("localhost", &request).unwrap();
+    let parsed = http::deserialize(&mut &bytes[..]).unwrap();
+    assert_eq!(parsed.path_and_query(), request.path_and_query());
+    assert_eq!(parsed.body, request.body);
+    let get = http::serialize("localhost", &Request::get("/ATX")).unwrap();
+    let parsed = http::deserialize(&mut &get[..]).unwrap();
+    assert!(parsed.body.is_empty());
+}
+
+#[test]
+fn test_deserialize_bad() {
+    let bad: &[&[u8]] = &[
+        b"GET /ATX HTTP/1.1\r\n",
+        b"FOO /ATX HTTP/1.1\r\n\r\n",
+        b"GET /ATX\r\n\r\n",
+        b"POST /ATX HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort",
+    ];
+    for bytes in bad {
+        assert!(http::deserialize(&mut &bytes[..]).is_err());
+    }
+    let bytes = b"POST /ATX HTTP/1.1\r\nContent-Length: 99999999999\r\n\r\n";
+    match http::deserialize(&mut &bytes[..]) {
+        Err(IoError::Parser(e)) => assert!(e.ends_with("body too large")),
+        _ => panic!("expected body too large"),
+    }
+}
+
+#[test]
+fn test_deserialize_limited() {
+    let bytes = b"POST /big HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello";
+    let limit = |r: &Request| match r.path.as_str() {
+        "/big" => 5,
+        _ => 4,
+    };
+    let request = http::deserialize_limited(&mut &bytes[..], limit).unwrap();
+    assert_eq!(request.body.as_bytes(), b"hello");
+    let bytes = b"POST /small HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello";
+    match http::deserialize_limited(&mut &bytes[..], limit) {
+        Err(IoError::Parser(e)) => assert!(e.ends_with("body too large")),
+        _ => panic!("expected body too large"),
+    }
+}
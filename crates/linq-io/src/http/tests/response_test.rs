@@ -0,0 +1,66 @@
+use crate::http;
+
+#[test]
+fn test_parse_content_length() {
+    let bytes = b"HTTP/1.1 200 OK\r\n\
+                  Content-Type: application/json\r\n\
+                  content-length: 2\r\n\
+                  \r\n\
+                  {}trailing";
+    let response = http::parse(bytes).unwrap();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.header("Content-Type"), Some("application/json"));
+    assert_eq!(response.header("CONTENT-LENGTH"), Some("2"));
+    assert_eq!(response.body, b"{}");
+}
+
+#[test]
+fn test_parse_until_eof() {
+    let response = http::parse(b"HTTP/1.0 404 Not Found\r\n\r\nnope").unwrap();
+    assert_eq!(response.status, 404);
+    assert_eq!(response.body, b"nope");
+}
+
+#[test]
+fn test_parse_chunked() {
+    let bytes = b"HTTP/1.1 200 OK\r\n\
+                  Transfer-Encoding: chunked\r\n\
+                  \r\n\
+                  4\r\n{\"a\"\r\n\
+                  3;ext=1\r\n:1}\r\n\
+                  0\r\n\r\n";
+    let response = http::parse(bytes).unwrap();
+    assert_eq!(response.body, b"{\"a\":1}");
+}
+
+#[test]
+fn test_parse_errors() {
+    assert!(http::parse(b"HTTP/1.1 200 OK\r\n").is_err());
+    assert!(http::parse(b"HTTP/1.1 abc OK\r\n\r\n").is_err());
+    assert!(http::parse(b"HTTP/1.1 200 OK\r\nbad\r\n\r\n").is_err());
+    let short = b"HTTP/1.1 200 OK\r\nContent-Length: 9\r\n\r\n{}";
+    assert!(http::parse(short).is_err());
+    let chunk = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nz\r\n";
+    assert!(http::parse(chunk).is_err());
+}
+
+#[test]
+fn test_to_bytes() {
+    let response = http::Response {
+        status: 404,
+        headers: vec![("Content-Type".into(), "application/json".into())],
+        body: b"{}".to_vec(),
+    };
+    let bytes = response.to_bytes();
+    assert_eq!(
+        String::from_utf8(bytes.clone()).unwrap(),
+        "HTTP/1.1 404 Not Found\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: 2\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {}"
+    );
+    let parsed = http::parse(&bytes).unwrap();
+    assert_eq!((parsed.status, parsed.body), (404, response.body));
+}
@@ -0,0 +1,159 @@
+use crate::error::*;
+use crate::http::{self, Fingerprint, TlsConfig};
+use crate::io::Io;
+use crate::request::Request;
+use crate::usb::VirtualBus;
+use futures::executor::block_on;
+use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa};
+use rustls::{NoClientAuth, ServerConfig, ServerSession, Session, StreamOwned};
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+/// A self signed CA and a certificate for "localhost" signed by the CA
+struct Certs {
+    ca_pem: String,
+    der: Vec<u8>,
+    key: Vec<u8>,
+}
+
+fn certs() -> Certs {
+    let mut params = CertificateParams::new(vec![]);
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = Certificate::from_params(params).unwrap();
+    let leaf = Certificate::from_params(CertificateParams::new(vec![
+        "localhost".to_string(),
+    ]))
+    .unwrap();
+    Certs {
+        ca_pem: ca.serialize_pem().unwrap(),
+        der: leaf.serialize_der_with_signer(&ca).unwrap(),
+        key: leaf.serialize_private_key_der(),
+    }
+}
+
+/// Serve [n] https connections on localhost. Failed handshakes are expected
+/// (that is what some of these tests are about) so they are ignored
+fn serve(certs: &Certs, n: usize) -> (u16, JoinHandle<()>) {
+    serve_on(TcpListener::bind("127.0.0.1:0").unwrap(), certs, n)
+}
+
+/// Same as serve except on a listener of the callers choosing
+fn serve_on(
+    listener: TcpListener,
+    certs: &Certs,
+    n: usize,
+) -> (u16, JoinHandle<()>) {
+    let mut config = ServerConfig::new(NoClientAuth::new());
+    let chain = vec![rustls::Certificate(certs.der.clone())];
+    let key = rustls::PrivateKey(certs.key.clone());
+    config.set_single_cert(chain, key).unwrap();
+    let config = Arc::new(config);
+    let port = listener.local_addr().unwrap().port();
+    let handle = std::thread::spawn(move || {
+        for _ in 0..n {
+            let (socket, _) = listener.accept().unwrap();
+            let session = ServerSession::new(&config);
+            let mut stream = StreamOwned::new(session, socket);
+            let mut request = vec![0; 4096];
+            if stream.read(&mut request).is_ok() {
+                let response = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+                stream.write_all(response.as_bytes()).unwrap();
+                stream.sess.send_close_notify();
+                stream.flush().unwrap();
+            }
+        }
+    });
+    (port, handle)
+}
+
+fn get(tls: &TlsConfig, address: &str) -> Result<Vec<u8>> {
+    let keys = ["SERIAL", address];
+    http::send(address, Some(tls), &keys, &Request::get("/ATX/about"))
+}
+
+#[test]
+fn test_fingerprint() {
+    let fingerprint = Fingerprint::of(b"foo");
+    let s = fingerprint.to_string();
+    assert_eq!(s.len(), 32 * 3 - 1);
+    assert_eq!(s.parse::<Fingerprint>().unwrap(), fingerprint);
+    let lower = s.replace(":", "").to_lowercase();
+    assert_eq!(lower.parse::<Fingerprint>().unwrap(), fingerprint);
+    assert!("AB:CD".parse::<Fingerprint>().is_err());
+    assert!("zz".repeat(32).parse::<Fingerprint>().is_err());
+}
+
+#[test]
+fn test_bad_ca_bundle() {
+    assert!(TlsConfig::new().ca_pem(b"not a certificate").is_err());
+    assert!(TlsConfig::new().ca_bundle("/does/not/exist.pem").is_err());
+}
+
+#[test]
+fn test_pinned() {
+    let certs = certs();
+    let (port, server) = serve(&certs, 2);
+    let address = format!("127.0.0.1:{}", port);
+
+    // Pinned by serial
+    let tls = TlsConfig::new().pin("SERIAL", Fingerprint::of(&certs.der));
+    assert_eq!(get(&tls, &address).unwrap(), b"ok");
+
+    // Pin does not match
+    let tls = TlsConfig::new()
+        .insecure(true)
+        .pin(&address, Fingerprint::of(b"other"));
+    assert!(get(&tls, &address).is_err());
+    server.join().unwrap();
+}
+
+#[test]
+fn test_ca_bundle() {
+    let certs = certs();
+    let (port, server) = serve(&certs, 3);
+    let address = format!("localhost:{}", port);
+
+    // Not signed by a well known root
+    assert!(get(&TlsConfig::new(), &address).is_err());
+
+    // Signed by our CA
+    let tls = TlsConfig::new().ca_pem(certs.ca_pem.as_bytes()).unwrap();
+    assert_eq!(get(&tls, &address).unwrap(), b"ok");
+
+    // Name does not match the certificate
+    let address = format!("127.0.0.1:{}", port);
+    assert!(get(&tls, &address).is_err());
+    server.join().unwrap();
+}
+
+#[test]
+fn test_insecure() {
+    let certs = certs();
+    let (port, server) = serve(&certs, 1);
+    let address = format!("https://127.0.0.1:{}", port);
+    let tls = TlsConfig::new().insecure(true);
+    assert_eq!(get(&tls, &address).unwrap(), b"ok");
+    server.join().unwrap();
+}
+
+#[test]
+fn test_connect_https_default_port() {
+    // A bare address is https on port 443. (Binding 443 needs privileges, so
+    // there is nothing to test without them)
+    let listener = match TcpListener::bind("127.0.0.1:443") {
+        Ok(listener) => listener,
+        Err(_) => return,
+    };
+    let certs = certs();
+    // (One connection for the request and one for /ATX/about on open)
+    let (_, server) = serve_on(listener, &certs, 2);
+    let mut io = Io::with_backend(VirtualBus::new());
+    io.set_tls(TlsConfig::new().pin("127.0.0.1", Fingerprint::of(&certs.der)));
+    io.connect_https("127.0.0.1");
+    let response = block_on(io.request("127.0.0.1", Request::get("/ATX")));
+    io.close().unwrap();
+    assert_eq!(response.unwrap(), "ok");
+    server.join().unwrap();
+}
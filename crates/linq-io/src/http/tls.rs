@@ -0,0 +1,175 @@
+use crate::error::*;
+use rustls::{
+    Certificate, ClientConfig, ClientSession, RootCertStore,
+    ServerCertVerified, ServerCertVerifier, StreamOwned, TLSError,
+    WebPKIVerifier,
+};
+use std::collections::HashMap;
+use std::net::TcpStream;
+use std::path::Path;
+use std::sync::Arc;
+use webpki::DNSNameRef;
+
+/// Name we give rustls when the host is not a dns name (IE: an ip address).
+/// NOTE these devices can only be verified by pinning or insecure mode
+const NO_NAME: &str = "linq.invalid";
+
+/// SHA-256 of a certificate (DER)
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Fingerprint(pub [u8; 32]);
+
+impl Fingerprint {
+    /// Fingerprint of a DER encoded certificate
+    pub fn of(der: &[u8]) -> Self {
+        let digest = ring::digest::digest(&ring::digest::SHA256, der);
+        let mut bytes = [0; 32];
+        bytes.copy_from_slice(digest.as_ref());
+        Fingerprint(bytes)
+    }
+}
+
+impl std::fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let hex: Vec<String> =
+            self.0.iter().map(|x| format!("{:02X}", x)).collect();
+        write!(f, "{}", hex.join(":"))
+    }
+}
+
+/// Parse hex with or without colons IE: "AB:CD:..." or "abcd..."
+impl std::str::FromStr for Fingerprint {
+    type Err = IoError;
+    fn from_str(s: &str) -> Result<Self> {
+        let hex: String = s.chars().filter(|c| *c != ':').collect();
+        let bad = || IoError::Security(format!("bad fingerprint [{}]", s));
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(bad());
+        }
+        let mut bytes = [0; 32];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)
+                .map_err(|_| bad())?;
+        }
+        Ok(Fingerprint(bytes))
+    }
+}
+
+/// How we verify devices that speak https. Devices are verified by...
+///
+/// 1. A pinned fingerprint (looked up by serial or address) if there is one.
+///    Pinning accepts self signed certificates.
+/// 2. Nothing at all when insecure (For bench use only!)
+/// 3. The CA bundle (or the well known web roots when no bundle is given)
+#[derive(Clone)]
+pub struct TlsConfig {
+    roots: RootCertStore,
+    pins: HashMap<String, Fingerprint>,
+    insecure: bool,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        let mut roots = RootCertStore::empty();
+        roots.add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+        TlsConfig {
+            roots,
+            pins: HashMap::new(),
+            insecure: false,
+        }
+    }
+}
+
+impl TlsConfig {
+    pub fn new() -> Self {
+        TlsConfig::default()
+    }
+
+    /// Trust only the certificates in a PEM file (in place of the web roots)
+    pub fn ca_bundle<P: AsRef<Path>>(self, path: P) -> Result<Self> {
+        self.ca_pem(&std::fs::read(path)?)
+    }
+
+    /// Trust only the certificates in a PEM string (in place of the web
+    /// roots)
+    pub fn ca_pem(mut self, pem: &[u8]) -> Result<Self> {
+        let mut roots = RootCertStore::empty();
+        match roots.add_pem_file(&mut &pem[..]) {
+            Ok((n, 0)) if n > 0 => {
+                self.roots = roots;
+                Ok(self)
+            }
+            _ => Err(IoError::Security("bad ca bundle".to_string())),
+        }
+    }
+
+    /// Trust a device (by serial or address) only when its certificate has
+    /// this fingerprint
+    pub fn pin(mut self, key: &str, fingerprint: Fingerprint) -> Self {
+        self.pins.insert(key.to_owned(), fingerprint);
+        self
+    }
+
+    /// Skip verification of devices that are not pinned. (Bench use only!)
+    pub fn insecure(mut self, insecure: bool) -> Self {
+        self.insecure = insecure;
+        self
+    }
+
+    /// Build a rustls config for a device (found by serial or address)
+    pub fn client_config(&self, keys: &[&str]) -> ClientConfig {
+        let pin = keys.iter().find_map(|key| self.pins.get(*key)).cloned();
+        let mut config = ClientConfig::new();
+        config.root_store = self.roots.clone();
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(Verifier {
+                pin,
+                insecure: self.insecure,
+            }));
+        config
+    }
+
+    /// Start a TLS session over a connected socket
+    pub fn connect(
+        &self,
+        keys: &[&str],
+        host: &str,
+        socket: TcpStream,
+    ) -> StreamOwned<ClientSession, TcpStream> {
+        let name = host.rsplitn(2, ':').last().unwrap_or(host);
+        let name = DNSNameRef::try_from_ascii_str(name)
+            .or_else(|_| DNSNameRef::try_from_ascii_str(NO_NAME))
+            .unwrap();
+        let config = Arc::new(self.client_config(keys));
+        StreamOwned::new(ClientSession::new(&config, name), socket)
+    }
+}
+
+struct Verifier {
+    pin: Option<Fingerprint>,
+    insecure: bool,
+}
+
+impl ServerCertVerifier for Verifier {
+    fn verify_server_cert(
+        &self,
+        roots: &RootCertStore,
+        presented: &[Certificate],
+        name: DNSNameRef,
+        ocsp: &[u8],
+    ) -> std::result::Result<ServerCertVerified, TLSError> {
+        let presented_pin = presented.first().map(|x| Fingerprint::of(&x.0));
+        match self.pin {
+            Some(pin) if presented_pin == Some(pin) => {
+                Ok(ServerCertVerified::assertion())
+            }
+            Some(pin) => Err(TLSError::General(format!(
+                "certificate does not match pin {}",
+                pin
+            ))),
+            None if self.insecure => Ok(ServerCertVerified::assertion()),
+            None => WebPKIVerifier::new()
+                .verify_server_cert(roots, presented, name, ocsp),
+        }
+    }
+}
@@ -1,14 +1,22 @@
+use super::http::{HttpChannel, TlsConfig};
 use super::request::*;
 use super::update::*;
+use super::usb::capture::Recorder;
 use super::usb::usb::Usb;
-use super::usb::{UsbChannel, UsbMetadata};
+use super::usb::{Backend, Binding, Summary, UsbChannel, UsbMetadata};
+use super::usb::{DriverFactory, Product, ProductRegistry};
+use super::zmtp::{CurveConfig, ZmtpChannel};
+use crate::capabilities::Capabilities;
 use crate::channel::Channel;
+use crate::credentials::CredentialProvider;
 use crate::error::{IoError, Result as IoResult};
+use crate::metadata::DeviceMetadata;
 use futures::future::LocalBoxFuture;
 use futures::prelude::*;
 use futures::stream;
 use futures::stream::LocalBoxStream;
 use futures::Stream;
+use linq_db::k64::{About, AboutResponse, Update};
 use linq_util::gen_log_helpers;
 use std::collections::HashMap;
 use std::convert::TryFrom;
@@ -27,24 +35,109 @@ pub struct Io {
     usb: Arc<Usb>,
     /// Map of all connected devices
     channels: HashMap<String, Box<dyn Channel>>,
+    /// Credentials for devices that require authentication
+    credentials: Option<Arc<dyn CredentialProvider>>,
+    /// How we verify devices over https
+    tls: Arc<TlsConfig>,
+    /// Keys for encrypted zmtp
+    curve: Arc<CurveConfig>,
 }
 
 impl Io {
     /// Create a new Io object to manage communication channels.
     pub fn new() -> Self {
+        Io::with_usb(Usb::new())
+    }
+
+    /// Same as new except usb devices are found on some other [backend].
+    /// IE: Io::with_backend(VirtualBus::new()) to talk to simulated devices
+    pub fn with_backend<B>(backend: B) -> Self
+    where
+        B: Backend + Send + 'static,
+    {
+        Io::with_usb(Usb::with_backend(move || Box::new(backend)))
+    }
+
+    /// Same as new except every usb frame is recorded into a capture file at
+    /// [path]. (See capture::Replay to play the file back)
+    pub fn record(path: &str) -> IoResult<Self> {
+        let file = File::create(path)?;
+        Ok(Io::with_usb(Usb::with_backend(move || {
+            Box::new(Recorder::new(Binding::new()).file(file))
+        })))
+    }
+
+    fn with_usb(usb: Usb) -> Self {
         Io {
-            usb: Arc::new(Usb::new()),
+            usb: Arc::new(usb),
             channels: HashMap::new(),
+            credentials: None,
+            tls: Arc::new(TlsConfig::default()),
+            curve: Arc::new(CurveConfig::default()),
         }
     }
 
-    /// Scan USB port, adding each supported product into channel
+    /// Set where remote transports find credentials. (Only applies to
+    /// channels connected after this call)
+    pub fn set_credentials(&mut self, provider: Arc<dyn CredentialProvider>) {
+        self.credentials = Some(provider);
+    }
+
+    /// Set how devices are verified over https. (Only applies to channels
+    /// connected after this call)
+    pub fn set_tls(&mut self, tls: TlsConfig) {
+        self.tls = Arc::new(tls);
+    }
+
+    /// Set the keys used for encrypted zmtp. (Only applies to channels
+    /// connected after this call)
+    pub fn set_curve(&mut self, curve: CurveConfig) {
+        self.curve = Arc::new(curve);
+    }
+
+    /// Add a device reachable over http. Requests are made to the device using
+    /// the [address] in place of a serial number. (/ATX/about is read in the
+    /// background, see about)
+    pub fn connect_http(&mut self, address: &str) {
+        let credentials = self.credentials.clone();
+        let ch = HttpChannel::new(address, credentials, None).open(address);
+        self.channels.insert(address.to_owned(), Box::new(ch));
+    }
+
+    /// Same as connect_http except over https
+    pub fn connect_https(&mut self, address: &str) {
+        let (credentials, tls) = (self.credentials.clone(), self.tls.clone());
+        let ch = HttpChannel::new(address, credentials, Some(tls));
+        self.channels
+            .insert(address.to_owned(), Box::new(ch.open(address)));
+    }
+
+    /// Add a device reachable over zmtp at [address]. (/ATX/about is read in
+    /// the background, see about)
+    pub fn connect_zmtp(&mut self, serial: &str, address: &str) {
+        let credentials = self.credentials.clone();
+        let ch = ZmtpChannel::new(address, credentials, None).open(serial);
+        self.channels.insert(serial.to_owned(), Box::new(ch));
+    }
+
+    /// Same as connect_zmtp except encrypted with CURVE
+    pub fn connect_zmtps(&mut self, serial: &str, address: &str) {
+        let credentials = self.credentials.clone();
+        let curve = Some(self.curve.clone());
+        let ch = ZmtpChannel::new(address, credentials, curve).open(serial);
+        self.channels.insert(serial.to_owned(), Box::new(ch));
+    }
+
+    /// Scan USB port, adding each supported product into channel. (Devices
+    /// that have been unplugged since the last scan are forgotten)
     pub fn scan<'a>(
         &'a mut self,
     ) -> Pin<Box<dyn Future<Output = IoResult<Vec<UsbMetadata>>> + 'a>> {
         Box::pin(async move {
             let mut v: Vec<UsbMetadata> = vec![];
-            self.usb.scan().await?.into_iter().for_each(|x| {
+            let found = self.usb.scan().await?;
+            self.channels.retain(|k, x| x.meta(k).transport() != "usb");
+            found.into_iter().for_each(|x| {
                 let serial = x.serial.clone();
                 let ch = Box::new(UsbChannel::new(Arc::clone(&self.usb), x));
                 v.push(ch.meta.clone());
@@ -54,6 +147,38 @@ impl Io {
         })
     }
 
+    /// Describe the devices on the usb bus without opening them. (Unlike scan
+    /// no device is spoken to, so this is cheap enough to poll)
+    pub async fn summary(&self) -> IoResult<Vec<Summary>> {
+        self.usb.summary().await
+    }
+
+    /// Look for [product] during future scans. (The K64 and M5 are always
+    /// registered)
+    pub async fn register(&self, product: Product) -> IoResult<()> {
+        self.usb.register(product).await
+    }
+
+    /// Register every product in a JSON config file IE:
+    /// [{"vid":"0x0461","pid":"0x0020","transport":"hid","driver":"k64"}]
+    pub async fn register_file(&self, path: &str) -> IoResult<()> {
+        let registry = ProductRegistry::empty().load(path)?;
+        for product in registry.products() {
+            self.register(product.clone()).await?;
+        }
+        Ok(())
+    }
+
+    /// Speak to products registered with driver [name] using drivers made by
+    /// [factory]. (The "k64" and "m5" drivers are always registered)
+    pub async fn register_driver(
+        &self,
+        name: &str,
+        factory: DriverFactory,
+    ) -> IoResult<()> {
+        self.usb.register_driver(name, factory).await
+    }
+
     /// Print out some version information
     pub fn version<'a>() -> &'static str {
         Usb::version()
@@ -71,18 +196,46 @@ impl Io {
             .map_err(|_| IoError::Kernel("failed to join thread".into()))
     }
 
-    /// Get a box of varius metadata
-    pub fn meta(&self) -> IoResult<Vec<UsbMetadata>> {
+    /// Describe every device we have a channel to. (Sorted by serial number)
+    pub fn meta(&self) -> Vec<DeviceMetadata> {
+        let mut meta: Vec<DeviceMetadata> =
+            self.channels.iter().map(|(k, x)| x.meta(k)).collect();
+        meta.sort_by(|a, b| a.serial.cmp(&b.serial));
+        meta
+    }
+
+    /// What the device with serial number [serial] can do
+    pub fn capabilities(&self, serial: &str) -> IoResult<Capabilities> {
+        self.channels
+            .get(serial)
+            .map(|x| x.meta(serial).capabilities)
+            .ok_or_else(|| IoError::DeviceNotFound(serial.to_owned()))
+    }
+
+    /// What the device with serial number [serial] reported at /ATX/about
+    /// when its channel was opened (or last refreshed). Does not talk to the
+    /// device. (Network devices are read in the background so this is None
+    /// until the device answers)
+    pub fn about(&self, serial: &str) -> IoResult<Option<About>> {
         self.channels
-            .iter()
-            .map(|x| {
-                // TODO instead of returning only UsbMeta, figure best way to
-                //      distinguish different meta types
-                let meta = serde_json::from_str::<UsbMetadata>(&(*x.1).meta())
-                    .map_err(|x| IoError::Parser(x.to_string()));
-                meta
-            })
-            .collect()
+            .get(serial)
+            .map(|x| x.about())
+            .ok_or_else(|| IoError::DeviceNotFound(serial.to_owned()))
+    }
+
+    /// Read /ATX/about from the device again and cache it on the channel
+    pub async fn refresh(&self, serial: &str) -> IoResult<About> {
+        let ch = self
+            .channels
+            .get(serial)
+            .ok_or_else(|| IoError::DeviceNotFound(serial.to_owned()))?;
+        let response =
+            ch.request_raw(serial, Request::get("/ATX/about")).await?;
+        let about = serde_json::from_str::<AboutResponse>(&response)
+            .map_err(|e| IoError::Parser(e.to_string()))?
+            .about;
+        ch.set_about(about.clone());
+        Ok(about)
     }
 
     /// Update a device from a file on the fs
@@ -96,45 +249,35 @@ impl Io {
         Ok(self.update(serial, update, image))
     }
 
-    /// Send an update to device
+    /// Send an update to device. Reports (chunks sent, total) after each
+    /// chunk. (Usb drivers see every chunk, see UsbDriver::update)
     pub fn update<'a>(
         &'a self,
         sid: &'a str,
         pack: DashboardUpdatePackets,
         image: u8, // TODO deprecate need to move updates to channel trait
-    ) -> impl Stream<Item = IoResult<(usize, usize)>> + 'a {
-        let image = if image == 0 { pack.0 } else { pack.1 };
-        let s0 = self.requests_stream(sid, image, move |_, n, t| (n, t));
-        stream::iter(vec![s0]).flatten()
-    }
-
-    /// Convert an array of requests into a stream
-    /// TODO see if we can implement stream trait for Requests instead...
-    fn requests_stream<'a, F, T>(
-        &'a self,
-        serial: &'a str,
-        requests: Vec<Request>,
-        mut mapper: F,
-    ) -> LocalBoxStream<'a, IoResult<T>>
-    where
-        F: FnMut(String, usize, usize) -> T + 'a + Copy,
-    {
-        let total = requests.len();
-        stream::unfold(requests, move |mut requests| async move {
-            match requests.pop() {
-                Some(r) => self.request(serial, r).await.map_or_else(
-                    |e| Some((Err(e), vec![])),
-                    |s| {
-                        let n = total - requests.len();
-                        Some((Ok(mapper(s, n, total)), requests))
-                    },
-                ),
-                _ => None,
+    ) -> LocalBoxStream<'a, IoResult<(usize, usize)>> {
+        let chunks = if image == 0 { pack.0 } else { pack.1 };
+        let total = chunks.len();
+        stream::unfold(chunks, move |mut chunks| async move {
+            let chunk = chunks.pop()?;
+            match self.update_chunk(sid, &chunk).await {
+                Ok(()) => Some((Ok((total - chunks.len(), total)), chunks)),
+                Err(e) => Some((Err(e), vec![])),
             }
         })
         .boxed_local()
     }
 
+    /// Send one chunk of an update to a device with serial number [serial]
+    async fn update_chunk(&self, serial: &str, chunk: &Update) -> IoResult<()> {
+        info!("[{}] update offset {}", serial, chunk.offset);
+        match self.channels.get(serial) {
+            Some(ch) => ch.update(serial, chunk).await,
+            None => Err(IoError::DeviceNotFound(serial.to_owned())),
+        }
+    }
+
     /// Send a get request
     pub async fn get(&self, serial: &str, path: &str) -> IoResult<String> {
         self.request(serial, Request::get(path))
@@ -155,4 +298,19 @@ impl Io {
             .ok_or(IoError::DeviceNotFound(serial.to_owned()));
         Box::pin(async move { ch?.request_raw(serial, request).await })
     }
+
+    /// Same as request except the response is returned as raw bytes. (Useful
+    /// when the device responds with binary data)
+    pub fn request_bytes<'a>(
+        &'a self,
+        serial: &'a str,
+        request: Request,
+    ) -> Pin<Box<dyn Future<Output = IoResult<Vec<u8>>> + 'a>> {
+        info!("{}", request);
+        let ch = self
+            .channels
+            .get(serial)
+            .ok_or(IoError::DeviceNotFound(serial.to_owned()));
+        Box::pin(async move { ch?.request_bytes(serial, request).await })
+    }
 }
@@ -1,5 +1,10 @@
+#[cfg(test)]
+mod tests;
+
+mod capabilities;
 mod channel;
-mod http;
+mod credentials;
+mod metadata;
 mod request;
 mod response;
 mod update;
@@ -10,6 +15,18 @@ mod zmtp;
 extern crate linq_util;
 
 pub mod error;
+pub mod http;
 pub mod io;
-pub use request::Request;
-pub use usb::UsbMetadata;
+pub use capabilities::Capabilities;
+pub use credentials::{
+    CredentialProvider, EnvCredentials, FileCredentials, MemoryCredentials,
+};
+pub use http::{Fingerprint, TlsConfig};
+pub use metadata::{DeviceMetadata, Location};
+pub use request::{Auth, Body, Encoding, Method, Request, RequestBuilder};
+pub use update::DashboardUpdatePackets;
+pub use usb::{capture, k64};
+pub use usb::{Backend, Fault, Product, ProductRegistry, Simulator, Summary};
+pub use usb::{DriverFactory, UsbDriver, K64, M5};
+pub use usb::{Transport, UsbMetadata, VirtualBus};
+pub use zmtp::CurveConfig;
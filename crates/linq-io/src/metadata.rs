@@ -0,0 +1,84 @@
+use crate::capabilities::Capabilities;
+use linq_db::k64::About;
+use serde::{Deserialize, Serialize};
+
+/// Where a device is and how we reach it
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "transport", rename_all = "lowercase")]
+pub enum Location {
+    Usb {
+        vid: u32,
+        pid: u32,
+        /// Usb serial number. (May differ from the serial the device reports)
+        sid: String,
+    },
+    Http {
+        /// Network location of the device (IE: 192.168.0.10:80)
+        address: String,
+    },
+    Zmtp {
+        /// Endpoint we connect to (IE: tcp://192.168.0.10:33455)
+        endpoint: String,
+        /// When the socket was connected (seconds since the unix epoch). None
+        /// until the first request
+        connected: Option<u64>,
+    },
+}
+
+/// Describes a device on any transport
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceMetadata {
+    pub serial: String,
+    /// Product name IE: "LINQ2". (None until the device describes itself)
+    pub product: Option<String>,
+    pub prj_version: Option<String>,
+    pub atx_version: Option<String>,
+    pub capabilities: Capabilities,
+    #[serde(flatten)]
+    pub location: Location,
+}
+
+impl DeviceMetadata {
+    pub fn new(serial: &str, location: Location) -> Self {
+        DeviceMetadata {
+            serial: serial.to_owned(),
+            product: None,
+            prj_version: None,
+            atx_version: None,
+            capabilities: Capabilities::default(),
+            location,
+        }
+    }
+
+    /// Fill in the common fields the device reports in /ATX/about
+    pub fn with_about(self, about: &About) -> Self {
+        let some = |s: &str| match s {
+            "" => None,
+            s => Some(s.to_owned()),
+        };
+        DeviceMetadata {
+            product: some(&about.product),
+            prj_version: some(&about.prjVersion),
+            atx_version: some(&about.atxVersion),
+            ..self
+        }
+    }
+
+    /// Set what the device can do
+    pub fn with_capabilities(self, capabilities: Capabilities) -> Self {
+        DeviceMetadata {
+            capabilities,
+            ..self
+        }
+    }
+
+    /// Name of the transport IE: "usb"
+    pub fn transport(&self) -> &'static str {
+        match self.location {
+            Location::Usb { .. } => "usb",
+            Location::Http { .. } => "http",
+            Location::Zmtp { .. } => "zmtp",
+        }
+    }
+}
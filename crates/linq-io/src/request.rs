@@ -1,63 +1,406 @@
+use crate::error::*;
+use bytes::Bytes;
 use serde::Serialize;
 use std::borrow::Cow;
+use std::convert::TryFrom;
 
 /// Useful strings for mapping enum to strings for transmitting
-const GET: &'static str = "GET";
-const POST: &'static str = "POST";
-const DELETE: &'static str = "DELETE";
+const RAW: &str = "RAW";
+const GET: &str = "GET";
+const POST: &str = "POST";
+const PUT: &str = "PUT";
+const DELETE: &str = "DELETE";
+
+/// Body types carried by the LengthPrefixed encoding
+const TEXT: &str = "TEXT";
+const BINARY: &str = "BINARY";
+
+/// Request methods. NOTE the discriminants match E_LINQ_REQUEST_METHOD
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Method {
+    /// Body is transmitted as is. (No method, path or query)
+    Raw = 0,
+    Get = 1,
+    Post = 2,
+    Delete = 3,
+    Put = 4,
+}
+
+impl Method {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Raw => RAW,
+            Method::Get => GET,
+            Method::Post => POST,
+            Method::Put => PUT,
+            Method::Delete => DELETE,
+        }
+    }
+}
+
+impl std::fmt::Display for Method {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for Method {
+    type Err = IoError;
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            RAW => Ok(Method::Raw),
+            GET => Ok(Method::Get),
+            POST => Ok(Method::Post),
+            PUT => Ok(Method::Put),
+            DELETE => Ok(Method::Delete),
+            _ => Err(IoError::Parser(format!("bad method [{}]", s))),
+        }
+    }
+}
+
+/// Body of a request
+#[derive(Clone, PartialEq, Debug)]
+pub enum Body {
+    Empty,
+    Text(String),
+    Binary(Bytes),
+}
+
+impl Body {
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Body::Empty => &[],
+            Body::Text(s) => s.as_bytes(),
+            Body::Binary(b) => b.as_ref(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.as_bytes().is_empty()
+    }
+}
+
+/// Credentials attached to a request. Only transports that support
+/// authentication (IE: http) transmit these, others ignore them.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Auth {
+    Basic { user: String, password: String },
+    Token(String),
+}
+
+/// How a request is laid out when serialized into bytes
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Encoding {
+    /// "METHOD\0path\0body" (What legacy firmware understands). The body is
+    /// the last field so it may contain NUL bytes, but the method and path
+    /// may not.
+    NullTerminated,
+    /// Every field is prepended with a little endian u32 length. Does not
+    /// depend on any separator so every field is binary safe. A body is
+    /// preceded by its type ("TEXT" or "BINARY") so it decodes as it was
+    /// built. (A NullTerminated body decodes as text when it is valid utf8)
+    LengthPrefixed,
+}
 
 #[derive(Clone, PartialEq, Debug)]
-pub enum Request {
-    /// Get Request accepts a Path
-    Get(String),
-    /// Post Request accepts a Path, and Data string
-    Post(String, String),
-    /// Delete Request accepts a Path
-    Delete(String),
+pub struct Request {
+    pub method: Method,
+    pub path: String,
+    pub query: Vec<(String, String)>,
+    pub headers: Vec<(String, String)>,
+    pub auth: Option<Auth>,
+    pub body: Body,
 }
 
 impl std::fmt::Display for Request {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self {
-            Request::Get(p) => write!(f, "[{}] {}", "GET", p),
-            Request::Post(p, b) => write!(f, "[{}] {} {:.18}...", "POST", p, b),
-            Request::Delete(p) => write!(f, "[{}] {}", "DELETE", p),
+        write!(f, "[{}] {}", self.method, self.path_and_query())?;
+        match &self.body {
+            Body::Empty => Ok(()),
+            Body::Text(b) => write!(f, " {:.18}...", b),
+            Body::Binary(b) => write!(f, " <{} bytes>", b.len()),
+        }
+    }
+}
+
+/// Percent encode everything except unreserved characters (RFC 3986)
+fn encode_component(s: &str) -> String {
+    s.bytes().fold(String::new(), |mut acc, b| {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => acc.push(b as char),
+            b'-' | b'.' | b'_' | b'~' => acc.push(b as char),
+            _ => acc.push_str(&format!("%{:02X}", b)),
+        }
+        acc
+    })
+}
+
+/// Reverse of encode_component
+fn decode_component(s: &str) -> Result<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = s
+                    .get(i + 1..i + 3)
+                    .and_then(|x| u8::from_str_radix(x, 16).ok())
+                    .ok_or(IoError::Parser("bad percent encoding".into()))?;
+                out.push(hex);
+                i += 3;
+            }
+            b'+' => {
+                out.push(b' ');
+                i += 1;
+            }
+            b => {
+                out.push(b);
+                i += 1;
+            }
         }
     }
+    String::from_utf8(out).map_err(|_| IoError::Parser("bad utf8".into()))
 }
 
 impl Request {
-    /// Helper routine to extract typical arguments for building URL
-    pub fn format_with_null_terminators(&self) -> String {
-        match self {
-            Request::Get(path) => format!("{}\0{}", GET, path),
-            Request::Post(p, b) => format!("{}\0{}\0{}", POST, p, b),
-            Request::Delete(path) => format!("{}\0{}", DELETE, path),
+    /// Create a request with no body
+    pub fn new(method: Method, path: &str) -> Self {
+        Request {
+            method,
+            path: path.to_owned(),
+            query: vec![],
+            headers: vec![],
+            auth: None,
+            body: Body::Empty,
+        }
+    }
+
+    /// Start building a request
+    pub fn builder(method: Method, path: &str) -> RequestBuilder {
+        RequestBuilder::new(method, path)
+    }
+
+    /// The path with the query string appended (if there are query params)
+    pub fn path_and_query(&self) -> String {
+        if self.query.is_empty() {
+            self.path.clone()
+        } else {
+            let query: Vec<String> = self
+                .query
+                .iter()
+                .map(|(k, v)| {
+                    format!("{}={}", encode_component(k), encode_component(v))
+                })
+                .collect();
+            format!("{}?{}", self.path, query.join("&"))
         }
     }
 
+    /// Helper routine to extract the fields we transmit (method, path, body).
+    /// When [typed] the body is preceded by its type
+    fn fields(&self, typed: bool) -> Vec<Cow<'_, [u8]>> {
+        let method = Cow::Borrowed(self.method.as_str().as_bytes());
+        let path = Cow::Owned(self.path_and_query().into_bytes());
+        let body = Cow::Borrowed(self.body.as_bytes());
+        let kind = match self.body {
+            Body::Binary(_) => Cow::Borrowed(BINARY.as_bytes()),
+            _ => Cow::Borrowed(TEXT.as_bytes()),
+        };
+        match (self.method, &self.body) {
+            (Method::Raw, _) => vec![body],
+            (_, Body::Empty) => vec![method, path],
+            _ if typed => vec![method, path, kind, body],
+            _ => vec![method, path, body],
+        }
+    }
+
+    /// Serialize the request with NUL terminators between each field
+    pub fn request_bytes(&self) -> Vec<u8> {
+        self.fields(false).join(&0)
+    }
+
+    /// Serialize the request with a length in front of each field
+    pub fn request_bytes_prefixed(&self) -> Vec<u8> {
+        self.fields(true)
+            .into_iter()
+            .fold(vec![], |mut acc, field| {
+                acc.extend_from_slice(&(field.len() as u32).to_le_bytes());
+                acc.extend_from_slice(&field);
+                acc
+            })
+    }
+
+    /// Serialize the request with the requested encoding
+    pub fn encode(&self, encoding: Encoding) -> Vec<u8> {
+        match encoding {
+            Encoding::NullTerminated => self.request_bytes(),
+            Encoding::LengthPrefixed => self.request_bytes_prefixed(),
+        }
+    }
+
+    /// Parse a request previously serialized with [encode]. NOTE that RAW
+    /// requests do not carry a method so they can not be decoded.
+    pub fn decode(bytes: &[u8], encoding: Encoding) -> Result<Self> {
+        let fields = match encoding {
+            Encoding::NullTerminated => bytes.splitn(3, |x| *x == 0).collect(),
+            Encoding::LengthPrefixed => Self::split_prefixed(bytes)?,
+        };
+        fn text(b: &[u8]) -> Result<&str> {
+            std::str::from_utf8(b)
+                .map_err(|_| IoError::Parser("bad utf8".to_string()))
+        }
+        let (method, path, body) = match (encoding, &fields[..]) {
+            (_, [m, p]) => (text(m)?.parse::<Method>()?, text(p)?, Body::Empty),
+            (Encoding::NullTerminated, [m, p, b]) => {
+                let body = match std::str::from_utf8(b) {
+                    Ok(s) => Body::Text(s.to_owned()),
+                    Err(_) => Body::Binary(b.to_vec().into()),
+                };
+                (text(m)?.parse::<Method>()?, text(p)?, body)
+            }
+            (Encoding::LengthPrefixed, [m, p, kind, b]) => {
+                let body = match text(kind)? {
+                    TEXT => Body::Text(text(b)?.to_owned()),
+                    BINARY => Body::Binary(b.to_vec().into()),
+                    _ => return Err(IoError::Parser("bad body type".into())),
+                };
+                (text(m)?.parse::<Method>()?, text(p)?, body)
+            }
+            _ => return Err(IoError::Parser("bad request".to_string())),
+        };
+        if method == Method::Raw {
+            return Err(IoError::Parser("bad request".to_string()));
+        }
+        let mut request = Request::with_target(method, path)?;
+        request.body = body;
+        Ok(request)
+    }
+
+    /// Create a request with no body from a path that may carry a percent
+    /// encoded query string IE: "/ATX/about?a=b%20c"
+    pub(crate) fn with_target(method: Method, target: &str) -> Result<Self> {
+        let mut split = target.splitn(2, '?');
+        let mut request = Request::new(method, split.next().unwrap_or(""));
+        if let Some(query) = split.next() {
+            for pair in query.split('&').filter(|x| !x.is_empty()) {
+                let mut kv = pair.splitn(2, '=');
+                let k = decode_component(kv.next().unwrap_or(""))?;
+                let v = decode_component(kv.next().unwrap_or(""))?;
+                request.query.push((k, v));
+            }
+        }
+        Ok(request)
+    }
+
+    /// Helper to walk a length prefixed buffer
+    fn split_prefixed(mut bytes: &[u8]) -> Result<Vec<&[u8]>> {
+        let mut fields = vec![];
+        while !bytes.is_empty() {
+            let len = bytes
+                .get(..4)
+                .and_then(|x| <[u8; 4]>::try_from(x).ok())
+                .map(|x| u32::from_le_bytes(x) as usize)
+                .ok_or(IoError::Parser("truncated length".to_string()))?;
+            let field = bytes
+                .get(4..4 + len)
+                .ok_or(IoError::Parser("truncated field".to_string()))?;
+            fields.push(field);
+            bytes = &bytes[4 + len..];
+        }
+        Ok(fields)
+    }
+
     /// Create a GET request
-    pub fn get<'a>(path: &'a str) -> Self {
-        Request::Get(path.to_owned())
+    pub fn get(path: &str) -> Self {
+        Request::new(Method::Get, path)
     }
 
     /// Create a POST req where data can be serialized from a native rust object
-    pub fn post<'a, T: Serialize>(path: &'a str, data: &T) -> Self {
+    pub fn post<T: Serialize>(path: &str, data: &T) -> Self {
         let t = serde_json::to_string(&data).unwrap(); // TODO return result
         Request::post_raw(path, t)
     }
 
     /// Create a POST request that cannot be serialized. (Useful for proxying)
-    pub fn post_raw<'a, S: Into<Cow<'a, str>>>(path: &'a str, data: S) -> Self {
-        let data: String = match data.into() {
-            Cow::Borrowed(s) => s.to_owned(),
-            Cow::Owned(s) => s,
-        };
-        Request::Post(path.to_owned(), data)
+    pub fn post_raw<'a, S: Into<Cow<'a, str>>>(path: &str, data: S) -> Self {
+        Request::builder(Method::Post, path).text(data).build()
+    }
+
+    /// Create a POST request with a binary body (certificates, images, etc)
+    pub fn post_bytes<B: Into<Bytes>>(path: &str, data: B) -> Self {
+        Request::builder(Method::Post, path).bytes(data).build()
+    }
+
+    /// Create a PUT request that cannot be serialized. (Useful for proxying)
+    pub fn put_raw<'a, S: Into<Cow<'a, str>>>(path: &str, data: S) -> Self {
+        Request::builder(Method::Put, path).text(data).build()
     }
 
     /// Create a DELETE request
-    pub fn delete<'a>(serial: &'a str, path: &'a str) -> Self {
-        Request::Delete(path.to_owned())
+    pub fn delete(path: &str) -> Self {
+        Request::new(Method::Delete, path)
+    }
+
+    /// Create a RAW request. The data is transmitted to the device as is
+    pub fn raw<B: Into<Bytes>>(data: B) -> Self {
+        Request::builder(Method::Raw, "").bytes(data).build()
+    }
+}
+
+/// Helper for building up a Request
+pub struct RequestBuilder {
+    request: Request,
+}
+
+impl RequestBuilder {
+    pub fn new(method: Method, path: &str) -> Self {
+        RequestBuilder {
+            request: Request::new(method, path),
+        }
+    }
+
+    /// Append a query parameter (IE: ?key=value)
+    pub fn query(mut self, key: &str, value: &str) -> Self {
+        self.request.query.push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Append a header. (Ignored by transports that do not have headers)
+    pub fn header(mut self, key: &str, value: &str) -> Self {
+        self.request
+            .headers
+            .push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// Attach credentials. (Ignored by transports without authentication)
+    pub fn auth(mut self, auth: Auth) -> Self {
+        self.request.auth = Some(auth);
+        self
+    }
+
+    /// Set a text body
+    pub fn text<'a, S: Into<Cow<'a, str>>>(mut self, data: S) -> Self {
+        self.request.body = Body::Text(data.into().into_owned());
+        self
+    }
+
+    /// Set a binary body
+    pub fn bytes<B: Into<Bytes>>(mut self, data: B) -> Self {
+        self.request.body = Body::Binary(data.into());
+        self
+    }
+
+    /// Set a body serialized from a native rust object
+    pub fn json<T: Serialize>(mut self, data: &T) -> Result<Self> {
+        let data = serde_json::to_string(data)
+            .map_err(|x| IoError::Parser(x.to_string()))?;
+        self.request.body = Body::Text(data);
+        Ok(self)
+    }
+
+    pub fn build(self) -> Request {
+        self.request
     }
 }
@@ -0,0 +1,210 @@
+use crate::credentials::*;
+use crate::error::*;
+use crate::request::{Auth, Request};
+use std::cell::RefCell;
+use std::sync::Mutex;
+
+fn basic(user: &str, password: &str) -> Auth {
+    Auth::Basic {
+        user: user.into(),
+        password: password.into(),
+    }
+}
+
+fn forbidden() -> IoError {
+    ApiError::new(403, "/ATX/about", "").into()
+}
+
+/// Hands out a different token each time it is asked to refresh
+struct Rotating {
+    refreshed: Mutex<usize>,
+}
+
+impl CredentialProvider for Rotating {
+    fn credentials(&self, _key: &str) -> Option<Auth> {
+        Some(Auth::Token(format!("{}", self.refreshed.lock().unwrap())))
+    }
+
+    fn refresh(&self, key: &str) -> Option<Auth> {
+        *self.refreshed.lock().unwrap() += 1;
+        self.credentials(key)
+    }
+}
+
+#[test]
+fn test_memory_credentials() {
+    let credentials = MemoryCredentials::new();
+    assert_eq!(credentials.credentials("A"), None);
+    credentials.insert("A", basic("a", "a"));
+    credentials.insert("*", Auth::Token("any".into()));
+    assert_eq!(credentials.credentials("A"), Some(basic("a", "a")));
+    assert_eq!(credentials.credentials("B"), None);
+    assert_eq!(credentials.fallback(), Some(Auth::Token("any".into())));
+    assert_eq!(lookup(&credentials, ["B"]), credentials.fallback());
+    credentials.remove("A");
+    assert_eq!(lookup(&credentials, ["A"]), credentials.fallback());
+}
+
+#[test]
+fn test_lookup_order() {
+    let credentials = MemoryCredentials::new();
+    credentials.insert("10.0.0.1:80", basic("addr", "addr"));
+    let keys = vec!["SERIAL", "10.0.0.1:80"];
+    assert_eq!(lookup(&credentials, keys), Some(basic("addr", "addr")));
+    credentials.insert("SERIAL", basic("sid", "sid"));
+    let keys = vec!["SERIAL", "10.0.0.1:80"];
+    assert_eq!(lookup(&credentials, keys), Some(basic("sid", "sid")));
+
+    // Every key is matched exactly before the wildcard
+    let credentials = MemoryCredentials::new();
+    credentials.insert("*", Auth::Token("any".into()));
+    credentials.insert("10.0.0.1:80", basic("addr", "addr"));
+    let keys = ["SERIAL", "10.0.0.1:80"];
+    assert_eq!(lookup(&credentials, keys), Some(basic("addr", "addr")));
+    let keys = ["SERIAL", "10.0.0.2:80"];
+    assert_eq!(lookup(&credentials, keys), Some(Auth::Token("any".into())));
+}
+
+#[test]
+fn test_env_credentials() {
+    let credentials = EnvCredentials::new("LINQ_TEST_AUTH");
+    assert_eq!(
+        credentials.var(Some("10.0.0.1:80"), "USER"),
+        "LINQ_TEST_AUTH_10_0_0_1_80_USER"
+    );
+    assert_eq!(credentials.var(None, "TOKEN"), "LINQ_TEST_AUTH_TOKEN");
+    std::env::set_var("LINQ_TEST_AUTH_ABC_123_USER", "admin");
+    std::env::set_var("LINQ_TEST_AUTH_ABC_123_PASSWORD", "secret");
+    std::env::set_var("LINQ_TEST_AUTH_TOKEN", "fallback");
+    assert_eq!(
+        credentials.credentials("abc-123"),
+        Some(basic("admin", "secret"))
+    );
+    assert_eq!(credentials.credentials("other"), None);
+    assert_eq!(
+        lookup(&credentials, ["other"]),
+        Some(Auth::Token("fallback".into()))
+    );
+}
+
+#[test]
+fn test_file_credentials() {
+    let path = std::env::temp_dir()
+        .join(format!("linq-credentials-{}.json", std::process::id()));
+    let json = r#"{
+        "A": { "user": "admin", "password": "admin" },
+        "*": { "token": "abc" }
+    }"#;
+    std::fs::write(&path, json).unwrap();
+    let credentials = FileCredentials::new(&path).unwrap();
+    assert_eq!(credentials.credentials("A"), Some(basic("admin", "admin")));
+    assert_eq!(lookup(&credentials, ["B"]), Some(Auth::Token("abc".into())));
+
+    // Edits to the file are picked up when refreshing
+    std::fs::write(&path, r#"{"A":{"token":"new"}}"#).unwrap();
+    assert_eq!(credentials.refresh("A"), Some(Auth::Token("new".into())));
+    assert_eq!(lookup(&credentials, ["B"]), None);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_file_credentials_parse_error() {
+    assert!(FileCredentials::parse(r#"{"A":{"user":"a"}}"#).is_err());
+    assert!(FileCredentials::parse("[]").is_err());
+    let path = std::env::temp_dir().join("linq-credentials-does-not-exist");
+    assert!(FileCredentials::new(path).is_err());
+}
+
+#[test]
+fn test_authenticate_attaches_credentials() {
+    let credentials = MemoryCredentials::new();
+    credentials.insert("A", basic("a", "a"));
+    let request = Request::get("/ATX/about");
+    let response = authenticate(Some(&credentials), &["A"], request, |r| {
+        assert_eq!(r.auth, Some(basic("a", "a")));
+        Ok(vec![])
+    });
+    assert!(response.is_ok());
+
+    // Credentials supplied by the caller are left alone
+    let request = Request::builder(crate::request::Method::Get, "/ATX/about")
+        .auth(Auth::Token("mine".into()))
+        .build();
+    let response = authenticate(Some(&credentials), &["A"], request, |r| {
+        assert_eq!(r.auth, Some(Auth::Token("mine".into())));
+        Ok(vec![])
+    });
+    assert!(response.is_ok());
+}
+
+#[test]
+fn test_authenticate_retries_once_after_403() {
+    let credentials = Rotating {
+        refreshed: Mutex::new(0),
+    };
+    let seen = RefCell::new(vec![]);
+    let request = Request::get("/ATX/about");
+    let response = authenticate(Some(&credentials), &["A"], request, |r| {
+        seen.borrow_mut().push(r.auth.clone().unwrap());
+        match seen.borrow().len() {
+            1 => Err(forbidden()),
+            _ => Ok(b"ok".to_vec()),
+        }
+    });
+    assert_eq!(response.unwrap(), b"ok");
+    assert_eq!(
+        *seen.borrow(),
+        vec![Auth::Token("0".into()), Auth::Token("1".into())]
+    );
+
+    // Give up if the device keeps rejecting us
+    let mut attempts = 0;
+    let request = Request::get("/ATX/about");
+    let response = authenticate(Some(&credentials), &["A"], request, |_| {
+        attempts += 1;
+        Err(forbidden())
+    });
+    assert_eq!(attempts, 2);
+    match response {
+        Err(IoError::ApiError(e)) => assert_eq!(e.kind, ApiErrorKind::Linq403),
+        _ => panic!("expected 403"),
+    }
+}
+
+#[test]
+fn test_authenticate_without_provider() {
+    let mut attempts = 0;
+    let request = Request::get("/ATX/about");
+    let response = authenticate(None, &["A"], request, |r| {
+        assert_eq!(r.auth, None);
+        attempts += 1;
+        Err(forbidden())
+    });
+    assert!(response.is_err());
+    assert_eq!(attempts, 1);
+
+    // Nothing to retry with
+    let empty = MemoryCredentials::new();
+    let mut attempts = 0;
+    let request = Request::get("/ATX/about");
+    let response = authenticate(Some(&empty), &["A"], request, |_| {
+        attempts += 1;
+        Err(forbidden())
+    });
+    assert!(response.is_err());
+    assert_eq!(attempts, 1);
+}
+
+#[test]
+fn test_authenticate_does_not_resend_same_credentials() {
+    let credentials = MemoryCredentials::new();
+    credentials.insert("A", basic("a", "a"));
+    let mut attempts = 0;
+    let request = Request::get("/ATX/about");
+    let response = authenticate(Some(&credentials), &["A"], request, |_| {
+        attempts += 1;
+        Err(forbidden())
+    });
+    assert!(response.is_err());
+    assert_eq!(attempts, 1);
+}
@@ -0,0 +1,81 @@
+use crate::error::*;
+use linq_sys::*;
+
+#[test]
+fn test_api_error_kind_maps_binding_codes() {
+    let codes = vec![
+        (E_LINQ_ERROR_LINQ_ERROR_OOM, ApiErrorKind::LinqOom),
+        (E_LINQ_ERROR_LINQ_ERROR_BAD_ARGS, ApiErrorKind::LinqBadArgs),
+        (E_LINQ_ERROR_LINQ_ERROR_PROTOCOL, ApiErrorKind::LinqProtocol),
+        (E_LINQ_ERROR_LINQ_ERROR_IO, ApiErrorKind::LinqIo),
+        (
+            E_LINQ_ERROR_LINQ_ERROR_DEVICE_NOT_FOUND,
+            ApiErrorKind::LinqDeviceNotFound,
+        ),
+        (E_LINQ_ERROR_LINQ_ERROR_TIMEOUT, ApiErrorKind::LinqTimeout),
+        (
+            E_LINQ_ERROR_LINQ_ERROR_SHUTTING_DOWN,
+            ApiErrorKind::LinqShuttingDown,
+        ),
+        (E_LINQ_ERROR_LINQ_ERROR_LIBUSB, ApiErrorKind::LinqLibusb),
+        (E_LINQ_ERROR_LINQ_ERROR_400, ApiErrorKind::Linq400),
+        (E_LINQ_ERROR_LINQ_ERROR_403, ApiErrorKind::Linq403),
+        (E_LINQ_ERROR_LINQ_ERROR_404, ApiErrorKind::Linq404),
+        (E_LINQ_ERROR_LINQ_ERROR_500, ApiErrorKind::Linq500),
+        (E_LINQ_ERROR_LINQ_ERROR_504, ApiErrorKind::Linq504),
+    ];
+    for (code, kind) in codes {
+        assert_eq!(ApiErrorKind::from_code(code as i64), kind);
+        assert_eq!(kind.code(), Some(code as i64));
+    }
+    assert_eq!(ApiErrorKind::from_code(409), ApiErrorKind::LinqUnknown);
+    // Codes that do not fit the binding are not truncated onto a known code
+    let wrapped = (1_i64 << 32) + 404;
+    assert_eq!(ApiErrorKind::from_code(wrapped), ApiErrorKind::LinqUnknown);
+    assert_eq!(ApiErrorKind::LinqUnknown.code(), None);
+}
+
+#[test]
+fn test_api_error_from_body() {
+    let body = b"{\"error\":404,\"message\":\"no such thing\"}";
+    let e = ApiError::from_body("/ATX/foo", body).unwrap();
+    assert_eq!(e.kind, ApiErrorKind::Linq404);
+    assert_eq!(e.code, 404);
+    assert_eq!(e.path, "/ATX/foo");
+    assert_eq!(e.message, "no such thing");
+}
+
+#[test]
+fn test_api_error_from_body_keeps_unknown_code() {
+    let e = ApiError::from_body("/ATX", b"{\"error\":409}\0\0").unwrap();
+    assert_eq!(e.kind, ApiErrorKind::LinqUnknown);
+    assert_eq!(e.code, 409);
+}
+
+#[test]
+fn test_api_error_from_body_not_an_error() {
+    assert!(ApiError::from_body("/ATX", b"{\"error\":200}").is_none());
+    assert!(ApiError::from_body("/ATX", b"{\"siteId\":\"foo\"}").is_none());
+    assert!(ApiError::from_body("/ATX", b"{\"error\":1,\"a\":2}").is_none());
+    assert!(ApiError::from_body("/ATX", &[0xff, 0x00]).is_none());
+}
+
+#[test]
+fn test_translate_response() {
+    let ok = translate_response("/ATX", None, b"{\"a\":1}".to_vec());
+    assert_eq!(ok.unwrap(), b"{\"a\":1}");
+    let ok = translate_response("/ATX", Some(200), b"{}".to_vec());
+    assert!(ok.is_ok());
+    match translate_response("/ATX", None, b"{\"error\":504}".to_vec()) {
+        Err(IoError::ApiError(e)) => assert_eq!(e.kind, ApiErrorKind::Linq504),
+        _ => panic!("expected api error"),
+    }
+    match translate_response("/ATX/x", Some(403), b"Forbidden\r\n".to_vec()) {
+        Err(IoError::ApiError(e)) => {
+            assert_eq!(e.kind, ApiErrorKind::Linq403);
+            assert_eq!(e.path, "/ATX/x");
+            assert_eq!(e.message, "Forbidden");
+        }
+        _ => panic!("expected api error"),
+    }
+}
@@ -0,0 +1,75 @@
+use crate::error::*;
+use crate::io::Io;
+use crate::request::Request;
+use crate::usb::{Simulator, VirtualBus};
+use futures::executor::block_on;
+use serde_json::json;
+use std::sync::Arc;
+
+#[test]
+fn test_io_summary() {
+    let bus = VirtualBus::new();
+    let a = Arc::new(Simulator::new("A"));
+    bus.attach("usb-a", a.clone());
+    let mut io = Io::with_backend(bus.clone());
+
+    // Nothing is opened (or asked) to describe the bus
+    let summary = block_on(io.summary()).unwrap();
+    assert_eq!(summary.len(), 1);
+    assert_eq!(summary[0].serial, "usb-a");
+    assert!(a.requests().is_empty());
+    assert!(io.meta().is_empty());
+
+    bus.attach("usb-b", Arc::new(Simulator::new("B")));
+    let serials: Vec<String> = block_on(io.summary())
+        .unwrap()
+        .into_iter()
+        .map(|x| x.serial)
+        .collect();
+    assert_eq!(serials, vec!["usb-a", "usb-b"]);
+    io.close().unwrap();
+}
+
+#[test]
+fn test_io_virtual_bus() {
+    let bus = VirtualBus::new();
+    let a = Arc::new(Simulator::new("A"));
+    let b = Arc::new(Simulator::new("B"));
+    bus.attach("usb-a", a.clone());
+    bus.attach("usb-b", b.clone());
+    let mut io = Io::with_backend(bus.clone());
+
+    // Devices are found by sid. (USB serial is only used on the wire)
+    let meta = block_on(io.scan()).unwrap();
+    let serials: Vec<&str> = meta.iter().map(|x| &x.serial[..]).collect();
+    assert_eq!(serials, vec!["A", "B"]);
+    assert_eq!(meta[0].sid, "usb-a");
+
+    let response = block_on(io.get("A", "/ATX/about/sid")).unwrap();
+    assert_eq!(response, "{\"sid\":\"A\"}");
+    let r = Request::post_raw("/ATX/about", "{\"siteId\":\"bench\"}");
+    assert!(block_on(io.request("B", r)).is_ok());
+    assert_eq!(b.get("/ATX/about/siteId"), Some(json!("bench")));
+    assert_eq!(a.get("/ATX/about/siteId"), Some(json!("Site ID")));
+
+    // Unplugged devices fail like missing usb devices
+    bus.detach("usb-a");
+    match block_on(io.get("A", "/ATX/about")) {
+        Err(IoError::Usb(UsbError::DeviceNotFound(s))) => {
+            assert_eq!(s, "usb-a")
+        }
+        _ => panic!("expected device not found"),
+    }
+    match block_on(io.get("C", "/ATX/about")) {
+        Err(IoError::DeviceNotFound(s)) => assert_eq!(s, "C"),
+        _ => panic!("expected device not found"),
+    }
+
+    // Rescan forgets unplugged devices but keeps network channels
+    io.connect_http("10.0.0.1");
+    assert_eq!(block_on(io.scan()).unwrap().len(), 1);
+    let serials: Vec<String> =
+        io.meta().into_iter().map(|x| x.serial).collect();
+    assert_eq!(serials, vec!["10.0.0.1", "B"]);
+    io.close().unwrap();
+}
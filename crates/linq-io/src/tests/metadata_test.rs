@@ -0,0 +1,136 @@
+use crate::io::Io;
+use crate::metadata::{DeviceMetadata, Location};
+use crate::request::Request;
+use crate::usb::{Simulator, VirtualBus};
+use futures::executor::block_on;
+use linq_db::k64::{About, AboutResponse};
+use serde_json::json;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[test]
+fn test_metadata_serialize() {
+    let about = About {
+        prjVersion: "2.6.6".to_string(),
+        product: "LINQ2".to_string(),
+        ..Default::default()
+    };
+    let address = "10.0.0.1:80".to_string();
+    let meta =
+        DeviceMetadata::new("A", Location::Http { address }).with_about(&about);
+    assert_eq!(meta.transport(), "http");
+    let value = serde_json::to_value(&meta).unwrap();
+    assert_eq!(value["transport"], json!("http"));
+    assert_eq!(value["address"], json!("10.0.0.1:80"));
+    assert_eq!(value["product"], json!("LINQ2"));
+    assert_eq!(value["prjVersion"], json!("2.6.6"));
+    assert_eq!(value["atxVersion"], json!(null));
+    let parsed: DeviceMetadata = serde_json::from_value(value).unwrap();
+    assert_eq!(parsed, meta);
+}
+
+#[test]
+fn test_metadata_io() {
+    let bus = VirtualBus::new();
+    bus.attach("usb-a", Arc::new(Simulator::new("A")));
+    let mut io = Io::with_backend(bus);
+    block_on(io.scan()).unwrap();
+    io.connect_http("10.0.0.1");
+    io.connect_zmtp("C", "10.0.0.2:33455");
+    let meta = io.meta();
+    let transports: Vec<&str> = meta.iter().map(|x| x.transport()).collect();
+    assert_eq!(transports, vec!["http", "usb", "zmtp"]);
+    assert_eq!(meta[0].serial, "10.0.0.1");
+    let expect = Location::Usb {
+        vid: 0x0461,
+        pid: 0x0020,
+        sid: "usb-a".to_string(),
+    };
+    assert_eq!((meta[1].serial.as_str(), &meta[1].location), ("A", &expect));
+    assert!(meta[1].capabilities.update);
+    let expect = Location::Zmtp {
+        endpoint: "tcp://10.0.0.2:33455".to_string(),
+        connected: None,
+    };
+    assert_eq!(meta[2].location, expect);
+    io.close().unwrap();
+}
+
+#[test]
+fn test_metadata_about_cached() {
+    let bus = VirtualBus::new();
+    let sim = Arc::new(Simulator::new("A"));
+    bus.attach("usb-a", sim.clone());
+    let mut io = Io::with_backend(bus);
+
+    // Scan output carries about so listing devices needs no extra requests
+    let meta = block_on(io.scan()).unwrap();
+    let about = meta[0].about.clone().unwrap();
+    assert_eq!(about.siteId, "Site ID");
+    let requests = sim.requests().len();
+    assert_eq!(io.about("A").unwrap(), Some(about));
+    assert_eq!(io.meta()[0].product, Some("LINQ2".to_string()));
+    assert_eq!(io.meta()[0].prj_version, Some("2.6.6".to_string()));
+    assert_eq!(sim.requests().len(), requests);
+
+    // Refresh on demand
+    let r = Request::post_raw("/ATX/about", "{\"siteId\":\"bench\"}");
+    block_on(io.request("A", r)).unwrap();
+    assert_eq!(io.about("A").unwrap().unwrap().siteId, "Site ID");
+    assert_eq!(block_on(io.refresh("A")).unwrap().siteId, "bench");
+    assert_eq!(io.about("A").unwrap().unwrap().siteId, "bench");
+    assert!(io.about("B").is_err());
+    io.close().unwrap();
+}
+
+#[test]
+fn test_metadata_about_uncached() {
+    let mut io = Io::with_backend(VirtualBus::new());
+    io.connect_http("10.0.0.1");
+    assert_eq!(io.about("10.0.0.1").unwrap(), None);
+    assert_eq!(io.meta()[0].product, None);
+    io.close().unwrap();
+}
+
+#[test]
+fn test_metadata_about_read_on_open() {
+    let about = About {
+        prjVersion: "2.6.6".to_string(),
+        product: "LINQ2".to_string(),
+        ..Default::default()
+    };
+    let body = serde_json::to_string(&AboutResponse { about }).unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = vec![0; 4096];
+        let n = stream.read(&mut request).unwrap();
+        let head = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        );
+        stream.write_all(head.as_bytes()).unwrap();
+        stream.write_all(body.as_bytes()).unwrap();
+        String::from_utf8_lossy(&request[..n]).to_string()
+    });
+
+    // The channel is usable right away and described once the device answers
+    let mut io = Io::with_backend(VirtualBus::new());
+    io.connect_http(&address);
+    assert!(server
+        .join()
+        .unwrap()
+        .starts_with("GET /ATX/about HTTP/1.1"));
+    let product = (0..100)
+        .find_map(|_| {
+            std::thread::sleep(Duration::from_millis(10));
+            io.meta()[0].product.clone()
+        })
+        .unwrap();
+    assert_eq!(product, "LINQ2");
+    assert_eq!(io.about(&address).unwrap().unwrap().prjVersion, "2.6.6");
+    io.close().unwrap();
+}
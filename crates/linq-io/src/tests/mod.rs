@@ -0,0 +1,5 @@
+mod credentials_test;
+mod error_test;
+mod io_test;
+mod metadata_test;
+mod request_test;
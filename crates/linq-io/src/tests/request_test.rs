@@ -0,0 +1,113 @@
+use crate::request::{Body, Encoding, Method, Request};
+
+/// A body with NUL bytes and invalid utf8 (ie: DER encoded certificate)
+const BINARY: &[u8] = &[0x30, 0x82, 0x00, 0x0a, 0xff, 0x00, 0xfe];
+
+#[test]
+fn test_request_bytes_null_terminated() {
+    let request = Request::get("/ATX/about");
+    assert_eq!(request.request_bytes(), b"GET\0/ATX/about");
+    let request = Request::post_raw("/ATX/exe/save", "{\"save\":1}");
+    assert_eq!(
+        request.request_bytes(),
+        b"POST\0/ATX/exe/save\0{\"save\":1}"
+    );
+}
+
+#[test]
+fn test_request_bytes_binary_body() {
+    let request = Request::post_bytes("/ATX/cert", BINARY);
+    let bytes = request.request_bytes();
+    assert_eq!(&bytes[..15], b"POST\0/ATX/cert\0");
+    assert_eq!(&bytes[15..], BINARY);
+}
+
+#[test]
+fn test_request_bytes_prefixed() {
+    let request = Request::get("/ATX");
+    assert_eq!(
+        request.request_bytes_prefixed(),
+        b"\x03\0\0\0GET\x04\0\0\0/ATX"
+    );
+}
+
+#[test]
+fn test_request_decode_roundtrip() {
+    let requests = [
+        Request::get("/ATX/about"),
+        Request::delete("/ATX/userManagement/users/foo"),
+        Request::builder(Method::Get, "/ATX/network")
+            .query("a b", "c&d=e")
+            .query("f", "")
+            .build(),
+        Request::put_raw("/ATX/about", "{\"siteId\":\"foo\"}"),
+        Request::post_raw("/ATX/about", "{\"siteId\":\"foo\"}"),
+        Request::post_bytes("/ATX/cert", BINARY),
+    ];
+    for encoding in [Encoding::NullTerminated, Encoding::LengthPrefixed] {
+        for request in requests.iter() {
+            let bytes = request.encode(encoding);
+            let decoded = Request::decode(&bytes, encoding).unwrap();
+            assert_eq!(&decoded, request);
+        }
+    }
+}
+
+#[test]
+fn test_request_decode_prefixed_body_with_nul() {
+    let request = Request::post_raw("/ATX/about", "a\0b\0c");
+    let bytes = request.request_bytes_prefixed();
+    let decoded = Request::decode(&bytes, Encoding::LengthPrefixed).unwrap();
+    assert_eq!(decoded, request);
+}
+
+#[test]
+fn test_request_decode_prefixed_keeps_body_type() {
+    let request = Request::post_bytes("/ATX/cert", &b"{\"a\":1}"[..]);
+    let bytes = request.request_bytes_prefixed();
+    let decoded = Request::decode(&bytes, Encoding::LengthPrefixed).unwrap();
+    assert_eq!(decoded.body, Body::Binary((&b"{\"a\":1}"[..]).into()));
+    assert_eq!(decoded, request);
+}
+
+#[test]
+fn test_request_decode_bad() {
+    let bytes = Request::get("/ATX/about").request_bytes_prefixed();
+    let truncated = &bytes[..bytes.len() - 1];
+    assert!(Request::decode(truncated, Encoding::LengthPrefixed).is_err());
+    assert!(Request::decode(b"FOO\0/ATX", Encoding::NullTerminated).is_err());
+}
+
+#[test]
+fn test_request_raw() {
+    let request = Request::raw(BINARY);
+    assert_eq!(request.request_bytes(), BINARY);
+    assert!(Request::decode(BINARY, Encoding::NullTerminated).is_err());
+}
+
+#[test]
+fn test_request_builder() {
+    let request = Request::builder(Method::Put, "/ATX/about")
+        .query("x", "1")
+        .query("y", "/")
+        .header("X-Foo", "bar")
+        .json(&vec![1, 2, 3])
+        .unwrap()
+        .build();
+    assert_eq!(request.method, Method::Put);
+    assert_eq!(request.path_and_query(), "/ATX/about?x=1&y=%2F");
+    assert_eq!(request.headers, vec![("X-Foo".into(), "bar".into())]);
+    assert_eq!(request.body, Body::Text("[1,2,3]".into()));
+    assert_eq!(
+        request.request_bytes(),
+        b"PUT\0/ATX/about?x=1&y=%2F\0[1,2,3]"
+    );
+}
+
+#[test]
+fn test_method_from_str() {
+    for m in [Method::Get, Method::Post, Method::Put, Method::Delete] {
+        assert_eq!(m.as_str().parse::<Method>().unwrap(), m);
+    }
+    assert!("FOO".parse::<Method>().is_err());
+}
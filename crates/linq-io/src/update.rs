@@ -1,11 +1,10 @@
-use super::request::Request;
 use crate::error::*;
-use linq_db::k64::{DashboardUpdate, DashboardUpdateImage};
+use linq_db::k64::{DashboardUpdate, DashboardUpdateImage, Update};
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::Path;
 
-pub struct DashboardUpdatePackets(pub Vec<Request>, pub Vec<Request>);
+pub struct DashboardUpdatePackets(pub Vec<Update>, pub Vec<Update>);
 impl DashboardUpdatePackets {
     /// Have file location, want requests
     pub fn parse_file(path: &str) -> Result<Self> {
@@ -15,7 +14,7 @@ impl DashboardUpdatePackets {
         Self::parse(&update)
     }
 
-    /// We have a JSON string Dashboard Update and we want a vec of requests
+    /// We have a JSON string Dashboard Update and we want a vec of chunks
     /// NOTE: we reverse our array because we pop() them off before tx and pop
     ///       takes off the end of the list...
     pub fn parse(u: &str) -> Result<Self> {
@@ -24,18 +23,13 @@ impl DashboardUpdatePackets {
         if update.files.len() < 2 {
             return Err(IoError::Parser("bad update file".to_string()));
         }
-        let website = Self::map_requests(update.files.pop().unwrap())?;
-        let firmware = Self::map_requests(update.files.pop().unwrap())?;
+        let website = Self::chunks(update.files.pop().unwrap());
+        let firmware = Self::chunks(update.files.pop().unwrap());
         Ok(DashboardUpdatePackets(firmware, website))
     }
 
-    /// Take an update image and return a Vector of requests
-    fn map_requests(update: DashboardUpdateImage) -> Result<Vec<Request>> {
-        update
-            .update
-            .into_iter()
-            .map(|u| Ok(Request::post("/ATX/exe/update", &u)))
-            .rev()
-            .collect()
+    /// Take an update image and return a Vector of chunks (reversed)
+    fn chunks(update: DashboardUpdateImage) -> Vec<Update> {
+        update.update.into_iter().rev().collect()
     }
 }
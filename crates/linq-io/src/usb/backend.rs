@@ -0,0 +1,69 @@
+use super::drivers::driver::{Reader, ReaderWriter, UsbDriver, Writer};
+use super::metadata::{Summary, UsbMetadata};
+use super::registry::{Product, ProductRegistry};
+use crate::error::*;
+
+/// A usb bus. The libusb binding is the default backend. (See VirtualBus for
+/// a bus of simulated devices)
+pub trait Backend {
+    /// Tell the bus about a product to look for during scan
+    fn add_product(&mut self, _product: &Product) -> Result<()> {
+        Ok(())
+    }
+
+    /// Describe every device connected to the bus
+    fn scan(&mut self) -> Result<Vec<Summary>>;
+
+    /// Send a packet to device with usb serial number [serial]
+    fn send(&self, serial: &str, bytes: &[u8]) -> Result<usize>;
+
+    /// Receive a packet from device with usb serial number [serial]
+    fn recv(&self, serial: &str, bytes: &mut [u8]) -> Result<usize>;
+}
+
+/// A backend owned by the usb thread
+pub type BoxBackend = Box<dyn Backend>;
+
+/// Scan the bus and open a driver for each registered product
+pub fn scan(
+    backend: &mut BoxBackend,
+    registry: &ProductRegistry,
+) -> Result<Vec<(UsbMetadata, Box<dyn UsbDriver>)>> {
+    let summaries = backend.scan()?;
+    let backend = backend.as_ref();
+    summaries
+        .iter()
+        .map(|x| {
+            let (vid, pid, sid) = (x.vendor, x.product, &x.serial);
+            let product = registry.find(vid, pid).ok_or_else(|| {
+                let e = format!("invalid product [{:04x}/{:04x}]", vid, pid);
+                UsbError::Protocol(e)
+            })?;
+            let mut driver =
+                registry.create(&product.driver).ok_or_else(|| {
+                    let e = format!("invalid driver [{}]", product.driver);
+                    UsbError::Protocol(e)
+                })?;
+            let serial = driver.open(backend, sid)?;
+            let mut meta = UsbMetadata::new(&serial, x);
+            meta.capabilities = driver.capabilities();
+            meta.about = driver.about();
+            Ok((meta, driver))
+        })
+        .collect()
+}
+
+/// Drivers speak to any backend
+impl<'a> ReaderWriter for dyn Backend + 'a {}
+
+impl<'a> Writer for dyn Backend + 'a {
+    fn write(&self, s: &str, bytes: &[u8]) -> Result<usize> {
+        self.send(s, bytes)
+    }
+}
+
+impl<'a> Reader for dyn Backend + 'a {
+    fn read(&self, s: &str, bytes: &mut [u8]) -> Result<usize> {
+        self.recv(s, bytes)
+    }
+}
@@ -1,35 +1,13 @@
+use super::backend::Backend;
 use super::drivers::driver::{Reader, ReaderWriter, Writer};
-use super::drivers::k64;
-use super::drivers::m5;
-use super::metadata::{Summary, UsbMetadata};
+use super::metadata::Summary;
+use super::registry::{Product, Transport};
 use crate::error::*;
-use crate::request::Request;
 use linq_sys::*;
 use linq_util::lformat;
 use log::{debug, error, info, trace, warn};
 use std::ffi::{CStr, CString};
 
-const PID_K64: u32 = 0x0020;
-const PID_M5: u32 = 0x4444;
-
-/// Helper type when forwarding requests to the correct driver
-#[derive(Copy, Clone)]
-pub struct Driver(fn(ctx: &Binding, &str, Request) -> Result<String>);
-
-/// Need custom debug implementation
-impl std::fmt::Debug for Driver {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        Ok(())
-    }
-}
-
-/// NOTE we only impelement default for serde serializer...
-impl Default for Driver {
-    fn default() -> Self {
-        Driver(k64::request_raw)
-    }
-}
-
 /// Our binding needs a c compatible callback to redirect our logs
 unsafe extern "C" fn logger(s: *mut linq_sys::log_callback_s) {
     // TODO should batch these
@@ -98,35 +76,11 @@ impl Binding {
     }
 
     /// Get a string and parse it as an array of connected devices
-    pub fn scan(&mut self) -> Result<Vec<UsbMetadata>> {
+    pub fn scan(&mut self) -> Result<Vec<Summary>> {
         let e = unsafe { linq_sys::usbh_scan(self.binding) };
         Self::into_result(e as i32)?;
         serde_json::from_str::<Vec<Summary>>(&self.summary_raw())
-            .map_err(|x| IoError::Parser(x.to_string()))?
-            .iter()
-            .map(|x| {
-                let (pid, sid) = (x.product, &x.serial);
-                let (serial, driver) = match x.product {
-                    PID_K64 => k64::open(self, &sid),
-                    PID_M5 => m5::open(self, &sid),
-                    _ => {
-                        let e = format!("invalid pid [{}]", pid).to_owned();
-                        Err(UsbError::Protocol(e).into())
-                    }
-                }?;
-                Ok(UsbMetadata::new(&serial, Driver(driver), &x))
-            })
-            .collect()
-    }
-
-    /// Send a request to a usb channel
-    pub fn request<'a>(
-        &self,
-        serial: &'a str,
-        request: Request,
-        driver: Driver,
-    ) -> Result<String> {
-        driver.0(self, serial, request)
+            .map_err(|x| IoError::Parser(x.to_string()))
     }
 
     /// Translate our Rust types into C and send to binding
@@ -179,6 +133,33 @@ impl Drop for Binding {
     }
 }
 
+/// The default usb bus
+impl Backend for Binding {
+    fn add_product(&mut self, product: &Product) -> Result<()> {
+        let transport = match product.transport {
+            Transport::Hid => E_LINQ_TRANSPORT_LINQ_TRANSPORT_USB_HID,
+            Transport::Cdc => E_LINQ_TRANSPORT_LINQ_TRANSPORT_USB_CDC,
+        };
+        let (vid, pid) = (product.vid, product.pid);
+        unsafe {
+            linq_sys::usbh_add_product(self.binding, transport, vid, pid)
+        };
+        Ok(())
+    }
+
+    fn scan(&mut self) -> Result<Vec<Summary>> {
+        Binding::scan(self)
+    }
+
+    fn send(&self, serial: &str, bytes: &[u8]) -> Result<usize> {
+        Binding::send(self, serial, bytes)
+    }
+
+    fn recv(&self, serial: &str, bytes: &mut [u8]) -> Result<usize> {
+        Binding::recv(self, serial, bytes)
+    }
+}
+
 /// Concrete implementation for Usb Rx/Tx
 impl ReaderWriter for Binding {}
 
@@ -1,8 +1,10 @@
 use super::metadata::UsbMetadata;
 use super::usb::Usb;
-use crate::channel::{AsyncRequester, Channel, Meta};
+use crate::channel::{AboutCache, AsyncRequester, Channel, Meta};
 use crate::error::*;
+use crate::metadata::{DeviceMetadata, Location};
 use crate::request::Request;
+use linq_db::k64::Update;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::future::Future;
@@ -18,19 +20,21 @@ struct ErrorPacket {
 pub struct UsbChannel {
     pub meta: UsbMetadata,
     usb: Arc<Usb>,
+    about: AboutCache,
 }
 
 impl UsbChannel {
     /// Create a new USB channel instance (requires handle to USB context)
     pub fn new<'a>(usb: Arc<Usb>, meta: UsbMetadata) -> Self {
-        UsbChannel { usb, meta }
+        let about = AboutCache::new(meta.about.clone());
+        UsbChannel { usb, meta, about }
     }
 
     fn send<'str>(
         &self,
         _serial: &'str str, // Ignored (see note below)
         r: Request,
-    ) -> Pin<Box<dyn Future<Output = Result<String>>>>
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>>>>>
     where
         Self: Sized,
     {
@@ -42,7 +46,7 @@ impl UsbChannel {
         //      caller. Should USB devices fix their descriptors, then we can
         //      forward the regular serial IE: replace meta.sid w/ serial
         //      as first argument to request.
-        let f = self.usb.request(&self.meta.sid, r, self.meta.driver);
+        let f = self.usb.request(&self.meta.sid, r);
         Box::pin(async move {
             let response = f.await?;
             Ok(response)
@@ -52,24 +56,43 @@ impl UsbChannel {
 
 impl Channel for UsbChannel {}
 impl AsyncRequester for UsbChannel {
-    fn request_raw<'a>(
+    fn request_bytes<'a>(
         &'a self,
         _serial: &'a str, // Ignored (see note below)
         r: Request,
-    ) -> Pin<Box<dyn Future<Output = Result<String>> + 'a>>
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>>> + 'a>>
     where
         Self: Sized,
     {
-        let f = self.usb.request(&self.meta.sid, r, self.meta.driver);
+        let f = self.usb.request(&self.meta.sid, r);
         Box::pin(async move {
             let response = f.await?;
             Ok(response)
         })
     }
+
+    /// Update chunks go through the driver (see UsbDriver::update)
+    fn update<'a>(
+        &'a self,
+        _serial: &'a str, // Ignored (see note above)
+        update: &Update,
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + 'a>> {
+        Box::pin(self.usb.update(&self.meta.sid, update.clone()))
+    }
 }
 
 impl Meta for UsbChannel {
-    fn meta(&self) -> String {
-        serde_json::to_string(&self.meta).unwrap()
+    fn describe(&self, _serial: &str) -> DeviceMetadata {
+        let location = Location::Usb {
+            vid: self.meta.vid,
+            pid: self.meta.pid,
+            sid: self.meta.sid.clone(),
+        };
+        DeviceMetadata::new(&self.meta.serial, location)
+            .with_capabilities(self.meta.capabilities)
+    }
+
+    fn cache(&self) -> &AboutCache {
+        &self.about
     }
 }
@@ -0,0 +1,291 @@
+use super::super::backend::Backend;
+use super::super::metadata::Summary;
+use super::super::registry::Product;
+use super::driver::{Reader, ReaderWriter, Writer};
+use crate::error::*;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Which way a frame traveled
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Host to device
+    Out,
+    /// Device to host
+    In,
+}
+
+/// A single usb transfer. Failed transfers are captured too (with an error)
+/// so that timeouts are reproduced during replay
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Frame {
+    /// Microseconds since the capture started
+    pub time: u64,
+    pub direction: Direction,
+    pub serial: String,
+    #[serde(with = "hex")]
+    pub data: Vec<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Frames are stored as hex strings so capture files can be read by a human
+mod hex {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(b: &[u8], s: S) -> Result<S::Ok, S::Error> {
+        let hex: String = b.iter().map(|x| format!("{:02x}", x)).collect();
+        s.serialize_str(&hex)
+    }
+
+    pub fn deserialize<'de, D>(d: D) -> Result<Vec<u8>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(d)?;
+        if !s.is_ascii() {
+            return Err(D::Error::custom("hex is not ascii"));
+        }
+        if s.len() % 2 != 0 {
+            return Err(D::Error::custom("odd length hex"));
+        }
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(D::Error::custom)
+    }
+}
+
+/// Parse a capture file. (One JSON frame per line)
+pub fn parse(s: &str) -> Result<Vec<Frame>> {
+    s.lines()
+        .filter(|x| !x.trim().is_empty())
+        .map(|x| {
+            serde_json::from_str::<Frame>(x)
+                .map_err(|e| IoError::Parser(e.to_string()))
+        })
+        .collect()
+}
+
+/// Read a capture file from the file system
+pub fn load(path: &str) -> Result<Vec<Frame>> {
+    parse(&std::fs::read_to_string(path)?)
+}
+
+/// Write a capture file to the file system
+pub fn save(path: &str, frames: &[Frame]) -> Result<()> {
+    let mut file = File::create(path)?;
+    frames.iter().try_for_each(|x| write_frame(&mut file, x))
+}
+
+fn write_frame(w: &mut impl Write, frame: &Frame) -> Result<()> {
+    let line = serde_json::to_string(frame)
+        .map_err(|e| IoError::Parser(e.to_string()))?;
+    writeln!(w, "{}", line)?;
+    Ok(w.flush()?)
+}
+
+/// Wraps a Reader/Writer (or a usb Backend) and records every frame that
+/// passes through. When a file is given each frame is written as it happens
+/// so the capture survives a crash.
+pub struct Recorder<T> {
+    inner: T,
+    start: Instant,
+    frames: Mutex<Vec<Frame>>,
+    file: Option<Mutex<File>>,
+}
+
+impl<T> Recorder<T> {
+    /// Record frames in memory
+    pub fn new(inner: T) -> Self {
+        Recorder {
+            inner,
+            start: Instant::now(),
+            frames: Mutex::new(vec![]),
+            file: None,
+        }
+    }
+
+    /// Also write every frame to [file]
+    pub fn file(mut self, file: File) -> Self {
+        self.file = Some(Mutex::new(file));
+        self
+    }
+
+    /// Every frame recorded so far
+    pub fn frames(&self) -> Vec<Frame> {
+        self.frames.lock().unwrap().clone()
+    }
+
+    /// Stop recording and return the wrapped Reader/Writer
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn record(
+        &self,
+        direction: Direction,
+        serial: &str,
+        data: &[u8],
+        error: Option<&IoError>,
+    ) {
+        let frame = Frame {
+            time: self.start.elapsed().as_micros() as u64,
+            direction,
+            serial: serial.to_owned(),
+            data: data.to_vec(),
+            error: error.map(|e| e.to_string()),
+        };
+        if let Some(file) = &self.file {
+            // NOTE a broken capture file should not break the device
+            let _ = write_frame(&mut *file.lock().unwrap(), &frame);
+        }
+        self.frames.lock().unwrap().push(frame);
+    }
+
+    fn record_send(
+        &self,
+        serial: &str,
+        bytes: &[u8],
+        f: impl FnOnce() -> Result<usize>,
+    ) -> Result<usize> {
+        let result = f();
+        self.record(Direction::Out, serial, bytes, result.as_ref().err());
+        result
+    }
+
+    fn record_recv(
+        &self,
+        serial: &str,
+        bytes: &mut [u8],
+        f: impl FnOnce(&mut [u8]) -> Result<usize>,
+    ) -> Result<usize> {
+        let result = f(bytes);
+        let data = match result {
+            Ok(l) => &bytes[..l],
+            Err(_) => &[],
+        };
+        self.record(Direction::In, serial, data, result.as_ref().err());
+        result
+    }
+}
+
+impl<T: ReaderWriter> ReaderWriter for Recorder<T> {}
+
+impl<T: ReaderWriter> Writer for Recorder<T> {
+    fn write(&self, s: &str, bytes: &[u8]) -> Result<usize> {
+        self.record_send(s, bytes, || self.inner.write(s, bytes))
+    }
+}
+
+impl<T: ReaderWriter> Reader for Recorder<T> {
+    fn read(&self, s: &str, bytes: &mut [u8]) -> Result<usize> {
+        self.record_recv(s, bytes, |b| self.inner.read(s, b))
+    }
+}
+
+impl<T: Backend> Backend for Recorder<T> {
+    fn add_product(&mut self, product: &Product) -> Result<()> {
+        self.inner.add_product(product)
+    }
+
+    fn scan(&mut self) -> Result<Vec<Summary>> {
+        self.inner.scan()
+    }
+
+    fn send(&self, serial: &str, bytes: &[u8]) -> Result<usize> {
+        self.record_send(serial, bytes, || self.inner.send(serial, bytes))
+    }
+
+    fn recv(&self, serial: &str, bytes: &mut [u8]) -> Result<usize> {
+        self.record_recv(serial, bytes, |b| self.inner.recv(serial, b))
+    }
+}
+
+/// Plays a capture back to a driver. Writes must match the capture exactly
+/// and reads return what the device sent at the time. Useful to reproduce
+/// field failures in unit tests IE:
+///
+/// k64::request_raw(&Replay::load("field.cap")?, "", Request::get(path))
+pub struct Replay {
+    frames: Mutex<VecDeque<Frame>>,
+}
+
+impl Replay {
+    pub fn new(frames: Vec<Frame>) -> Self {
+        Replay {
+            frames: Mutex::new(frames.into()),
+        }
+    }
+
+    /// Replay a capture file
+    pub fn load(path: &str) -> Result<Self> {
+        Ok(Replay::new(load(path)?))
+    }
+
+    /// Frames the driver has not consumed yet
+    pub fn remaining(&self) -> usize {
+        self.frames.lock().unwrap().len()
+    }
+
+    /// Take the next frame if it went in [direction]
+    fn next(&self, direction: Direction) -> Result<Frame> {
+        let mut frames = self.frames.lock().unwrap();
+        match frames.front() {
+            Some(f) if f.direction == direction => {
+                Ok(frames.pop_front().unwrap())
+            }
+            Some(f) => {
+                let e =
+                    format!("replay expected {:?} at {}", f.direction, f.time);
+                Err(UsbError::Protocol(e).into())
+            }
+            None => Err(UsbError::Protocol("replay finished".into()).into()),
+        }
+    }
+}
+
+impl ReaderWriter for Replay {}
+
+impl Writer for Replay {
+    fn write(&self, _: &str, bytes: &[u8]) -> Result<usize> {
+        let frame = self.next(Direction::Out)?;
+        if frame.data != bytes {
+            let e = format!("replay diverged at {}", frame.time);
+            return Err(UsbError::Protocol(e).into());
+        }
+        match frame.error {
+            Some(e) => Err(UsbError::Protocol(e).into()),
+            None => Ok(bytes.len()),
+        }
+    }
+}
+
+impl Reader for Replay {
+    fn read(&self, _: &str, bytes: &mut [u8]) -> Result<usize> {
+        let frame = self.next(Direction::In)?;
+        match frame.error {
+            Some(e) => Err(UsbError::Protocol(e).into()),
+            None if frame.data.len() > bytes.len() => {
+                let e = format!(
+                    "replay frame at {} is {} bytes (expected at most {})",
+                    frame.time,
+                    frame.data.len(),
+                    bytes.len()
+                );
+                Err(UsbError::Protocol(e).into())
+            }
+            None => {
+                bytes[..frame.data.len()].copy_from_slice(&frame.data);
+                Ok(frame.data.len())
+            }
+        }
+    }
+}
@@ -1,5 +1,9 @@
+use super::super::backend::Backend;
+use crate::capabilities::Capabilities;
 use crate::error::*;
 use crate::request::Request;
+use linq_db::k64::{About, Update};
+use std::sync::Arc;
 
 /// A  Devices implement there on forms of reading and writing
 pub trait ReaderWriter: Writer + Reader {}
@@ -13,3 +17,48 @@ pub trait Writer {
 pub trait Reader {
     fn read<'a>(&self, s: &'a str, bytes: &mut [u8]) -> Result<usize>;
 }
+
+/// Speaks to a single usb device. A driver is created for every device of a
+/// registered product found during scan, so drivers may keep per device
+/// state. (Every method is called from the usb thread)
+pub trait UsbDriver {
+    /// Called once when the device is found. Returns the serial number the
+    /// device is addressed by from then on
+    fn open(&mut self, bus: &dyn Backend, sid: &str) -> Result<String>;
+
+    /// Make a request to the device
+    fn request(
+        &mut self,
+        bus: &dyn Backend,
+        sid: &str,
+        r: Request,
+    ) -> Result<Vec<u8>>;
+
+    /// Send one chunk of an update to the device
+    fn update(
+        &mut self,
+        bus: &dyn Backend,
+        sid: &str,
+        update: &Update,
+    ) -> Result<()> {
+        let r = Request::post("/ATX/exe/update", update);
+        self.request(bus, sid, r).map(|_| ())
+    }
+
+    /// What the device can do. (Discovered during open)
+    fn capabilities(&self) -> Capabilities;
+
+    /// What the device reported at /ATX/about during open. (None if the
+    /// product does not describe itself)
+    fn about(&self) -> Option<About> {
+        None
+    }
+
+    /// Called when the device is forgotten. (IE: on rescan or close)
+    fn close(&mut self, _bus: &dyn Backend, _sid: &str) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Creates a driver for each device of a product. (See ProductRegistry)
+pub type DriverFactory = Arc<dyn Fn() -> Box<dyn UsbDriver> + Send + Sync>;
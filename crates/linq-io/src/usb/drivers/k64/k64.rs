@@ -1,10 +1,13 @@
-use super::super::driver::{Reader, ReaderWriter};
+use super::super::super::backend::Backend;
+use super::super::driver::{Reader, ReaderWriter, UsbDriver};
 use super::packet;
-use crate::error::{ApiError, IoError, Result, UsbError};
-use crate::request::Request;
+use crate::capabilities::Capabilities;
+use crate::error::{translate_response, ApiError, ApiErrorKind};
+use crate::error::{IoError, Result, UsbError};
+use crate::request::{Encoding, Request};
 use linq_db::k64::{About, AboutResponse};
 use linq_util::log::*;
-use packet::{ACK, IO_SIZE, PREAMBLE};
+use packet::{Framing, ACK, IO_SIZE, PREAMBLE};
 use serde::de::DeserializeOwned;
 use serde_json::Value;
 
@@ -12,8 +15,25 @@ gen_log_helpers!("K64");
 
 pub const MAX_RETRY: u8 = 3;
 
+/// Wire options used when talking to a K64. Legacy firmware only understands
+/// the default options.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Protocol {
+    pub framing: Framing,
+    pub encoding: Encoding,
+}
+
+impl Default for Protocol {
+    fn default() -> Self {
+        Protocol {
+            framing: Framing::Legacy,
+            encoding: Encoding::NullTerminated,
+        }
+    }
+}
+
 /// Helper to make sure we have a valid ack
-fn read_ack<'a>(ctx: &impl Reader, s: &'a str) -> Result<()> {
+fn read_ack<'a>(ctx: &(impl Reader + ?Sized), s: &'a str) -> Result<()> {
     debug!("[{}] read_ack", s);
     let l: usize;
     let mut incoming: [u8; IO_SIZE] = [0; IO_SIZE];
@@ -29,7 +49,7 @@ fn read_ack<'a>(ctx: &impl Reader, s: &'a str) -> Result<()> {
 
 /// NOTE The USB on the OS level can have cached incoming bytes. This will
 ///      flush out what ever is pending so we can start from a fresh state
-fn flush(ctx: &impl Reader, s: &str) {
+fn flush(ctx: &(impl Reader + ?Sized), s: &str) {
     let mut incoming: [u8; IO_SIZE] = [0; IO_SIZE];
     loop {
         let l = ctx.read(s, &mut incoming);
@@ -41,7 +61,7 @@ fn flush(ctx: &impl Reader, s: &str) {
 }
 
 /// Helper to make sure we have a valid preamble
-fn read_preamble<'a>(ctx: &impl Reader, s: &'a str) -> Result<()> {
+fn read_preamble<'a>(ctx: &(impl Reader + ?Sized), s: &'a str) -> Result<()> {
     debug!("[{}] read_preamble", s);
     let l: usize;
     let mut incoming: [u8; IO_SIZE] = [0; IO_SIZE];
@@ -57,7 +77,7 @@ fn read_preamble<'a>(ctx: &impl Reader, s: &'a str) -> Result<()> {
 
 /// Helper method to parse caller data if type is serializable
 pub fn request<R: DeserializeOwned>(
-    ctx: &impl ReaderWriter,
+    ctx: &(impl ReaderWriter + ?Sized),
     sid: &str,
     r: Request,
 ) -> Result<R> {
@@ -69,95 +89,100 @@ pub fn request<R: DeserializeOwned>(
 
 /// Attempt to make a request
 pub fn make_request(
-    ctx: &impl ReaderWriter,
+    ctx: &(impl ReaderWriter + ?Sized),
     sid: &str,
-    r: &Request,
-) -> Result<String> {
+    p: &packet::Packets,
+) -> Result<Vec<u8>> {
     let mut incoming: [u8; IO_SIZE] = [0; IO_SIZE];
-    let (len, packets) = packet::from_request(r);
+    let (len, packets) = p;
     ctx.write(&sid, &PREAMBLE)?;
     read_ack(ctx, &sid)?;
-    ctx.write(&sid, &len)?;
+    ctx.write(&sid, len)?;
     for p in packets {
         read_ack(ctx, &sid)?;
-        ctx.write(&sid, &p)?;
+        ctx.write(&sid, p)?;
     }
     ctx.read(&sid, &mut incoming)?;
     if incoming != PREAMBLE {
         // Short mode
         debug!("{}", "received short mode packet");
-        let mut v = vec![[0; IO_SIZE]; 1];
-        v[0] = incoming;
-        Ok(packet::to_string(&v)?)
+        Ok(packet::to_bytes_short(&incoming))
     } else {
         // Long mode
         debug!("{}", "received long mode packet");
         ctx.write(&sid, &ACK)?;
         ctx.read(&sid, &mut incoming)?; // Length
-        let (_, chunks) = packet::to_len_chunks(&incoming);
+        let (size, chunks) = packet::to_len_chunks(&incoming);
         let mut v = vec![[0; IO_SIZE]; chunks];
         for i in 0..chunks {
             ctx.write(&sid, &ACK)?;
             ctx.read(&sid, &mut v[i])?;
         }
-        Ok(packet::to_string(&v)?)
-    }
-}
-
-/// Usb protocol does not support transmitting the error code with the
-/// response. So we peek at the response and parse it to see if it is an
-/// {\"error\": code} object. If it is, we translate this from Ok to Err
-/// to present sane behavior to caller.
-pub fn translate_error(response: String) -> Result<String> {
-    let packet = serde_json::from_str::<Value>(&response);
-    let packet = if let Ok(p) = packet {
-        p.as_object()
-            .and_then(|object| {
-                if object.keys().len() == 1
-                    && object.contains_key("error")
-                    && object["error"].is_number()
-                {
-                    Some(object["error"].as_i64())
-                } else {
-                    None
-                }
-            })
-            .and_then(|x| match x {
-                Some(200) => None,
-                Some(400) => Some(ApiError::Linq400),
-                Some(403) => Some(ApiError::Linq403),
-                Some(404) => Some(ApiError::Linq404),
-                Some(500) => Some(ApiError::Linq500),
-                Some(504) => Some(ApiError::Linq504),
-                _ => Some(ApiError::LinqUnknown),
-            })
-    } else {
-        None
-    };
-    match packet {
-        Some(e) => Err(e.into()),
-        None => Ok(response),
+        Ok(packet::to_bytes(&v, size))
     }
 }
 
 /// Drive some packets and make a request to a K64 USB device
 pub fn request_raw(
-    ctx: &impl ReaderWriter,
+    ctx: &(impl ReaderWriter + ?Sized),
     sid: &str,
     r: Request,
 ) -> Result<String> {
+    request_bytes(ctx, sid, r).and_then(|x| {
+        String::from_utf8(x)
+            .map(|s| s.trim_matches(char::from(0)).to_owned())
+            .map_err(|_| IoError::Parser("bad utf8".to_string()))
+    })
+}
+
+/// Same as request_raw except the response is not required to be text
+pub fn request_bytes(
+    ctx: &(impl ReaderWriter + ?Sized),
+    sid: &str,
+    r: Request,
+) -> Result<Vec<u8>> {
+    request_bytes_with(ctx, sid, r, Protocol::default())
+}
+
+/// Same as request_bytes except caller picks the wire options. Only stray
+/// from the defaults with firmware known to support it. A request too large
+/// for the framing is rejected before anything is written to the device.
+pub fn request_bytes_with(
+    ctx: &(impl ReaderWriter + ?Sized),
+    sid: &str,
+    r: Request,
+    protocol: Protocol,
+) -> Result<Vec<u8>> {
+    let Protocol { framing, encoding } = protocol;
+    let packets = packet::from_request_with(&r, framing, encoding)?;
+    request_packets(ctx, sid, &r.path, packets)
+}
+
+/// Drive a request that has already been framed into packets. Usb protocol
+/// does not support transmitting the error code with the response, so the
+/// response is translated into an ApiError if it is an error object.
+fn request_packets(
+    ctx: &(impl ReaderWriter + ?Sized),
+    sid: &str,
+    path: &str,
+    packets: packet::Packets,
+) -> Result<Vec<u8>> {
     let mut retry: u8 = 0;
-    let mut result: Result<String>;
+    let mut result: Result<Vec<u8>>;
 
     // This loop is designed to shovel our shit into the K64 no matter how much
     // it kicks and screams. Should this loop fail us, then either the K64 has
     // been unplugged, or we killed it!
     loop {
-        result = make_request(ctx, sid, &r).and_then(|r| translate_error(r));
+        result = make_request(ctx, sid, &packets)
+            .and_then(|r| translate_response(path, None, r));
         debug!("{:.10?}", result);
         match &result {
             Ok(_) => break,
-            Err(IoError::ApiError(ApiError::Linq504)) => {
+            Err(IoError::ApiError(ApiError {
+                kind: ApiErrorKind::Linq504,
+                ..
+            })) => {
                 // TODO inject time delay
                 retry = retry + 1;
             }
@@ -180,20 +205,24 @@ pub fn request_raw(
     result
 }
 
-/// Return a driver handle for a K64 USB device
-pub fn open<T: ReaderWriter>(
-    ctx: &T,
-    sid: &str,
-) -> Result<(String, fn(ctx: &T, sid: &str, r: Request) -> Result<String>)> {
+/// Read the serial number of a K64 USB device
+pub fn open(ctx: &(impl ReaderWriter + ?Sized), sid: &str) -> Result<String> {
     info!("[{}] open", sid);
+    about(ctx, sid).map(|x| x.about.sid)
+}
+
+/// Read /ATX/about from a K64 USB device. (The device is retried while it
+/// settles after being plugged in)
+pub fn about(
+    ctx: &(impl ReaderWriter + ?Sized),
+    sid: &str,
+) -> Result<AboutResponse> {
     let mut retry = 0;
     loop {
-        let response: Result<AboutResponse> =
-            request(ctx, &sid, Request::get("/ATX/about"));
-        match response {
-            Ok(a) => return Ok((a.about.sid, request_raw)),
+        match request(ctx, sid, Request::get("/ATX/about")) {
+            Ok(a) => return Ok(a),
             Err(e) => {
-                retry = retry + 1;
+                retry += 1;
                 if retry > MAX_RETRY {
                     return Err(e);
                 }
@@ -201,3 +230,93 @@ pub fn open<T: ReaderWriter>(
         }
     }
 }
+
+/// Discover what a K64 USB device can do. Firmware that describes itself
+/// at /ATX/about/capabilities is trusted. Older firmware always accepts
+/// updates (with website images) and reboots, so only network support is
+/// probed for. (Older firmware only reads legacy length packets)
+pub fn capabilities(
+    ctx: &(impl ReaderWriter + ?Sized),
+    sid: &str,
+    protocol: Protocol,
+) -> Capabilities {
+    let max_payload = protocol.framing.max_len();
+    let r = Request::get("/ATX/about/capabilities");
+    let described = request_bytes_with(ctx, sid, r, protocol)
+        .ok()
+        .and_then(|x| serde_json::from_slice::<Value>(&x).ok())
+        .and_then(|mut x| x.get_mut("capabilities").map(Value::take))
+        .and_then(|x| serde_json::from_value::<Capabilities>(x).ok());
+    match described {
+        Some(c) => c.limit(max_payload),
+        None => {
+            let r = Request::get("/ATX/network");
+            let network = request_bytes_with(ctx, sid, r, protocol).is_ok();
+            Capabilities {
+                update: true,
+                website: true,
+                network,
+                reboot: true,
+                max_payload: Framing::Legacy.max_len(),
+            }
+        }
+    }
+}
+
+/// The K64 usb driver
+#[derive(Default)]
+pub struct K64 {
+    protocol: Protocol,
+    capabilities: Capabilities,
+    about: Option<About>,
+}
+
+impl K64 {
+    pub fn new() -> Self {
+        K64::default()
+    }
+
+    /// Same as new except speak to the device with [protocol]
+    pub fn with_protocol(protocol: Protocol) -> Self {
+        K64 {
+            protocol,
+            ..K64::default()
+        }
+    }
+}
+
+impl UsbDriver for K64 {
+    fn open(&mut self, bus: &dyn Backend, sid: &str) -> Result<String> {
+        info!("[{}] open", sid);
+        let about = about(bus, sid)?.about;
+        let serial = about.sid.clone();
+        // Ask with extended framing so the firmware can say how large a
+        // payload it takes, then only speak extended framing when it does
+        let probe = Protocol {
+            framing: Framing::Extended,
+            ..self.protocol
+        };
+        let capabilities = capabilities(bus, sid, probe);
+        self.protocol.framing = Framing::from_capabilities(&capabilities);
+        self.capabilities = capabilities.limit(self.protocol.framing.max_len());
+        self.about = Some(about);
+        Ok(serial)
+    }
+
+    fn request(
+        &mut self,
+        bus: &dyn Backend,
+        sid: &str,
+        r: Request,
+    ) -> Result<Vec<u8>> {
+        request_bytes_with(bus, sid, r, self.protocol)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    fn about(&self) -> Option<About> {
+        self.about.clone()
+    }
+}
@@ -2,6 +2,8 @@
 mod tests;
 
 mod k64;
-mod packet;
+pub mod packet;
+mod sim;
 
 pub use k64::*;
+pub use sim::{Fault, Simulator};
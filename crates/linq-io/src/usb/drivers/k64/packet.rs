@@ -1,5 +1,8 @@
+use super::super::capture::{Direction, Frame};
+use crate::capabilities::Capabilities;
 use crate::error::*;
-use crate::request::Request;
+use crate::request::{Body, Encoding, Request};
+use std::collections::HashMap;
 /// Supported transfer size of HID protocol
 pub const IO_SIZE: usize = 64;
 
@@ -17,48 +20,129 @@ pub const ACK: [u8; IO_SIZE] = [
     0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
 ];
 
+/// Largest payload a legacy (2 byte) length packet can describe
+pub const MAX_LEN: usize = 0xFFFF;
+
+/// Largest payload an extended (4 byte) length packet can describe
+pub const MAX_LEN_EXTENDED: usize = 0xFFFF_FFFF;
+
 /// Struct for helping parse and manipulate HID packets in 64 byte chunks
 pub type Packets = ([u8; IO_SIZE], Vec<[u8; IO_SIZE]>);
 
+/// How the length packet is framed on the wire. Legacy firmware only reads
+/// the first 2 bytes of the length packet. Extended framing keeps the legacy
+/// layout for small payloads and only for payloads larger then [MAX_LEN] sets
+/// the first 2 bytes to 0xFFFF and stores a little endian u32 in bytes 2..6.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Framing {
+    Legacy,
+    Extended,
+}
+
+impl Framing {
+    /// The framing to speak with firmware that takes payloads as large as
+    /// [capabilities]. Extended framing is only used when the firmware says
+    /// it takes more than a legacy length packet can describe
+    pub fn from_capabilities(capabilities: &Capabilities) -> Framing {
+        match capabilities.max_payload > MAX_LEN {
+            true => Framing::Extended,
+            false => Framing::Legacy,
+        }
+    }
+
+    /// The largest payload this framing can describe
+    pub fn max_len(&self) -> usize {
+        match self {
+            Framing::Legacy => MAX_LEN,
+            Framing::Extended => MAX_LEN_EXTENDED,
+        }
+    }
+}
+
 /// Take a length packet and convert into usable number
 pub fn to_len(packet: &[u8; IO_SIZE]) -> usize {
-    usize::from(packet[0]) + (usize::from(packet[1]) << 8)
+    let legacy = usize::from(packet[0]) + (usize::from(packet[1]) << 8);
+    if legacy == MAX_LEN {
+        // NOTE a legacy packet describing exactly MAX_LEN has zeros here. An
+        //      extended packet is only ever used for lengths above MAX_LEN
+        let mut ext: [u8; 4] = [0; 4];
+        ext.copy_from_slice(&packet[2..6]);
+        let ext = u32::from_le_bytes(ext) as usize;
+        if ext > MAX_LEN {
+            return ext;
+        }
+    }
+    legacy
 }
 
 /// Take a length packet and calculate how many chunks
 pub fn to_len_chunks(packet: &[u8; IO_SIZE]) -> (usize, usize) {
     let size = to_len(packet);
-    let chunks = if size % IO_SIZE == 0 {
-        size / IO_SIZE
-    } else {
-        size / IO_SIZE + 1
-    };
-    (size, chunks)
+    (size, to_chunks(size))
+}
+
+/// How many IO_SIZE chunks are needed to carry a payload of size [l]
+pub fn to_chunks(l: usize) -> usize {
+    l.div_ceil(IO_SIZE)
 }
 
 /// Prepare a length for transmit in packet frame
-pub fn from_len(l: usize) -> [u8; IO_SIZE] {
+pub fn from_len(l: usize) -> Result<[u8; IO_SIZE]> {
+    from_len_framed(l, Framing::Legacy)
+}
+
+/// Prepare a length for transmit in packet frame with explicit framing
+pub fn from_len_framed(l: usize, framing: Framing) -> Result<[u8; IO_SIZE]> {
+    if l > framing.max_len() {
+        return Err(UsbError::Overflow(l, framing.max_len()).into());
+    }
     let mut u: [u8; IO_SIZE] = [0; IO_SIZE];
-    u[0] = l as u8;
-    u[1] = (l / 256) as u8;
-    u
+    if l > MAX_LEN {
+        u[0] = 0xFF;
+        u[1] = 0xFF;
+        u[2..6].copy_from_slice(&(l as u32).to_le_bytes());
+    } else {
+        u[0] = l as u8;
+        u[1] = (l / 256) as u8;
+    }
+    Ok(u)
 }
 
 /// Convert a request into packets for transfer
-pub fn from_request(r: &Request) -> Packets {
-    from_str(r.format_with_null_terminators().as_ref())
+pub fn from_request(r: &Request) -> Result<Packets> {
+    from_bytes(&r.request_bytes())
+}
+
+/// Convert a request into packets for transfer with explicit framing and
+/// request encoding
+pub fn from_request_with(
+    r: &Request,
+    framing: Framing,
+    encoding: Encoding,
+) -> Result<Packets> {
+    from_bytes_framed(&r.encode(encoding), framing)
 }
 
 /// We have a string and want some packets
-pub fn from_str(s: &str) -> Packets {
-    let rlen = s.len();
-    let len: [u8; IO_SIZE] = from_len(rlen);
-    let n = if rlen % IO_SIZE == 0 {
-        rlen / IO_SIZE
-    } else {
-        rlen / IO_SIZE + 1
-    };
-    let p: Vec<[u8; IO_SIZE]> = (0..n)
+pub fn from_str(s: &str) -> Result<Packets> {
+    from_bytes(s.as_bytes())
+}
+
+/// We have a string and want some packets with explicit framing
+pub fn from_str_framed(s: &str, framing: Framing) -> Result<Packets> {
+    from_bytes_framed(s.as_bytes(), framing)
+}
+
+/// We have some bytes and want some packets
+pub fn from_bytes(b: &[u8]) -> Result<Packets> {
+    from_bytes_framed(b, Framing::Legacy)
+}
+
+/// We have some bytes and want some packets with explicit framing
+pub fn from_bytes_framed(b: &[u8], framing: Framing) -> Result<Packets> {
+    let rlen = b.len();
+    let len: [u8; IO_SIZE] = from_len_framed(rlen, framing)?;
+    let p: Vec<[u8; IO_SIZE]> = (0..to_chunks(rlen))
         .map(|i| {
             let mut v: [u8; IO_SIZE] = [0; IO_SIZE];
             let start = i * IO_SIZE;
@@ -67,11 +151,11 @@ pub fn from_str(s: &str) -> Packets {
             } else {
                 IO_SIZE
             };
-            v[..c].copy_from_slice(&s[start..start + c].as_bytes());
+            v[..c].copy_from_slice(&b[start..start + c]);
             v
         })
         .collect();
-    (len, p)
+    Ok((len, p))
 }
 
 /// We collected a stream of packets into an array and we want them as packet
@@ -93,3 +177,374 @@ pub fn to_string(p: &Vec<[u8; IO_SIZE]>) -> Result<String> {
         })
         .collect()
 }
+
+/// We have some packets and the length of the payload and want the payload.
+/// Unlike to_string this does not touch NUL bytes inside of the payload.
+pub fn to_bytes(p: &[[u8; IO_SIZE]], len: usize) -> Vec<u8> {
+    let mut v: Vec<u8> = p.iter().flat_map(|x| x.iter().copied()).collect();
+    v.truncate(len);
+    v
+}
+
+/// A short mode response does not have a length so we only trim the padding
+pub fn to_bytes_short(p: &[u8; IO_SIZE]) -> Vec<u8> {
+    let len = p.iter().rposition(|x| *x != 0).map_or(0, |x| x + 1);
+    p[..len].to_vec()
+}
+
+/// What a frame means in the context of a K64 exchange
+#[derive(Clone, Debug, PartialEq)]
+pub enum Kind {
+    Preamble,
+    Ack,
+    /// Length packet describing a payload of n bytes
+    Length(usize),
+    /// Data chunk i of n (counting from 1)
+    Data(usize, usize),
+    /// A response small enough to fit in a single packet
+    Short,
+    /// The transfer failed (IE: a read timed out)
+    Failed(String),
+    /// A frame we can not make sense of
+    Unknown,
+}
+
+impl std::fmt::Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Kind::Preamble => write!(f, "PREAMBLE"),
+            Kind::Ack => write!(f, "ACK"),
+            Kind::Length(n) => write!(f, "LENGTH {}", n),
+            Kind::Data(i, n) => write!(f, "DATA {}/{}", i, n),
+            Kind::Short => write!(f, "SHORT"),
+            Kind::Failed(e) => write!(f, "FAILED {}", e),
+            Kind::Unknown => write!(f, "UNKNOWN"),
+        }
+    }
+}
+
+/// A decoded frame
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    pub time: u64,
+    pub serial: String,
+    pub direction: Direction,
+    pub kind: Kind,
+    /// Set when the frame breaks the protocol
+    pub violation: Option<String>,
+}
+
+/// A request and (if the device answered) the response rebuilt from frames
+#[derive(Clone, Debug, PartialEq)]
+pub struct Exchange {
+    pub serial: String,
+    pub request: Vec<u8>,
+    pub response: Option<Vec<u8>>,
+    /// Index of the last event of this exchange
+    pub end: usize,
+}
+
+/// Every frame of a capture decoded, and every exchange rebuilt
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Trace {
+    pub events: Vec<Event>,
+    pub exchanges: Vec<Exchange>,
+}
+
+impl Trace {
+    /// All the protocol violations in the trace
+    pub fn violations(&self) -> Vec<&Event> {
+        self.events
+            .iter()
+            .filter(|x| x.violation.is_some())
+            .collect()
+    }
+}
+
+/// Where a device is in an exchange, from the point of view of the host
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Phase {
+    /// Host sends PREAMBLE
+    Idle,
+    /// Device ACKs the PREAMBLE, host sends the length
+    Ack,
+    Length,
+    /// Device ACKs, host sends chunk i of n
+    AckChunk(usize, usize),
+    Chunk(usize, usize),
+    /// Device sends a short response or a PREAMBLE
+    Response,
+    /// Host ACKs, device sends the length
+    HostAck,
+    ResponseLength,
+    /// Host ACKs, device sends chunk i of n
+    HostAckChunk(usize, usize),
+    ResponseChunk(usize, usize),
+}
+
+/// Decoder state for a single device
+struct Decoder {
+    phase: Phase,
+    len: usize,
+    buffer: Vec<[u8; IO_SIZE]>,
+    /// The current exchange failed, so the host is expected to flush and retry
+    failed: bool,
+    exchange: Option<usize>,
+}
+
+impl Decoder {
+    fn new() -> Self {
+        Decoder {
+            phase: Phase::Idle,
+            len: 0,
+            buffer: vec![],
+            failed: false,
+            exchange: None,
+        }
+    }
+
+    /// Classify a frame without any context
+    fn classify(p: &[u8; IO_SIZE]) -> Kind {
+        if *p == PREAMBLE {
+            Kind::Preamble
+        } else if *p == ACK {
+            Kind::Ack
+        } else {
+            Kind::Unknown
+        }
+    }
+
+    /// Decode the next frame. Returns what the frame is, any violation, and
+    /// a request or response that was completed by this frame
+    fn next(
+        &mut self,
+        direction: Direction,
+        p: &[u8; IO_SIZE],
+    ) -> (Kind, Option<String>, Option<Completed>) {
+        use Direction::{In, Out};
+        let kind = Decoder::classify(p);
+        let phase = self.phase;
+        let expected =
+            move |what: &str| format!("expected {} during {:?}", what, phase);
+        match (self.phase, direction, &kind) {
+            (_, Out, Kind::Preamble) => {
+                let violation = match (self.phase, self.failed) {
+                    (Phase::Idle, _) | (_, true) => None,
+                    _ => Some(format!("PREAMBLE during {:?}", self.phase)),
+                };
+                self.phase = Phase::Ack;
+                self.failed = false;
+                self.buffer.clear();
+                (kind, violation, None)
+            }
+            (Phase::Ack, In, Kind::Ack) => {
+                self.phase = Phase::Length;
+                (kind, None, None)
+            }
+            (Phase::Length, Out, _) => {
+                self.len = to_len(p);
+                self.phase = match to_chunks(self.len) {
+                    0 => Phase::Response,
+                    n => Phase::AckChunk(1, n),
+                };
+                let done = match self.phase {
+                    Phase::Response => Some(Completed::Request(vec![])),
+                    _ => None,
+                };
+                (Kind::Length(self.len), None, done)
+            }
+            (Phase::AckChunk(i, n), In, Kind::Ack) => {
+                self.phase = Phase::Chunk(i, n);
+                (kind, None, None)
+            }
+            (Phase::Chunk(i, n), Out, _) => {
+                self.buffer.push(*p);
+                if i == n {
+                    self.phase = Phase::Response;
+                    let request = to_bytes(&self.buffer, self.len);
+                    self.buffer.clear();
+                    let done = Some(Completed::Request(request));
+                    (Kind::Data(i, n), None, done)
+                } else {
+                    self.phase = Phase::AckChunk(i + 1, n);
+                    (Kind::Data(i, n), None, None)
+                }
+            }
+            (Phase::Response, In, Kind::Preamble) => {
+                self.phase = Phase::HostAck;
+                (kind, None, None)
+            }
+            (Phase::Response, In, _) => {
+                self.phase = Phase::Idle;
+                let done = Some(Completed::Response(to_bytes_short(p)));
+                (Kind::Short, None, done)
+            }
+            (Phase::HostAck, Out, Kind::Ack) => {
+                self.phase = Phase::ResponseLength;
+                (kind, None, None)
+            }
+            (Phase::ResponseLength, In, _) => {
+                self.len = to_len(p);
+                let (kind, done) = match to_chunks(self.len) {
+                    0 => {
+                        self.phase = Phase::Idle;
+                        (Kind::Length(0), Some(Completed::Response(vec![])))
+                    }
+                    n => {
+                        self.phase = Phase::HostAckChunk(1, n);
+                        (Kind::Length(self.len), None)
+                    }
+                };
+                (kind, None, done)
+            }
+            (Phase::HostAckChunk(i, n), Out, Kind::Ack) => {
+                self.phase = Phase::ResponseChunk(i, n);
+                (kind, None, None)
+            }
+            (Phase::ResponseChunk(i, n), In, _) => {
+                self.buffer.push(*p);
+                if i == n {
+                    self.phase = Phase::Idle;
+                    let response = to_bytes(&self.buffer, self.len);
+                    self.buffer.clear();
+                    let done = Some(Completed::Response(response));
+                    (Kind::Data(i, n), None, done)
+                } else {
+                    self.phase = Phase::HostAckChunk(i + 1, n);
+                    (Kind::Data(i, n), None, None)
+                }
+            }
+            // The host is flushing stale frames after a failure
+            (_, In, _) if self.failed => (kind, None, None),
+            (Phase::Ack, _, _)
+            | (Phase::AckChunk(..), _, _)
+            | (Phase::HostAck, _, _)
+            | (Phase::HostAckChunk(..), _, _) => {
+                let violation = Some(expected("ACK"));
+                self.phase = Phase::Idle;
+                (kind, violation, None)
+            }
+            (Phase::Idle, _, _) => (kind, Some(expected("PREAMBLE")), None),
+            _ => {
+                let violation = Some(format!(
+                    "unexpected {:?} frame during {:?}",
+                    direction, self.phase
+                ));
+                self.phase = Phase::Idle;
+                (kind, violation, None)
+            }
+        }
+    }
+}
+
+/// Something a frame finished rebuilding
+enum Completed {
+    Request(Vec<u8>),
+    Response(Vec<u8>),
+}
+
+/// Decode a capture into a trace of K64 packet exchanges. Frames from more
+/// than one device may be interleaved.
+pub fn decode(frames: &[Frame]) -> Trace {
+    let mut decoders: HashMap<&str, Decoder> = HashMap::new();
+    let mut trace = Trace::default();
+    for frame in frames {
+        let decoder =
+            decoders.entry(&frame.serial).or_insert_with(Decoder::new);
+        let (kind, violation, done) = match (&frame.error, frame.data.len()) {
+            (Some(e), _) => {
+                decoder.failed = true;
+                (Kind::Failed(e.to_owned()), None, None)
+            }
+            (None, IO_SIZE) => {
+                let mut p = [0; IO_SIZE];
+                p.copy_from_slice(&frame.data);
+                decoder.next(frame.direction, &p)
+            }
+            (None, n) => {
+                let violation = format!("frame of {} bytes", n);
+                (Kind::Unknown, Some(violation), None)
+            }
+        };
+        let end = trace.events.len();
+        match done {
+            Some(Completed::Request(request)) => {
+                decoder.exchange = Some(trace.exchanges.len());
+                trace.exchanges.push(Exchange {
+                    serial: frame.serial.to_owned(),
+                    request,
+                    response: None,
+                    end,
+                });
+            }
+            Some(Completed::Response(response)) => {
+                if let Some(i) = decoder.exchange.take() {
+                    trace.exchanges[i].response = Some(response);
+                    trace.exchanges[i].end = end;
+                }
+            }
+            None => (),
+        }
+        trace.events.push(Event {
+            time: frame.time,
+            serial: frame.serial.to_owned(),
+            direction: frame.direction,
+            kind,
+            violation,
+        });
+    }
+    trace
+}
+
+/// Render a request as text IE: "GET /ATX/about". Requests in an unknown
+/// encoding are rendered as is.
+pub fn describe_request(bytes: &[u8]) -> String {
+    let request = Request::decode(bytes, Encoding::NullTerminated)
+        .or_else(|_| Request::decode(bytes, Encoding::LengthPrefixed));
+    match request {
+        Ok(r) => match &r.body {
+            Body::Empty => format!("{} {}", r.method, r.path_and_query()),
+            Body::Text(b) => {
+                format!("{} {} {}", r.method, r.path_and_query(), b)
+            }
+            Body::Binary(b) => format!(
+                "{} {} <{} bytes>",
+                r.method,
+                r.path_and_query(),
+                b.len()
+            ),
+        },
+        Err(_) => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+impl std::fmt::Display for Event {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let arrow = match self.direction {
+            Direction::Out => "->",
+            Direction::In => "<-",
+        };
+        let time = self.time as f64 / 1_000_000.0;
+        write!(f, "[{:12.6}] {} {} {}", time, self.serial, arrow, self.kind)?;
+        match &self.violation {
+            Some(v) => write!(f, " !! {}", v),
+            None => Ok(()),
+        }
+    }
+}
+
+impl std::fmt::Display for Trace {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for (i, event) in self.events.iter().enumerate() {
+            writeln!(f, "{}", event)?;
+            for x in self.exchanges.iter().filter(|x| x.end == i) {
+                writeln!(f, "    > {}", describe_request(&x.request))?;
+                if let Some(response) = &x.response {
+                    let response = String::from_utf8_lossy(response);
+                    writeln!(f, "    < {}", response)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,372 @@
+use super::super::driver::{Reader, ReaderWriter, Writer};
+use super::packet::{self, Framing, ACK, IO_SIZE, PREAMBLE};
+use crate::error::*;
+use crate::request::{Encoding, Method, Request};
+use linq_db::k64::{About, IpConfig, Network, Update};
+use linq_sys::E_LINQ_ERROR_LINQ_ERROR_TIMEOUT;
+use serde_json::{json, Map, Value};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+/// Faults a simulator can inject into the next exchange
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Fault {
+    /// Do not acknowledge the next PREAMBLE (host read times out)
+    DropAck,
+    /// Respond to the next PREAMBLE with a garbage packet instead of ACK
+    Garbage,
+    /// Respond to the next request with 504 (please try again later)
+    Busy,
+}
+
+/// Where we are in the exchange with the host
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Phase {
+    /// Waiting for a PREAMBLE
+    Idle,
+    /// Waiting for the length packet
+    Length,
+    /// Waiting for data chunk [n] of [total]
+    Chunk(usize, usize),
+    /// Waiting for the host to ACK the next packet of a long mode response
+    Responding,
+}
+
+struct State {
+    phase: Phase,
+    /// Length of the request being received
+    len: usize,
+    /// Request being received
+    buffer: Vec<u8>,
+    /// Packets waiting for the host to read
+    incoming: VecDeque<[u8; IO_SIZE]>,
+    /// Long mode response packets waiting for the host to ACK
+    pending: VecDeque<[u8; IO_SIZE]>,
+    faults: VecDeque<Fault>,
+    /// The /ATX resource tree
+    tree: Value,
+    requests: Vec<Request>,
+    updates: Vec<Update>,
+    violations: Vec<String>,
+    long_mode: bool,
+    framing: Framing,
+    encoding: Encoding,
+}
+
+/// A software K64. Speaks the HID packet protocol and serves an in memory
+/// /ATX resource tree. (Useful for testing everything above the binding)
+pub struct Simulator {
+    state: Mutex<State>,
+}
+
+/// Helper to create the resource tree of a fresh device
+fn tree(about: &About) -> Value {
+    json!({
+        "about": about,
+        "network": Network {
+            ip_config: IpConfig {
+                ip: "192.168.168.168".to_string(),
+                sn: "255.255.255.0".to_string(),
+                gw: "192.168.168.1".to_string(),
+                ..Default::default()
+            }
+        }
+    })
+}
+
+/// Split "/ATX/a/b" into ["a", "b"]. (None if not an /ATX path)
+fn segments(path: &str) -> Option<Vec<&str>> {
+    let mut segments = path.split('/').filter(|x| !x.is_empty());
+    match segments.next() {
+        Some("ATX") => Some(segments.collect()),
+        _ => None,
+    }
+}
+
+fn error(code: u16) -> Vec<u8> {
+    format!("{{\"error\":{}}}", code).into_bytes()
+}
+
+impl Simulator {
+    /// Create a simulated device with some default about info
+    pub fn new(serial: &str) -> Self {
+        Simulator::with_about(About {
+            siteId: "Site ID".to_string(),
+            prjVersion: "2.6.6".to_string(),
+            atxVersion: "2.5.2".to_string(),
+            sid: serial.to_string(),
+            mac: "CC:67:AB:FF:28:A2".to_string(),
+            product: "LINQ2".to_string(),
+            ..Default::default()
+        })
+    }
+
+    /// Create a simulated device with explicit about info
+    pub fn with_about(about: About) -> Self {
+        Simulator {
+            state: Mutex::new(State {
+                phase: Phase::Idle,
+                len: 0,
+                buffer: vec![],
+                incoming: VecDeque::new(),
+                pending: VecDeque::new(),
+                faults: VecDeque::new(),
+                tree: tree(&about),
+                requests: vec![],
+                updates: vec![],
+                violations: vec![],
+                long_mode: false,
+                framing: Framing::Legacy,
+                encoding: Encoding::NullTerminated,
+            }),
+        }
+    }
+
+    /// Always respond in long mode. (By default responses that fit in a
+    /// single packet are sent in short mode)
+    pub fn long_mode(self, long_mode: bool) -> Self {
+        self.state.lock().unwrap().long_mode = long_mode;
+        self
+    }
+
+    /// Framing and encoding the simulated firmware understands
+    pub fn protocol(self, framing: Framing, encoding: Encoding) -> Self {
+        let mut state = self.state.lock().unwrap();
+        state.framing = framing;
+        state.encoding = encoding;
+        drop(state);
+        self
+    }
+
+    /// Inject a fault into a future exchange. Faults are applied in order
+    pub fn inject(&self, fault: Fault) {
+        self.state.lock().unwrap().faults.push_back(fault);
+    }
+
+    /// Peek at a resource IE: get("/ATX/about/siteId")
+    pub fn get(&self, path: &str) -> Option<Value> {
+        let state = self.state.lock().unwrap();
+        segments(path)?
+            .iter()
+            .try_fold(&state.tree, |node, key| node.get(key))
+            .cloned()
+    }
+
+    /// Every request the simulator has received
+    pub fn requests(&self) -> Vec<Request> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Every update chunk the simulator has received
+    pub fn updates(&self) -> Vec<Update> {
+        self.state.lock().unwrap().updates.clone()
+    }
+
+    /// Every protocol violation committed by the host
+    pub fn violations(&self) -> Vec<String> {
+        self.state.lock().unwrap().violations.clone()
+    }
+}
+
+impl State {
+    fn take_fault(&mut self, f: impl Fn(&Fault) -> bool) -> Option<Fault> {
+        let i = self.faults.iter().position(f)?;
+        self.faults.remove(i)
+    }
+
+    fn violation(&mut self, e: String) {
+        self.violations.push(e);
+        self.phase = Phase::Idle;
+    }
+
+    fn on_packet(&mut self, p: [u8; IO_SIZE]) {
+        if p == PREAMBLE {
+            if self.phase != Phase::Idle {
+                let e = format!("PREAMBLE during {:?}", self.phase);
+                self.violations.push(e);
+            }
+            self.buffer.clear();
+            self.pending.clear();
+            self.phase = Phase::Length;
+            let fault = self.take_fault(|x| *x != Fault::Busy);
+            match fault {
+                Some(Fault::DropAck) => (),
+                Some(Fault::Garbage) => {
+                    self.incoming.push_back([0xA5; IO_SIZE])
+                }
+                _ => self.incoming.push_back(ACK),
+            }
+            return;
+        }
+        match self.phase {
+            Phase::Idle => self.violation("expected PREAMBLE".to_string()),
+            Phase::Length => {
+                self.len = packet::to_len(&p);
+                if self.len > self.framing.max_len() {
+                    return self.violation(format!("length {}", self.len));
+                }
+                match packet::to_chunks(self.len) {
+                    0 => self.respond(),
+                    n => {
+                        self.phase = Phase::Chunk(0, n);
+                        self.incoming.push_back(ACK);
+                    }
+                }
+            }
+            Phase::Chunk(i, n) => {
+                self.buffer.extend_from_slice(&p);
+                if i + 1 == n {
+                    self.buffer.truncate(self.len);
+                    self.respond();
+                } else {
+                    self.phase = Phase::Chunk(i + 1, n);
+                    self.incoming.push_back(ACK);
+                }
+            }
+            Phase::Responding if p == ACK => {
+                if let Some(next) = self.pending.pop_front() {
+                    self.incoming.push_back(next);
+                }
+                if self.pending.is_empty() {
+                    self.phase = Phase::Idle;
+                }
+            }
+            Phase::Responding => self.violation("expected ACK".to_string()),
+        }
+    }
+
+    /// We have received a complete request. Queue up the response
+    fn respond(&mut self) {
+        let body = match self.take_fault(|x| *x == Fault::Busy) {
+            Some(_) => error(504),
+            None => match Request::decode(&self.buffer, self.encoding) {
+                Ok(r) => {
+                    self.requests.push(r.clone());
+                    self.handle(&r)
+                }
+                Err(_) => error(400),
+            },
+        };
+        if body.len() < IO_SIZE && !self.long_mode {
+            let mut short = [0; IO_SIZE];
+            short[..body.len()].copy_from_slice(&body);
+            self.incoming.push_back(short);
+            self.phase = Phase::Idle;
+        } else {
+            // NOTE our own responses can not overflow the framing
+            let (len, chunks) =
+                packet::from_bytes_framed(&body, self.framing).unwrap();
+            self.incoming.push_back(PREAMBLE);
+            self.pending.push_back(len);
+            self.pending.extend(chunks);
+            self.phase = Phase::Responding;
+        }
+    }
+
+    /// Serve a request from the resource tree
+    fn handle(&mut self, r: &Request) -> Vec<u8> {
+        let segments = match segments(&r.path) {
+            Some(segments) if !segments.is_empty() => segments,
+            _ => return error(404),
+        };
+        let body = match std::str::from_utf8(r.body.as_bytes()) {
+            Ok(b) if !b.is_empty() => serde_json::from_str::<Value>(b).ok(),
+            _ => None,
+        };
+        match (r.method, segments[..].split_last(), body) {
+            (Method::Post, Some((&cmd, &["exe"])), Some(body)) => {
+                self.exe(cmd, body)
+            }
+            (Method::Get, Some((last, _)), _) => {
+                match segments.iter().try_fold(&self.tree, |n, k| n.get(k)) {
+                    Some(node) => {
+                        let mut map = Map::new();
+                        map.insert(last.to_string(), node.clone());
+                        Value::Object(map).to_string().into_bytes()
+                    }
+                    None => error(404),
+                }
+            }
+            (Method::Post, Some((last, _)), Some(body))
+            | (Method::Put, Some((last, _)), Some(body)) => {
+                let node = segments
+                    .iter()
+                    .try_fold(&mut self.tree, |n, k| n.get_mut(k));
+                match (node, body) {
+                    // IE: POST /ATX/network/ipConfig/ip {"ip":"..."}
+                    (Some(node), Value::Object(mut map))
+                        if map.len() == 1 && map.contains_key(*last) =>
+                    {
+                        *node = map.remove(*last).unwrap();
+                        error(200)
+                    }
+                    // IE: POST /ATX/about {"siteId":"..."}
+                    (Some(Value::Object(node)), Value::Object(map)) => {
+                        node.extend(map);
+                        error(200)
+                    }
+                    (Some(_), _) => error(400),
+                    (None, _) => error(404),
+                }
+            }
+            (Method::Delete, Some((last, parents)), _) => {
+                let parent = parents
+                    .iter()
+                    .try_fold(&mut self.tree, |n, k| n.get_mut(k))
+                    .and_then(|x| x.as_object_mut());
+                match parent.and_then(|x| x.remove(*last)) {
+                    Some(_) => error(200),
+                    None => error(404),
+                }
+            }
+            _ => error(400),
+        }
+    }
+
+    /// Serve a request to /ATX/exe/...
+    fn exe(&mut self, cmd: &str, body: Value) -> Vec<u8> {
+        match cmd {
+            "save" | "reboot" => error(200),
+            "update" => match serde_json::from_value::<Update>(body) {
+                Ok(update) => {
+                    self.updates.push(update);
+                    error(200)
+                }
+                Err(_) => error(400),
+            },
+            _ => error(404),
+        }
+    }
+}
+
+impl ReaderWriter for Simulator {}
+
+impl Writer for Simulator {
+    fn write(&self, _: &str, bytes: &[u8]) -> Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        if bytes.len() != IO_SIZE {
+            let e = format!("packet of {} bytes", bytes.len());
+            state.violation(e.clone());
+            return Err(UsbError::Protocol(e).into());
+        }
+        let mut p = [0; IO_SIZE];
+        p.copy_from_slice(bytes);
+        state.on_packet(p);
+        Ok(bytes.len())
+    }
+}
+
+impl Reader for Simulator {
+    fn read(&self, _: &str, bytes: &mut [u8]) -> Result<usize> {
+        match self.state.lock().unwrap().incoming.pop_front() {
+            Some(p) => {
+                bytes[..IO_SIZE].copy_from_slice(&p);
+                Ok(IO_SIZE)
+            }
+            None => {
+                Err(UsbError::Usbh(E_LINQ_ERROR_LINQ_ERROR_TIMEOUT, "timeout")
+                    .into())
+            }
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use crate::capabilities::Capabilities;
+use crate::request::{Encoding, Request};
+use crate::usb::drivers::driver::UsbDriver;
+use crate::usb::drivers::k64::packet::Framing;
+use crate::usb::drivers::k64::{self, Protocol, Simulator, K64};
+use crate::usb::virtual_bus::VirtualBus;
+use std::sync::Arc;
+
+#[test]
+fn test_capabilities_probe() {
+    let sim = Simulator::new("SID");
+    let caps = k64::capabilities(&sim, "", Protocol::default());
+    let expect = Capabilities {
+        update: true,
+        website: true,
+        network: true,
+        reboot: true,
+        max_payload: Framing::Legacy.max_len(),
+    };
+    assert_eq!(caps, expect);
+
+    // Firmware without network support
+    let r = Request::delete("/ATX/network");
+    k64::request_raw(&sim, "", r).unwrap();
+    let caps = k64::capabilities(&sim, "", Protocol::default());
+    assert!(!caps.network);
+    assert!(caps.update);
+}
+
+#[test]
+fn test_capabilities_described() {
+    let sim = Simulator::new("SID");
+    let about = r#"{"capabilities":{"update":true,"maxPayload":4000000}}"#;
+    k64::request_raw(&sim, "", Request::post_raw("/ATX/about", about)).unwrap();
+    let caps = k64::capabilities(&sim, "", Protocol::default());
+    let expect = Capabilities {
+        update: true,
+        max_payload: Framing::Legacy.max_len(),
+        ..Capabilities::default()
+    };
+    assert_eq!(caps, expect);
+}
+
+#[test]
+fn test_capabilities_limit() {
+    let caps = Capabilities::default().limit(10);
+    assert_eq!(caps.max_payload, 10);
+    let caps = Capabilities {
+        max_payload: 4,
+        ..Capabilities::default()
+    };
+    assert_eq!(caps.limit(10).max_payload, 4);
+}
+
+#[test]
+fn test_capabilities_select_framing() {
+    let bus = VirtualBus::new();
+    let sim = Simulator::new("SID")
+        .protocol(Framing::Extended, Encoding::NullTerminated);
+    let about = r#"{"capabilities":{"update":true,"maxPayload":4000000}}"#;
+    k64::request_raw(&sim, "", Request::post_raw("/ATX/about", about)).unwrap();
+    bus.attach("SID", Arc::new(sim));
+    let mut driver = K64::new();
+    driver.open(&bus, "SID").unwrap();
+    assert_eq!(driver.capabilities().max_payload, 4000000);
+    let big = format!("{{\"siteId\":\"{}\"}}", "x".repeat(0x10000));
+    let r = Request::post_raw("/ATX/about", big);
+    assert!(driver.request(&bus, "SID", r).is_ok());
+
+    // Firmware that does not describe itself keeps legacy framing
+    let bus = VirtualBus::new();
+    bus.attach("SID", Arc::new(Simulator::new("SID")));
+    let mut driver = K64::new();
+    driver.open(&bus, "SID").unwrap();
+    assert_eq!(driver.capabilities().max_payload, Framing::Legacy.max_len());
+}
@@ -1,5 +1,5 @@
 use super::packet_test::*;
-use crate::error::{ApiError, IoError, Result};
+use crate::error::{ApiErrorKind, IoError, Result, UsbError};
 use crate::request::Request;
 use crate::usb::drivers::driver::{Reader, ReaderWriter, Writer};
 use crate::usb::drivers::k64;
@@ -61,7 +61,7 @@ impl Reader for MockPackets {
 #[test]
 fn test_long() {
     let mut mock = MockPackets::new();
-    let (len, packets) = from_str(TEST_DATA);
+    let (len, packets) = from_str(TEST_DATA).unwrap();
     mock.add_incoming(ACK);
     mock.add_incoming(ACK);
     mock.add_incoming(PREAMBLE);
@@ -72,10 +72,26 @@ fn test_long() {
     assert!(k64::request_raw(&mut mock, "", Request::get("")).is_ok());
 }
 
+#[test]
+fn test_long_binary() {
+    let mut mock = MockPackets::new();
+    let bytes: Vec<u8> = vec![0xff, 0x00, 0x00, 0x01, 0x00];
+    let (len, packets) = from_bytes(&bytes).unwrap();
+    mock.add_incoming(ACK);
+    mock.add_incoming(ACK);
+    mock.add_incoming(PREAMBLE);
+    mock.add_incoming(len);
+    for i in packets {
+        mock.add_incoming(i);
+    }
+    let response = k64::request_bytes(&mock, "", Request::get(""));
+    assert_eq!(response.unwrap(), bytes);
+}
+
 #[test]
 fn test_short() {
     let mut mock = MockPackets::new();
-    let (_, packets) = from_str("{\"siteId\":\"foo\"}");
+    let (_, packets) = from_str("{\"siteId\":\"foo\"}").unwrap();
     mock.add_incoming(ACK);
     mock.add_incoming(ACK);
     mock.add_incoming(packets[0]);
@@ -93,13 +109,13 @@ fn test_bad() {
 #[test]
 fn test_translate_api_error() {
     let mut mock = MockPackets::new();
-    let (_, packets) = from_str("{\"error\":400}");
+    let (_, packets) = from_str("{\"error\":400}").unwrap();
     mock.add_incoming(ACK);
     mock.add_incoming(ACK);
     mock.add_incoming(packets[0]);
-    let response = k64::request_raw(&mut mock, "", Request::get(""));
-    let result = if let Err(IoError::ApiError(_)) = response {
-        true
+    let response = k64::request_raw(&mut mock, "", Request::get("/ATX/foo"));
+    let result = if let Err(IoError::ApiError(e)) = response {
+        e.kind == ApiErrorKind::Linq400 && e.path == "/ATX/foo"
     } else {
         false
     };
@@ -109,7 +125,7 @@ fn test_translate_api_error() {
 #[test]
 fn test_flush() {
     let mut mock = MockPackets::new();
-    let (_, packets) = from_str("{\"siteId\":\"foo\"}");
+    let (_, packets) = from_str("{\"siteId\":\"foo\"}").unwrap();
     mock.add_incoming(packets[0]); // Garbage in OS incoming buffer
     mock.add_incoming(packets[0]); // Flush it all out
     mock.add_incoming(packets[0]); // Flush it all out
@@ -121,3 +137,17 @@ fn test_flush() {
     mock.add_incoming(packets[0]);
     assert!(k64::request_raw(&mut mock, "", Request::get("")).is_ok());
 }
+
+#[test]
+fn test_overflow() {
+    let mut mock = MockPackets::new();
+    let big = "x".repeat(MAX_LEN);
+    let response = k64::request_raw(&mut mock, "", Request::post_raw("", big));
+    match response {
+        Err(IoError::Usb(UsbError::Overflow(_, max))) => {
+            assert_eq!(max, MAX_LEN)
+        }
+        _ => panic!("expected overflow"),
+    }
+    assert_eq!(mock.outgoing.borrow().len(), 0);
+}
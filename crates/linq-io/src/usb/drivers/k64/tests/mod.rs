@@ -1,2 +1,5 @@
-mod packet_test;
+mod capabilities_test;
 mod k64_test;
+mod packet_test;
+mod sim_test;
+mod trace_test;
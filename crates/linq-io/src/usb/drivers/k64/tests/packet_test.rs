@@ -1,5 +1,7 @@
 use super::super::packet;
+use super::super::packet::{Framing, IO_SIZE, MAX_LEN};
 use crate::request::Request;
+use proptest::prelude::*;
 
 pub const TEST_DATA: &'static str = r#"
         {
@@ -28,13 +30,13 @@ pub const TEST_DATA: &'static str = r#"
 
 #[test]
 fn test_packet_to_string() {
-    let p0: packet::Packets = packet::from_str(TEST_DATA);
+    let p0: packet::Packets = packet::from_str(TEST_DATA).unwrap();
     assert_eq!(packet::to_string(&p0.1).unwrap(), TEST_DATA);
 }
 
 #[test]
 fn test_packet_from_len() {
-    let len = packet::from_len(427);
+    let len = packet::from_len(427).unwrap();
     assert_eq!(len[0], 0xab);
     assert_eq!(len[1], 0x01);
 }
@@ -42,21 +44,98 @@ fn test_packet_from_len() {
 #[test]
 fn test_packet_from_request_get() {
     let request = Request::get("/ATX/about");
-    let packets = packet::from_request(&request);
+    let packets = packet::from_request(&request).unwrap();
     assert_eq!(packets.1[0][0..3], [b'G', b'E', b'T']);
 }
 
 #[test]
 fn test_packet_from_request_post() {
     let request = Request::post_raw("/ATX/about", "{\"foo\"}");
-    let packets = packet::from_request(&request);
+    let packets = packet::from_request(&request).unwrap();
     assert_eq!(packets.1[0][0..4], [b'P', b'O', b'S', b'T']);
 }
 
 #[test]
 fn test_packet_to_len_chunks() {
-    let len = packet::from_len(427);
+    let len = packet::from_len(427).unwrap();
     let (size, chunks) = packet::to_len_chunks(&len);
     assert_eq!(size, 427);
     assert_eq!(chunks, 7);
 }
+
+#[test]
+fn test_packet_from_len_overflow() {
+    assert!(packet::from_len(MAX_LEN).is_ok());
+    assert!(packet::from_len(MAX_LEN + 1).is_err());
+    assert!(packet::from_len_framed(MAX_LEN + 1, Framing::Extended).is_ok());
+}
+
+#[test]
+fn test_packet_from_str_overflow() {
+    let big = "x".repeat(MAX_LEN + 1);
+    assert!(packet::from_str(&big).is_err());
+    let (len, packets) =
+        packet::from_str_framed(&big, Framing::Extended).unwrap();
+    assert_eq!(packet::to_len(&len), MAX_LEN + 1);
+    assert_eq!(packets.len(), MAX_LEN / IO_SIZE + 1);
+    assert_eq!(packet::to_string(&packets).unwrap(), big);
+}
+
+#[test]
+fn test_packet_extended_keeps_legacy_layout() {
+    // Small payloads must still be readable by legacy firmware
+    let legacy = packet::from_len(427).unwrap();
+    let extended = packet::from_len_framed(427, Framing::Extended).unwrap();
+    assert_eq!(legacy[..], extended[..]);
+}
+
+#[test]
+fn test_packet_bytes_roundtrip() {
+    let bytes: Vec<u8> = (0..200).map(|x| (x % 3) as u8).collect();
+    let (len, packets) = packet::from_bytes(&bytes).unwrap();
+    assert_eq!(packet::to_bytes(&packets, packet::to_len(&len)), bytes);
+}
+
+#[test]
+fn test_packet_to_bytes_short() {
+    let (_, packets) = packet::from_str("{\"siteId\":\"foo\"}").unwrap();
+    assert_eq!(packet::to_bytes_short(&packets[0]), b"{\"siteId\":\"foo\"}");
+}
+
+proptest! {
+    #[test]
+    fn prop_legacy_len_roundtrip(l in 0..=MAX_LEN) {
+        let len = packet::from_len(l).unwrap();
+        prop_assert_eq!(packet::to_len(&len), l);
+    }
+
+    #[test]
+    fn prop_legacy_len_overflow(l in (MAX_LEN + 1)..(4 * MAX_LEN)) {
+        prop_assert!(packet::from_len(l).is_err());
+    }
+
+    #[test]
+    fn prop_extended_len_roundtrip(l in prop_oneof![
+        0..IO_SIZE * 2,
+        (MAX_LEN - IO_SIZE)..(MAX_LEN + IO_SIZE),
+        (MAX_LEN + 1)..packet::MAX_LEN_EXTENDED,
+        Just(packet::MAX_LEN_EXTENDED),
+    ]) {
+        let len = packet::from_len_framed(l, Framing::Extended).unwrap();
+        let (size, chunks) = packet::to_len_chunks(&len);
+        prop_assert_eq!(size, l);
+        prop_assert_eq!(chunks, l.div_ceil(IO_SIZE));
+    }
+
+    #[test]
+    fn prop_str_roundtrip(n in prop_oneof![
+        0..IO_SIZE * 3,
+        (MAX_LEN - IO_SIZE)..=MAX_LEN,
+    ]) {
+        let s = "a".repeat(n);
+        let (len, packets) = packet::from_str(&s).unwrap();
+        prop_assert_eq!(packet::to_len(&len), n);
+        prop_assert_eq!(packets.len(), packet::to_chunks(n));
+        prop_assert_eq!(packet::to_string(&packets).unwrap(), s);
+    }
+}
@@ -0,0 +1,178 @@
+use crate::error::{ApiErrorKind, IoError};
+use crate::request::{Encoding, Request};
+use crate::update::DashboardUpdatePackets;
+use crate::usb::drivers::k64;
+use crate::usb::drivers::k64::packet::Framing;
+use crate::usb::drivers::k64::{Fault, Protocol, Simulator};
+use linq_db::k64::AboutResponse;
+use serde_json::json;
+
+#[test]
+fn test_sim_about() {
+    for long_mode in &[false, true] {
+        let sim = Simulator::new("SID").long_mode(*long_mode);
+        let about: AboutResponse =
+            k64::request(&sim, "", Request::get("/ATX/about")).unwrap();
+        assert_eq!(about.about.sid, "SID");
+        assert_eq!(about.about.product, "LINQ2");
+        assert!(sim.violations().is_empty());
+    }
+}
+
+#[test]
+fn test_sim_open() {
+    let sim = Simulator::new("SID");
+    let serial = k64::open(&sim, "").unwrap();
+    assert_eq!(serial, "SID");
+}
+
+#[test]
+fn test_sim_short_mode() {
+    let sim = Simulator::new("SID");
+    let r = Request::get("/ATX/network/ipConfig/ip");
+    let response = k64::request_raw(&sim, "", r).unwrap();
+    assert_eq!(response, "{\"ip\":\"192.168.168.168\"}");
+}
+
+#[test]
+fn test_sim_post() {
+    let sim = Simulator::new("SID");
+    let data = "{\"ip\":\"10.0.0.2\"}";
+    let r = Request::post_raw("/ATX/network/ipConfig/ip", data);
+    assert!(k64::request_raw(&sim, "", r).is_ok());
+    let r = Request::post_raw("/ATX/about", "{\"siteId\":\"bench\"}");
+    assert!(k64::request_raw(&sim, "", r).is_ok());
+    assert_eq!(sim.get("/ATX/network/ipConfig/ip"), Some(json!("10.0.0.2")));
+    assert_eq!(sim.get("/ATX/about/siteId"), Some(json!("bench")));
+    assert_eq!(sim.get("/ATX/about/sid"), Some(json!("SID")));
+    let r = Request::post_raw("/ATX/exe/save", "{\"save\":1}");
+    assert!(k64::request_raw(&sim, "", r).is_ok());
+    let paths: Vec<String> =
+        sim.requests().into_iter().map(|x| x.path).collect();
+    assert_eq!(
+        paths,
+        vec!["/ATX/network/ipConfig/ip", "/ATX/about", "/ATX/exe/save"]
+    );
+}
+
+#[test]
+fn test_sim_post_partial() {
+    let sim = Simulator::new("SID");
+    let data = json!({"dhcp": true, "dns": ["10.0.0.1"], "hostname": "bench"});
+    let r = Request::post_raw("/ATX/network/ipConfig", data.to_string());
+    assert!(k64::request_raw(&sim, "", r).is_ok());
+    assert_eq!(sim.get("/ATX/network/ipConfig/dhcp"), Some(json!(true)));
+    assert_eq!(
+        sim.get("/ATX/network/ipConfig/dns"),
+        Some(json!(["10.0.0.1"]))
+    );
+    assert_eq!(
+        sim.get("/ATX/network/ipConfig/hostname"),
+        Some(json!("bench"))
+    );
+    assert_eq!(
+        sim.get("/ATX/network/ipConfig/ip"),
+        Some(json!("192.168.168.168"))
+    );
+}
+
+#[test]
+fn test_sim_delete() {
+    let sim = Simulator::new("SID");
+    let r = Request::delete("/ATX/network/ipConfig/gw");
+    assert!(k64::request_raw(&sim, "", r).is_ok());
+    assert_eq!(sim.get("/ATX/network/ipConfig/gw"), None);
+}
+
+#[test]
+fn test_sim_api_errors() {
+    let sim = Simulator::new("SID");
+    let expect = |r, kind| match k64::request_raw(&sim, "", r) {
+        Err(IoError::ApiError(e)) => assert_eq!(e.kind, kind),
+        _ => panic!("expected {:?}", kind),
+    };
+    expect(Request::get("/ATX/nope"), ApiErrorKind::Linq404);
+    expect(Request::get("/foo"), ApiErrorKind::Linq404);
+    expect(
+        Request::post_raw("/ATX/about", "not json"),
+        ApiErrorKind::Linq400,
+    );
+    expect(
+        Request::post_raw("/ATX/exe/nope", "{}"),
+        ApiErrorKind::Linq404,
+    );
+}
+
+#[test]
+fn test_sim_extended_protocol() {
+    let protocol = Protocol {
+        framing: Framing::Extended,
+        encoding: Encoding::LengthPrefixed,
+    };
+    let sim =
+        Simulator::new("SID").protocol(protocol.framing, protocol.encoding);
+    let big = format!("{{\"siteId\":\"{}\"}}", "x".repeat(0x10000));
+    let r = Request::post_raw("/ATX/about", big);
+    assert!(k64::request_bytes_with(&sim, "", r, protocol).is_ok());
+    let r = Request::get("/ATX/about");
+    let response = k64::request_bytes_with(&sim, "", r, protocol).unwrap();
+    assert!(response.len() > 0x10000);
+    assert!(sim.violations().is_empty());
+}
+
+#[test]
+fn test_sim_update() {
+    let sim = Simulator::new("SID");
+    let update = json!({
+        "files": [
+            { "update": [
+                { "type": "firmware", "size": 4, "offset": 0,
+                  "payload": "AAAA", "md5": "" },
+                { "type": "firmware", "size": 4, "offset": 4,
+                  "payload": "BBBB", "md5": "" }
+            ]},
+            { "update": [] }
+        ]
+    });
+    let packets = DashboardUpdatePackets::parse(&update.to_string()).unwrap();
+    for u in packets.0.iter().rev() {
+        let r = Request::post("/ATX/exe/update", u);
+        assert!(k64::request_raw(&sim, "", r).is_ok());
+    }
+    let offsets: Vec<u32> = sim.updates().iter().map(|x| x.offset).collect();
+    assert_eq!(offsets, vec![0, 4]);
+}
+
+#[test]
+fn test_sim_faults_recover() {
+    for fault in &[Fault::DropAck, Fault::Garbage, Fault::Busy] {
+        let sim = Simulator::new("SID");
+        sim.inject(*fault);
+        let r = Request::get("/ATX/about/sid");
+        let response = k64::request_raw(&sim, "", r);
+        assert_eq!(response.unwrap(), "{\"sid\":\"SID\"}");
+        assert_eq!(sim.requests().len(), 1);
+    }
+}
+
+#[test]
+fn test_sim_faults_give_up() {
+    let sim = Simulator::new("SID");
+    for _ in 0..=k64::MAX_RETRY {
+        sim.inject(Fault::Busy);
+    }
+    match k64::request_raw(&sim, "", Request::get("/ATX/about")) {
+        Err(IoError::ApiError(e)) => assert_eq!(e.kind, ApiErrorKind::Linq504),
+        _ => panic!("expected 504"),
+    }
+}
+
+#[test]
+fn test_sim_violations() {
+    use crate::usb::drivers::driver::Writer;
+    use crate::usb::drivers::k64::packet::ACK;
+    let sim = Simulator::new("SID");
+    sim.write("", &ACK).unwrap();
+    assert!(sim.write("", &[0; 3]).is_err());
+    assert_eq!(sim.violations().len(), 2);
+}
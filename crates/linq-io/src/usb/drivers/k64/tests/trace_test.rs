@@ -0,0 +1,116 @@
+use super::super::packet::{self, Kind, ACK, IO_SIZE, PREAMBLE};
+use super::super::{Fault, Simulator};
+use crate::request::Request;
+use crate::usb::drivers::capture::{Direction, Frame, Recorder};
+use crate::usb::drivers::k64;
+
+fn frame(direction: Direction, data: &[u8]) -> Frame {
+    Frame {
+        time: 0,
+        direction,
+        serial: "SID".to_string(),
+        data: data.to_vec(),
+        error: None,
+    }
+}
+
+/// Record the frames of a request to a simulator
+fn record(sim: Simulator, r: Request) -> Vec<Frame> {
+    let recorder = Recorder::new(sim);
+    k64::request_raw(&recorder, "SID", r).unwrap();
+    recorder.frames()
+}
+
+#[test]
+fn test_trace_short_mode() {
+    let frames = record(Simulator::new("SID"), Request::get("/ATX/about/sid"));
+    let trace = packet::decode(&frames);
+    let kinds: Vec<&Kind> = trace.events.iter().map(|x| &x.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            &Kind::Preamble,
+            &Kind::Ack,
+            &Kind::Length(18),
+            &Kind::Ack,
+            &Kind::Data(1, 1),
+            &Kind::Short
+        ]
+    );
+    assert!(trace.violations().is_empty());
+    assert_eq!(trace.exchanges.len(), 1);
+    let exchange = &trace.exchanges[0];
+    assert_eq!(
+        packet::describe_request(&exchange.request),
+        "GET /ATX/about/sid"
+    );
+    assert_eq!(exchange.response, Some(b"{\"sid\":\"SID\"}".to_vec()));
+    assert_eq!(exchange.end, 5);
+    let text = trace.to_string();
+    assert!(text.contains("SID -> LENGTH 18"));
+    assert!(text.contains("    > GET /ATX/about/sid\n    < {\"sid\":\"SID\"}"));
+}
+
+#[test]
+fn test_trace_long_mode() {
+    let sim = Simulator::new("SID").long_mode(true);
+    let data = format!("{{\"siteId\":\"{}\"}}", "x".repeat(100));
+    let frames = record(sim, Request::post_raw("/ATX/about", &data[..]));
+    let trace = packet::decode(&frames);
+    assert!(trace.violations().is_empty());
+    let request = &trace.exchanges[0].request;
+    assert_eq!(
+        packet::describe_request(request),
+        format!("POST /ATX/about {}", data)
+    );
+    assert_eq!(
+        trace.exchanges[0].response,
+        Some(b"{\"error\":200}".to_vec())
+    );
+    let last: Vec<&Kind> = trace.events.iter().rev().map(|x| &x.kind).collect();
+    assert_eq!(
+        last[..4],
+        [&Kind::Data(1, 1), &Kind::Ack, &Kind::Length(13), &Kind::Ack]
+    );
+}
+
+#[test]
+fn test_trace_retry() {
+    let sim = Simulator::new("SID");
+    sim.inject(Fault::DropAck);
+    let frames = record(sim, Request::get("/ATX/about/sid"));
+    let trace = packet::decode(&frames);
+    assert!(trace.violations().is_empty());
+    assert!(trace
+        .events
+        .iter()
+        .any(|x| matches!(x.kind, Kind::Failed(_))));
+    assert_eq!(trace.exchanges.len(), 1);
+    assert!(trace.exchanges[0].response.is_some());
+}
+
+#[test]
+fn test_trace_violations() {
+    let mut garbage = [0xA5; IO_SIZE];
+    garbage[0] = 1;
+    let frames = vec![
+        frame(Direction::Out, &ACK),
+        frame(Direction::Out, &PREAMBLE),
+        frame(Direction::In, &garbage),
+        frame(Direction::Out, &PREAMBLE),
+        frame(Direction::In, &ACK),
+        frame(Direction::Out, &PREAMBLE),
+        frame(Direction::Out, &[0; 3]),
+    ];
+    let trace = packet::decode(&frames);
+    let violations: Vec<usize> = trace
+        .events
+        .iter()
+        .enumerate()
+        .filter(|(_, x)| x.violation.is_some())
+        .map(|(i, _)| i)
+        .collect();
+    assert_eq!(violations, vec![0, 2, 5, 6]);
+    assert_eq!(trace.events[2].kind, Kind::Unknown);
+    assert!(trace.to_string().contains("!! expected ACK during Ack"));
+}
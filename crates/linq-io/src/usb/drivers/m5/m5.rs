@@ -1,32 +1,60 @@
-use super::super::driver::ReaderWriter;
+use super::super::super::backend::Backend;
+use super::super::driver::{ReaderWriter, UsbDriver};
+use crate::capabilities::Capabilities;
 use crate::error::*;
 use crate::Request;
 use serde::de::DeserializeOwned;
 
 /// Helper method to parse caller data if type is serializable
 pub fn request<R: DeserializeOwned>(
-    ctx: &impl ReaderWriter,
+    ctx: &(impl ReaderWriter + ?Sized),
     sid: &str,
     r: Request,
 ) -> Result<R> {
-    request_raw(ctx, &sid, Request::get("/ATX/about")).and_then(|r| {
-        serde_json::from_str::<R>(&r)
+    request_bytes(ctx, sid, r).and_then(|r| {
+        serde_json::from_slice::<R>(&r)
             .map_err(|x| UsbError::Parser(x.to_string()).into())
     })
 }
 
-pub fn request_raw(
-    ctx: &impl ReaderWriter,
-    sid: &str,
-    r: Request,
-) -> Result<String> {
+pub fn request_bytes(
+    _ctx: &(impl ReaderWriter + ?Sized),
+    _sid: &str,
+    _r: Request,
+) -> Result<Vec<u8>> {
+    Ok(vec![])
+}
+
+/// Read the serial number of a M5 USB device
+pub fn open(_ctx: &(impl ReaderWriter + ?Sized), _sid: &str) -> Result<String> {
     Ok("".to_owned())
 }
 
-/// Return a driver handle for a K64 USB device
-pub fn open<T: ReaderWriter>(
-    ctx: &T,
-    sid: &str,
-) -> Result<(String, fn(ctx: &T, sid: &str, r: Request) -> Result<String>)> {
-    Ok(("".to_owned(), request_raw))
+/// The M5 usb driver
+#[derive(Default)]
+pub struct M5 {}
+
+impl M5 {
+    pub fn new() -> Self {
+        M5::default()
+    }
+}
+
+impl UsbDriver for M5 {
+    fn open(&mut self, bus: &dyn Backend, sid: &str) -> Result<String> {
+        open(bus, sid)
+    }
+
+    fn request(
+        &mut self,
+        bus: &dyn Backend,
+        sid: &str,
+        r: Request,
+    ) -> Result<Vec<u8>> {
+        request_bytes(bus, sid, r)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
 }
@@ -1,3 +1,7 @@
+#[cfg(test)]
+mod tests;
+
+pub mod capture;
 pub mod driver;
 pub mod k64;
 pub mod m5;
@@ -0,0 +1,113 @@
+use crate::error::*;
+use crate::io::Io;
+use crate::request::Request;
+use crate::usb::drivers::capture::{self, Direction, Recorder, Replay};
+use crate::usb::drivers::driver::Reader;
+use crate::usb::drivers::k64::{self, Fault, Simulator};
+use crate::usb::VirtualBus;
+use futures::executor::block_on;
+use std::sync::Arc;
+
+fn temp(name: &str) -> String {
+    let name = format!("linq-{}-{}.cap", name, std::process::id());
+    std::env::temp_dir().join(name).to_str().unwrap().to_owned()
+}
+
+#[test]
+fn test_record_replay() {
+    let recorder = Recorder::new(Simulator::new("SID"));
+    let r = Request::get("/ATX/about/sid");
+    let response = k64::request_raw(&recorder, "usb", r.clone()).unwrap();
+    let frames = recorder.frames();
+    assert_eq!(frames[0].direction, Direction::Out);
+    assert_eq!(frames[0].serial, "usb");
+    assert_eq!(frames[0].data.len(), 64);
+    assert_eq!(frames[1].direction, Direction::In);
+    assert!(frames.windows(2).all(|x| x[0].time <= x[1].time));
+
+    let path = temp("replay");
+    capture::save(&path, &frames).unwrap();
+    assert_eq!(capture::load(&path).unwrap(), frames);
+    let replay = Replay::load(&path).unwrap();
+    assert_eq!(k64::request_raw(&replay, "usb", r).unwrap(), response);
+    assert_eq!(replay.remaining(), 0);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_replay_fault() {
+    let sim = Simulator::new("SID");
+    sim.inject(Fault::DropAck);
+    let recorder = Recorder::new(sim);
+    let r = Request::get("/ATX/about/sid");
+    let response = k64::request_raw(&recorder, "", r.clone()).unwrap();
+    let frames = recorder.frames();
+    assert!(frames.iter().any(|x| x.error.is_some()));
+
+    // The retry after the timeout is reproduced
+    let replay = Replay::new(frames);
+    assert_eq!(k64::request_raw(&replay, "", r).unwrap(), response);
+    assert_eq!(replay.remaining(), 0);
+}
+
+#[test]
+fn test_replay_diverged() {
+    let recorder = Recorder::new(Simulator::new("SID"));
+    let r = Request::get("/ATX/about/sid");
+    k64::request_raw(&recorder, "", r).unwrap();
+    let replay = Replay::new(recorder.frames());
+    let r = Request::get("/ATX/about/siteId");
+    match k64::request_raw(&replay, "", r) {
+        Err(IoError::Usb(UsbError::Protocol(_))) => {}
+        _ => panic!("expected replay to diverge"),
+    }
+}
+
+#[test]
+fn test_parse() {
+    let frames = capture::parse(
+        r#"
+        {"time":0,"direction":"out","serial":"A","data":"00ff"}
+        {"time":9,"direction":"in","serial":"A","data":"","error":"timeout"}
+        "#,
+    )
+    .unwrap();
+    assert_eq!(frames[0].data, vec![0x00, 0xff]);
+    assert_eq!(frames[1].error, Some("timeout".to_string()));
+    let bad = r#"{"time":0,"direction":"out","serial":"A","data":"0"}"#;
+    assert!(capture::parse(bad).is_err());
+    let bad = r#"{"time":0,"direction":"out","serial":"A","data":"zz"}"#;
+    assert!(capture::parse(bad).is_err());
+    let bad = r#"{"time":0,"direction":"out","serial":"A","data":"aéa"}"#;
+    assert!(capture::parse(bad).is_err());
+}
+
+#[test]
+fn test_replay_frame_too_large() {
+    let frames = capture::parse(
+        r#"{"time":0,"direction":"in","serial":"A","data":"00010203"}"#,
+    )
+    .unwrap();
+    let replay = Replay::new(frames);
+    let mut bytes = [0; 2];
+    match replay.read("A", &mut bytes) {
+        Err(IoError::Usb(UsbError::Protocol(_))) => {}
+        _ => panic!("expected frame to be too large"),
+    }
+}
+
+#[test]
+fn test_record_backend() {
+    let path = temp("backend");
+    let bus = VirtualBus::new();
+    bus.attach("usb", Arc::new(Simulator::new("SID")));
+    let file = std::fs::File::create(&path).unwrap();
+    let mut io = Io::with_backend(Recorder::new(bus).file(file));
+    block_on(io.scan()).unwrap();
+    block_on(io.get("SID", "/ATX/about")).unwrap();
+    io.close().unwrap();
+    let frames = capture::load(&path).unwrap();
+    assert!(frames.len() > 4);
+    assert!(frames.iter().all(|x| x.serial == "usb"));
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1 @@
+mod capture_test;
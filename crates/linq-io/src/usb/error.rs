@@ -15,6 +15,9 @@ pub enum UsbError {
     #[error("device not found => {0}")]
     DeviceNotFound(String),
 
+    #[error("payload too large => {0} bytes (max {1})")]
+    Overflow(usize, usize),
+
     #[error("failed to parse => {0}")]
     Parser(String),
 
@@ -1,8 +1,9 @@
-use super::binding::Driver;
+use crate::capabilities::Capabilities;
+use linq_db::k64::About;
 use serde::{Deserialize, Serialize};
 
 /// Helper for reading summary
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Summary {
     pub vendor: u32,
     pub product: u32,
@@ -15,19 +16,23 @@ pub struct UsbMetadata {
     pub pid: u32,
     pub sid: String,
     pub serial: String,
-    #[serde(skip)]
-    pub driver: Driver,
+    #[serde(default)]
+    pub capabilities: Capabilities,
+    /// What the device reported at /ATX/about when it was opened
+    #[serde(default)]
+    pub about: Option<About>,
 }
 
 impl UsbMetadata {
-    pub fn new(serial: &str, driver: Driver, s: &Summary) -> Self {
+    pub fn new(serial: &str, s: &Summary) -> Self {
         let (vid, pid, sid) = (s.vendor, s.product, s.serial.to_owned());
         UsbMetadata {
             vid,
             pid,
             sid,
             serial: serial.to_owned(),
-            driver,
+            capabilities: Capabilities::default(),
+            about: None,
         }
     }
 }
@@ -1,11 +1,26 @@
+#[cfg(test)]
+mod tests;
+
+mod backend;
 mod binding;
 mod channel;
 mod drivers;
 mod metadata;
+mod registry;
 mod thread;
+mod virtual_bus;
 
 pub mod error;
 pub mod usb;
 
 pub type UsbChannel = channel::UsbChannel;
 pub type UsbMetadata = metadata::UsbMetadata;
+pub use backend::Backend;
+pub(crate) use binding::Binding;
+pub use drivers::capture;
+pub use drivers::driver::{DriverFactory, UsbDriver};
+pub use drivers::k64::{self, Fault, Simulator, K64};
+pub use drivers::m5::M5;
+pub use metadata::Summary;
+pub use registry::{Product, ProductRegistry, Transport};
+pub use virtual_bus::VirtualBus;
@@ -0,0 +1,157 @@
+use super::drivers::driver::{DriverFactory, UsbDriver};
+use super::drivers::{k64::K64, m5::M5};
+use crate::error::*;
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+pub const VID_K64: u32 = 0x0461;
+pub const PID_K64: u32 = 0x0020;
+pub const VID_M5: u32 = 0x3333;
+pub const PID_M5: u32 = 0x4444;
+
+/// How the usb binding talks to a product
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    Hid,
+    Cdc,
+}
+
+/// A usb product we know how to talk to
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Product {
+    #[serde(deserialize_with = "id")]
+    pub vid: u32,
+    #[serde(deserialize_with = "id")]
+    pub pid: u32,
+    pub transport: Transport,
+    /// Name of the driver that speaks to this product IE: "k64"
+    pub driver: String,
+}
+
+impl Product {
+    pub fn new(vid: u32, pid: u32, transport: Transport, driver: &str) -> Self {
+        Product {
+            vid,
+            pid,
+            transport,
+            driver: driver.to_owned(),
+        }
+    }
+}
+
+/// Config files may write ids as numbers or as hex strings IE: "0x0461"
+fn id<'de, D: Deserializer<'de>>(d: D) -> std::result::Result<u32, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Id {
+        Number(u32),
+        Text(String),
+    }
+    match Id::deserialize(d)? {
+        Id::Number(n) => Ok(n),
+        Id::Text(s) => {
+            let hex = s.trim_start_matches("0x").trim_start_matches("0X");
+            u32::from_str_radix(hex, 16).map_err(de::Error::custom)
+        }
+    }
+}
+
+/// The usb products we scan for, and the drivers that speak to them. The
+/// default registry contains the K64 and the M5.
+#[derive(Clone)]
+pub struct ProductRegistry {
+    products: Vec<Product>,
+    drivers: BTreeMap<String, DriverFactory>,
+}
+
+impl Default for ProductRegistry {
+    fn default() -> Self {
+        ProductRegistry::empty()
+            .driver("k64", Arc::new(|| Box::new(K64::new())))
+            .driver("m5", Arc::new(|| Box::new(M5::new())))
+            .register(Product::new(VID_K64, PID_K64, Transport::Hid, "k64"))
+            .register(Product::new(VID_M5, PID_M5, Transport::Cdc, "m5"))
+    }
+}
+
+impl fmt::Debug for ProductRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProductRegistry")
+            .field("products", &self.products)
+            .field("drivers", &self.drivers.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl ProductRegistry {
+    pub fn new() -> Self {
+        ProductRegistry::default()
+    }
+
+    /// A registry without any products or drivers
+    pub fn empty() -> Self {
+        ProductRegistry {
+            products: vec![],
+            drivers: BTreeMap::new(),
+        }
+    }
+
+    /// Add a product. (Replaces a product with the same vid and pid)
+    pub fn register(mut self, product: Product) -> Self {
+        self.insert(product);
+        self
+    }
+
+    /// Same as register except by reference
+    pub fn insert(&mut self, product: Product) {
+        let (vid, pid) = (product.vid, product.pid);
+        self.products.retain(|x| !(x.vid == vid && x.pid == pid));
+        self.products.push(product);
+    }
+
+    /// Parse a JSON array of products and add them to the registry
+    pub fn parse(self, s: &str) -> Result<Self> {
+        serde_json::from_str::<Vec<Product>>(s)
+            .map_err(|e| IoError::Parser(e.to_string()))
+            .map(|products| products.into_iter().fold(self, Self::register))
+    }
+
+    /// Same as parse except read from a config file
+    pub fn load(self, path: &str) -> Result<Self> {
+        self.parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Find the product with [vid] and [pid]
+    pub fn find(&self, vid: u32, pid: u32) -> Option<&Product> {
+        self.products.iter().find(|x| x.vid == vid && x.pid == pid)
+    }
+
+    pub fn products(&self) -> &[Product] {
+        &self.products
+    }
+
+    /// Add a driver named [name]. (Replaces a driver with the same name)
+    pub fn driver(mut self, name: &str, factory: DriverFactory) -> Self {
+        self.insert_driver(name, factory);
+        self
+    }
+
+    /// Same as driver except by reference
+    pub fn insert_driver(&mut self, name: &str, factory: DriverFactory) {
+        self.drivers.insert(name.to_owned(), factory);
+    }
+
+    /// Create a driver for a device of a product using driver [name]
+    pub fn create(&self, name: &str) -> Option<Box<dyn UsbDriver>> {
+        self.drivers.get(name).map(|f| f())
+    }
+
+    /// Names of every registered driver
+    pub fn drivers(&self) -> Vec<&str> {
+        self.drivers.keys().map(|x| x.as_str()).collect()
+    }
+}
@@ -0,0 +1,178 @@
+use crate::capabilities::Capabilities;
+use crate::error::*;
+use crate::io::Io;
+use crate::request::Request;
+use crate::update::DashboardUpdatePackets;
+use crate::usb::{Backend, Product, ProductRegistry, Simulator};
+use crate::usb::{Transport, UsbDriver, VirtualBus, K64};
+use futures::executor::block_on;
+use futures::prelude::*;
+use linq_db::k64::Update;
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// A third party driver that wraps the K64 driver and counts requests
+struct Counter {
+    inner: K64,
+    requests: usize,
+    closed: Arc<AtomicUsize>,
+    updates: Arc<AtomicUsize>,
+}
+
+impl Counter {
+    fn factory(
+        closed: Arc<AtomicUsize>,
+        updates: Arc<AtomicUsize>,
+    ) -> Box<dyn UsbDriver> {
+        Box::new(Counter {
+            inner: K64::new(),
+            requests: 0,
+            closed,
+            updates,
+        })
+    }
+}
+
+impl UsbDriver for Counter {
+    fn open(&mut self, bus: &dyn Backend, sid: &str) -> Result<String> {
+        self.inner.open(bus, sid)
+    }
+
+    fn request(
+        &mut self,
+        bus: &dyn Backend,
+        sid: &str,
+        r: Request,
+    ) -> Result<Vec<u8>> {
+        self.requests += 1;
+        let r = match self.requests {
+            1 => r,
+            _ => Request::get("/ATX/about/sid"),
+        };
+        self.inner.request(bus, sid, r)
+    }
+
+    fn update(
+        &mut self,
+        bus: &dyn Backend,
+        sid: &str,
+        update: &Update,
+    ) -> Result<()> {
+        self.updates.fetch_add(1, Ordering::SeqCst);
+        self.inner.update(bus, sid, update)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+
+    fn close(&mut self, _bus: &dyn Backend, _sid: &str) -> Result<()> {
+        self.closed.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_driver_default() {
+    let registry = ProductRegistry::default();
+    assert_eq!(registry.drivers(), vec!["k64", "m5"]);
+    // Nothing is known about a device until it is opened
+    let k64 = registry.create("k64").unwrap();
+    assert_eq!(k64.capabilities(), Capabilities::default());
+    assert!(registry.create("nope").is_none());
+    assert!(ProductRegistry::empty().drivers().is_empty());
+}
+
+#[test]
+fn test_driver_register() {
+    let bus = VirtualBus::new();
+    bus.attach_as("usb", 0x1234, 0x0001, Arc::new(Simulator::new("SID")));
+    let mut io = Io::with_backend(bus);
+    let closed = Arc::new(AtomicUsize::new(0));
+    let count = closed.clone();
+    let updates = Arc::new(AtomicUsize::new(0));
+    let factory =
+        Arc::new(move || Counter::factory(count.clone(), updates.clone()));
+    block_on(io.register_driver("counter", factory)).unwrap();
+    let product = Product::new(0x1234, 0x0001, Transport::Hid, "counter");
+    block_on(io.register(product)).unwrap();
+    assert_eq!(block_on(io.scan()).unwrap()[0].serial, "SID");
+
+    // Driver state persists between requests
+    let about = block_on(io.get("SID", "/ATX/about")).unwrap();
+    assert!(about.contains("siteId"));
+    let about = block_on(io.get("SID", "/ATX/about")).unwrap();
+    assert_eq!(about, "{\"sid\":\"SID\"}");
+
+    // Drivers are closed on rescan and on close
+    block_on(io.scan()).unwrap();
+    assert_eq!(closed.load(Ordering::SeqCst), 1);
+    io.close().unwrap();
+    assert_eq!(closed.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_driver_device_not_found() {
+    let bus = VirtualBus::new();
+    bus.attach("usb", Arc::new(Simulator::new("SID")));
+    let mut io = Io::with_backend(bus.clone());
+    block_on(io.scan()).unwrap();
+    bus.detach("usb");
+    match block_on(io.get("SID", "/ATX/about")) {
+        Err(IoError::Usb(UsbError::DeviceNotFound(s))) => assert_eq!(s, "usb"),
+        r => panic!("expected device not found {:?}", r),
+    }
+    io.close().unwrap();
+}
+
+#[test]
+fn test_driver_capabilities() {
+    let bus = VirtualBus::new();
+    bus.attach("usb", Arc::new(Simulator::new("SID")));
+    let mut io = Io::with_backend(bus);
+    let meta = block_on(io.scan()).unwrap();
+    assert!(meta[0].capabilities.update);
+    assert!(meta[0].capabilities.network);
+    assert_eq!(io.capabilities("SID").unwrap(), meta[0].capabilities);
+    assert_eq!(io.meta()[0].capabilities, meta[0].capabilities);
+    assert!(io.capabilities("nope").is_err());
+    io.close().unwrap();
+}
+
+#[test]
+fn test_driver_update() {
+    let bus = VirtualBus::new();
+    let sim = Arc::new(Simulator::new("SID"));
+    bus.attach_as("usb", 0x1234, 0x0001, sim.clone());
+    let mut io = Io::with_backend(bus);
+    let (closed, updates) = (Arc::default(), Arc::new(AtomicUsize::new(0)));
+    let count = updates.clone();
+    let factory =
+        Arc::new(move || Counter::factory(Arc::clone(&closed), count.clone()));
+    block_on(io.register_driver("counter", factory)).unwrap();
+    let product = Product::new(0x1234, 0x0001, Transport::Hid, "counter");
+    block_on(io.register(product)).unwrap();
+    block_on(io.scan()).unwrap();
+
+    // Every chunk goes through the driver
+    let update = json!({
+        "files": [
+            { "update": [
+                { "type": "firmware", "size": 4, "offset": 0,
+                  "payload": "AAAA", "md5": "" },
+                { "type": "firmware", "size": 4, "offset": 4,
+                  "payload": "BBBB", "md5": "" }
+            ]},
+            { "update": [] }
+        ]
+    });
+    let pack = DashboardUpdatePackets::parse(&update.to_string()).unwrap();
+    let progress: Vec<(usize, usize)> =
+        block_on(io.update("SID", pack, 0).try_collect()).unwrap();
+    assert_eq!(progress, vec![(1, 2), (2, 2)]);
+    assert_eq!(updates.load(Ordering::SeqCst), 2);
+    let offsets: Vec<u32> = sim.updates().iter().map(|x| x.offset).collect();
+    assert_eq!(offsets, vec![0, 4]);
+    io.close().unwrap();
+}
@@ -0,0 +1,2 @@
+mod driver_test;
+mod registry_test;
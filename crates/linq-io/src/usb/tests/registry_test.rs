@@ -0,0 +1,85 @@
+use crate::error::*;
+use crate::io::Io;
+use crate::usb::registry::{PID_K64, VID_K64};
+use crate::usb::{Product, ProductRegistry, Simulator, Transport, VirtualBus};
+use futures::executor::block_on;
+use std::sync::Arc;
+
+#[test]
+fn test_registry_default() {
+    let registry = ProductRegistry::default();
+    assert_eq!(registry.products().len(), 2);
+    let k64 = registry.find(VID_K64, PID_K64).unwrap();
+    assert_eq!(k64.transport, Transport::Hid);
+    assert_eq!(k64.driver, "k64");
+    assert_eq!(registry.find(0x3333, 0x4444).unwrap().driver, "m5");
+    assert!(registry.find(VID_K64, 0x4444).is_none());
+    assert!(ProductRegistry::empty().products().is_empty());
+}
+
+#[test]
+fn test_registry_register() {
+    let registry = ProductRegistry::default()
+        .register(Product::new(0x1234, 0x0001, Transport::Hid, "k64"))
+        .register(Product::new(VID_K64, PID_K64, Transport::Hid, "custom"));
+    assert_eq!(registry.products().len(), 3);
+    assert_eq!(registry.find(VID_K64, PID_K64).unwrap().driver, "custom");
+    assert!(registry.find(0x1234, 0x0001).is_some());
+}
+
+#[test]
+fn test_registry_parse() {
+    let registry = ProductRegistry::empty()
+        .parse(
+            r#"[
+                {"vid":"0x1234","pid":1,"transport":"hid","driver":"k64"},
+                {"vid":4660,"pid":"0002","transport":"cdc","driver":"m5"}
+            ]"#,
+        )
+        .unwrap();
+    let expect = [
+        Product::new(0x1234, 1, Transport::Hid, "k64"),
+        Product::new(0x1234, 2, Transport::Cdc, "m5"),
+    ];
+    assert_eq!(registry.products(), &expect[..]);
+    let bad = r#"[{"vid":"0xzz","pid":1,"transport":"hid","driver":"k64"}]"#;
+    assert!(ProductRegistry::empty().parse(bad).is_err());
+    let bad = r#"[{"vid":1,"pid":1,"transport":"serial","driver":"k64"}]"#;
+    assert!(ProductRegistry::empty().parse(bad).is_err());
+    assert!(ProductRegistry::empty()
+        .load("/does/not/exist.json")
+        .is_err());
+}
+
+#[test]
+fn test_registry_scan() {
+    let bus = VirtualBus::new();
+    bus.attach_as("usb", 0x1234, 0x0001, Arc::new(Simulator::new("SID")));
+    let mut io = Io::with_backend(bus);
+
+    // Unregistered product
+    match block_on(io.scan()) {
+        Err(IoError::Usb(UsbError::Protocol(e))) => {
+            assert_eq!(e, "invalid product [1234/0001]")
+        }
+        _ => panic!("expected invalid product"),
+    }
+
+    // Registered with a driver we do not have
+    let product = Product::new(0x1234, 0x0001, Transport::Hid, "nope");
+    block_on(io.register(product)).unwrap();
+    assert!(block_on(io.scan()).is_err());
+
+    // Registered at runtime from a config file
+    let path = std::env::temp_dir()
+        .join(format!("linq-products-{}.json", std::process::id()));
+    let json =
+        r#"[{"vid":"0x1234","pid":"0x0001","transport":"hid","driver":"k64"}]"#;
+    std::fs::write(&path, json).unwrap();
+    block_on(io.register_file(path.to_str().unwrap())).unwrap();
+    let meta = block_on(io.scan()).unwrap();
+    assert_eq!(meta[0].serial, "SID");
+    assert_eq!((meta[0].vid, meta[0].pid), (0x1234, 0x0001));
+    std::fs::remove_file(&path).unwrap();
+    io.close().unwrap();
+}
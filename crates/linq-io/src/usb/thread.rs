@@ -1,58 +1,200 @@
-use super::binding::{Binding, Driver};
-use super::metadata::UsbMetadata;
+use super::backend::{self, BoxBackend};
+use super::drivers::driver::{DriverFactory, UsbDriver};
+use super::metadata::{Summary, UsbMetadata};
+use super::registry::{Product, ProductRegistry};
 use crate::error::*;
 use crate::request::Request;
 use futures::channel::oneshot::Sender as OneshotSender;
+use linq_db::k64::Update;
+use linq_util::gen_log_helpers;
+use std::collections::HashMap;
 use std::sync::mpsc::Receiver;
 
+gen_log_helpers!("USB");
+
 // TODO scan should return some usb device descriptions
 pub struct UsbRequestScan {
     pub response: OneshotSender<Result<Vec<UsbMetadata>>>,
 }
+pub struct UsbRequestSummary {
+    pub response: OneshotSender<Result<Vec<Summary>>>,
+}
 pub struct UsbRequestDevice {
-    pub response: OneshotSender<Result<String>>,
+    pub response: OneshotSender<Result<Vec<u8>>>,
     pub serial: String,
     pub request: Request,
-    pub driver: Driver,
+}
+pub struct UsbRequestUpdate {
+    pub response: OneshotSender<Result<()>>,
+    pub serial: String,
+    pub update: Update,
+}
+pub struct UsbRequestRegister {
+    pub response: OneshotSender<Result<()>>,
+    pub product: Product,
+}
+pub struct UsbRequestDriver {
+    pub response: OneshotSender<Result<()>>,
+    pub name: String,
+    pub factory: DriverFactory,
 }
 pub enum UsbRequest {
     Scan(UsbRequestScan),
+    Summary(UsbRequestSummary),
     Device(UsbRequestDevice),
+    Update(UsbRequestUpdate),
+    Register(UsbRequestRegister),
+    Driver(UsbRequestDriver),
     Close,
 }
 
+/// The driver of every open device, keyed by usb serial number
+type Drivers = HashMap<String, Box<dyn UsbDriver>>;
+
+/// Forget every open device
+fn close(backend: &BoxBackend, drivers: &mut Drivers) {
+    for (sid, mut driver) in drivers.drain() {
+        if let Err(e) = driver.close(backend.as_ref(), &sid) {
+            warn!("[{}] failed to close => {}", sid, e);
+        }
+    }
+}
+
+/// This helper routine converts our result so all our match arms match
+/// when dispatching requests
+fn scan(
+    backend: &mut BoxBackend,
+    registry: &ProductRegistry,
+    drivers: &mut Drivers,
+    request: UsbRequestScan,
+) -> Result<()> {
+    close(backend, drivers);
+    let result = backend::scan(backend, registry).map(|found| {
+        found
+            .into_iter()
+            .map(|(meta, driver)| {
+                drivers.insert(meta.sid.clone(), driver);
+                meta
+            })
+            .collect()
+    });
+    request
+        .response
+        .send(result)
+        .map_err(|_| IoError::Unknown)?;
+    Ok(())
+}
+
+/// This helper routine converts our result so all our match arms match
+/// when dispatching requests
+fn summary(backend: &mut BoxBackend, request: UsbRequestSummary) -> Result<()> {
+    request
+        .response
+        .send(backend.scan())
+        .map_err(|_| IoError::Unknown)?;
+    Ok(())
+}
+
 /// This helper routine converts our result so all our match arms match
 /// when dispatching requests
-fn scan(binding: &mut Binding, request: UsbRequestScan) -> Result<()> {
+fn request(
+    backend: &BoxBackend,
+    drivers: &mut Drivers,
+    request: UsbRequestDevice,
+) -> Result<()> {
+    let serial = &request.serial;
+    let result = match drivers.get_mut(serial) {
+        Some(d) => d.request(backend.as_ref(), serial, request.request),
+        None => Err(UsbError::DeviceNotFound(serial.to_owned()).into()),
+    };
     request
         .response
-        .send(binding.scan())
+        .send(result)
         .map_err(|_| IoError::Unknown)?;
     Ok(())
 }
 
 /// This helper routine converts our result so all our match arms match
 /// when dispatching requests
-fn request(binding: &mut Binding, request: UsbRequestDevice) -> Result<()> {
+fn update(
+    backend: &BoxBackend,
+    drivers: &mut Drivers,
+    request: UsbRequestUpdate,
+) -> Result<()> {
+    let serial = &request.serial;
+    let result = match drivers.get_mut(serial) {
+        Some(d) => d.update(backend.as_ref(), serial, &request.update),
+        None => Err(UsbError::DeviceNotFound(serial.to_owned()).into()),
+    };
     request
         .response
-        .send(binding.request(&request.serial, request.request, request.driver))
+        .send(result)
+        .map_err(|_| IoError::Unknown)?;
+    Ok(())
+}
+
+/// This helper routine converts our result so all our match arms match
+/// when dispatching requests
+fn register(
+    backend: &mut BoxBackend,
+    registry: &mut ProductRegistry,
+    request: UsbRequestRegister,
+) -> Result<()> {
+    let result = backend.add_product(&request.product);
+    if result.is_ok() {
+        registry.insert(request.product);
+    }
+    request
+        .response
+        .send(result)
+        .map_err(|_| IoError::Unknown)?;
+    Ok(())
+}
+
+/// This helper routine converts our result so all our match arms match
+/// when dispatching requests
+fn driver(
+    registry: &mut ProductRegistry,
+    request: UsbRequestDriver,
+) -> Result<()> {
+    registry.insert_driver(&request.name, request.factory);
+    request
+        .response
+        .send(Ok(()))
         .map_err(|_| IoError::Unknown)?;
     Ok(())
 }
 
 /// Main usb worker. Simply receives requests and dispaches them to the usb
-/// driver. We need this thread to provide a non blocking api for usb comm
-pub fn usb_thread(rx: Receiver<UsbRequest>) {
-    let mut binding = Binding::new();
+/// driver. We need this thread to provide a non blocking api for usb comm.
+/// The backend is created on this thread (the libusb binding is not Send)
+pub fn usb_thread<F>(rx: Receiver<UsbRequest>, backend: F)
+where
+    F: FnOnce() -> BoxBackend,
+{
+    let mut backend = backend();
+    let mut registry = ProductRegistry::default();
+    let mut drivers = Drivers::new();
+    for product in registry.products() {
+        if let Err(e) = backend.add_product(product) {
+            warn!("failed to add product => {}", e);
+        }
+    }
     for r in rx.iter() {
         let result = match r {
-            UsbRequest::Scan(r) => scan(&mut binding, r),
-            UsbRequest::Device(r) => request(&mut binding, r),
+            UsbRequest::Scan(r) => {
+                scan(&mut backend, &registry, &mut drivers, r)
+            }
+            UsbRequest::Summary(r) => summary(&mut backend, r),
+            UsbRequest::Register(r) => register(&mut backend, &mut registry, r),
+            UsbRequest::Driver(r) => driver(&mut registry, r),
+            UsbRequest::Device(r) => request(&backend, &mut drivers, r),
+            UsbRequest::Update(r) => update(&backend, &mut drivers, r),
             UsbRequest::Close => break,
         };
         if result.is_err() {
             break;
         }
     }
+    close(&backend, &mut drivers);
 }
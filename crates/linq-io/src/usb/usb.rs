@@ -1,13 +1,17 @@
-use super::binding::{Binding, Driver};
+use super::backend::BoxBackend;
+use super::binding::Binding;
 use crate::error::*;
 use crate::request::Request;
 use futures::channel::oneshot;
+use linq_db::k64::Update;
 use std::future::Future;
 use std::sync::mpsc;
 use std::sync::mpsc::Sender;
 use std::thread::JoinHandle;
 
-use super::metadata::UsbMetadata;
+use super::drivers::driver::DriverFactory;
+use super::metadata::{Summary, UsbMetadata};
+use super::registry::Product;
 use super::thread::*;
 
 /// Our Usb Binding is Syncronous. We delegate it to it's own thread
@@ -19,8 +23,16 @@ pub struct Usb {
 /// We wrap our usb binding with a "Manager" class that provides async api
 impl Usb {
     pub fn new() -> Self {
+        Usb::with_backend(|| Box::new(Binding::new()))
+    }
+
+    /// Same as new except requests are served by some other usb [backend]
+    pub fn with_backend<F>(backend: F) -> Self
+    where
+        F: FnOnce() -> BoxBackend + Send + 'static,
+    {
         let (tx, rx) = mpsc::channel();
-        let join_handle = std::thread::spawn(move || usb_thread(rx));
+        let join_handle = std::thread::spawn(move || usb_thread(rx, backend));
         let join_handle = Some(join_handle);
         Usb { tx, join_handle }
     }
@@ -39,20 +51,77 @@ impl Usb {
         async { rx.await.map_err(|_| IoError::Unknown)? }
     }
 
+    /// Describe the devices on the bus without opening them
+    pub fn summary(&self) -> impl Future<Output = Result<Vec<Summary>>> {
+        let (tx, rx) = oneshot::channel::<Result<Vec<Summary>>>();
+        self.tx
+            .send(UsbRequest::Summary(UsbRequestSummary { response: tx }))
+            .expect("Usb channel has been closed!");
+        async { rx.await.map_err(|_| IoError::Unknown)? }
+    }
+
     /// Async wrapper for request
     pub fn request<'a>(
         &self,
         serial: &'a str,
         request: Request,
-        driver: Driver,
-    ) -> impl Future<Output = Result<String>> {
-        let (tx, rx) = oneshot::channel::<Result<String>>();
+    ) -> impl Future<Output = Result<Vec<u8>>> {
+        let (tx, rx) = oneshot::channel::<Result<Vec<u8>>>();
         self.tx
             .send(UsbRequest::Device(UsbRequestDevice {
                 request,
                 serial: serial.to_owned(),
                 response: tx,
-                driver,
+            }))
+            .expect("Usb channel has been closed!");
+        async { rx.await.map_err(|_| IoError::Unknown)? }
+    }
+
+    /// Async wrapper for sending one chunk of an update
+    pub fn update(
+        &self,
+        serial: &str,
+        update: Update,
+    ) -> impl Future<Output = Result<()>> {
+        let (tx, rx) = oneshot::channel::<Result<()>>();
+        self.tx
+            .send(UsbRequest::Update(UsbRequestUpdate {
+                update,
+                serial: serial.to_owned(),
+                response: tx,
+            }))
+            .expect("Usb channel has been closed!");
+        async { rx.await.map_err(|_| IoError::Unknown)? }
+    }
+
+    /// Look for [product] during future scans
+    pub fn register(
+        &self,
+        product: Product,
+    ) -> impl Future<Output = Result<()>> {
+        let (tx, rx) = oneshot::channel::<Result<()>>();
+        self.tx
+            .send(UsbRequest::Register(UsbRequestRegister {
+                product,
+                response: tx,
+            }))
+            .expect("Usb channel has been closed!");
+        async { rx.await.map_err(|_| IoError::Unknown)? }
+    }
+
+    /// Speak to products registered with driver [name] using drivers made by
+    /// [factory]
+    pub fn register_driver(
+        &self,
+        name: &str,
+        factory: DriverFactory,
+    ) -> impl Future<Output = Result<()>> {
+        let (tx, rx) = oneshot::channel::<Result<()>>();
+        self.tx
+            .send(UsbRequest::Driver(UsbRequestDriver {
+                name: name.to_owned(),
+                factory,
+                response: tx,
             }))
             .expect("Usb channel has been closed!");
         async { rx.await.map_err(|_| IoError::Unknown)? }
@@ -0,0 +1,92 @@
+use super::backend::Backend;
+use super::drivers::driver::{Reader, Writer};
+use super::drivers::k64::Simulator;
+use super::metadata::Summary;
+use super::registry::{PID_K64, VID_K64};
+use crate::error::*;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// A device plugged into the bus and the ids it enumerates with
+struct Device {
+    vid: u32,
+    pid: u32,
+    sim: Arc<Simulator>,
+}
+
+/// An in process usb bus of simulated devices. Clones share the same bus so
+/// devices can be attached and detached while an Io is using the bus
+#[derive(Clone, Default)]
+pub struct VirtualBus {
+    devices: Arc<Mutex<BTreeMap<String, Device>>>,
+}
+
+impl VirtualBus {
+    pub fn new() -> Self {
+        VirtualBus::default()
+    }
+
+    /// Plug a device into the bus. [serial] is the usb serial number, which
+    /// (like real hardware) need not match the sid the device reports
+    pub fn attach(&self, serial: &str, device: Arc<Simulator>) {
+        self.attach_as(serial, VID_K64, PID_K64, device)
+    }
+
+    /// Same as attach except the device enumerates as [vid]/[pid]
+    pub fn attach_as(
+        &self,
+        serial: &str,
+        vid: u32,
+        pid: u32,
+        device: Arc<Simulator>,
+    ) {
+        let device = Device {
+            vid,
+            pid,
+            sim: device,
+        };
+        self.devices
+            .lock()
+            .unwrap()
+            .insert(serial.to_owned(), device);
+    }
+
+    /// Unplug a device from the bus
+    pub fn detach(&self, serial: &str) -> Option<Arc<Simulator>> {
+        self.devices.lock().unwrap().remove(serial).map(|x| x.sim)
+    }
+
+    /// Helper to find a device or fail like a missing usb device
+    fn device(&self, serial: &str) -> Result<Arc<Simulator>> {
+        self.devices
+            .lock()
+            .unwrap()
+            .get(serial)
+            .map(|x| x.sim.clone())
+            .ok_or_else(|| UsbError::DeviceNotFound(serial.to_owned()).into())
+    }
+}
+
+impl Backend for VirtualBus {
+    fn scan(&mut self) -> Result<Vec<Summary>> {
+        Ok(self
+            .devices
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(serial, x)| Summary {
+                vendor: x.vid,
+                product: x.pid,
+                serial: serial.to_owned(),
+            })
+            .collect())
+    }
+
+    fn send(&self, serial: &str, bytes: &[u8]) -> Result<usize> {
+        self.device(serial)?.write(serial, bytes)
+    }
+
+    fn recv(&self, serial: &str, bytes: &mut [u8]) -> Result<usize> {
+        self.device(serial)?.read(serial, bytes)
+    }
+}
@@ -0,0 +1,250 @@
+use super::CurveConfig;
+use crate::channel::{AboutCache, AsyncRequester, Channel, Meta};
+use crate::credentials::{authenticate, CredentialProvider};
+use crate::error::*;
+use crate::metadata::{DeviceMetadata, Location};
+use crate::request::{Auth, Method, Request};
+use futures::channel::oneshot;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How long we wait on a device before giving up (milliseconds)
+const TIMEOUT: i32 = 10000;
+
+/// Every linq message starts with a version frame and a type frame
+pub const VERSION: u8 = 0;
+pub const TYPE_REQUEST: u8 = 1;
+pub const TYPE_RESPONSE: u8 = 2;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ZmtpMetadata {
+    /// Endpoint we connect to (IE: tcp://192.168.0.10:33455)
+    pub endpoint: String,
+}
+
+/// Convert an address to a zmq endpoint. IE: "10.0.0.1:33455" is
+/// "tcp://10.0.0.1:33455"
+pub fn endpoint(address: &str) -> String {
+    match address.contains("://") {
+        true => address.to_owned(),
+        false => format!("tcp://{}", address),
+    }
+}
+
+/// The frames of a request. [ver, type, serial, "METHOD path", body?]
+pub fn request_frames(serial: &str, r: &Request) -> Result<Vec<Vec<u8>>> {
+    if r.method == Method::Raw {
+        let e = "raw requests are not supported over zmtp";
+        return Err(IoError::Parser(e.to_string()));
+    }
+    let path = format!("{} {}", r.method, r.path_and_query());
+    let mut frames = vec![
+        vec![VERSION],
+        vec![TYPE_REQUEST],
+        serial.as_bytes().to_vec(),
+        path.into_bytes(),
+    ];
+    if !r.body.is_empty() {
+        frames.push(r.body.as_bytes().to_vec());
+    }
+    Ok(frames)
+}
+
+/// Parse a response. [ver, type, serial, error (u16 big endian), body?]
+/// Returns None when the message is not a response (IE: a heartbeat)
+pub fn parse_response(
+    frames: &[Vec<u8>],
+) -> Result<Option<(String, i64, Vec<u8>)>> {
+    match frames {
+        [_, t, ..] if t[..] != [TYPE_RESPONSE] => Ok(None),
+        [v, _, serial, error, body @ ..]
+            if v[..] == [VERSION] && error.len() == 2 && body.len() < 2 =>
+        {
+            let serial = String::from_utf8_lossy(serial).to_string();
+            let error = u16::from_be_bytes([error[0], error[1]]) as i64;
+            let body = body.first().cloned().unwrap_or(vec![]);
+            Ok(Some((serial, error, body)))
+        }
+        _ => Err(IoError::Parser("bad zmtp response".to_string())),
+    }
+}
+
+/// A socket plus the credentials it was connected with. ZMTP authenticates
+/// when the connection is made (PLAIN mechanism) so when the credentials
+/// change we must reconnect.
+struct Connection {
+    context: zmq::Context,
+    endpoint: String,
+    socket: Option<zmq::Socket>,
+    auth: Option<Auth>,
+    curve: Option<Arc<CurveConfig>>,
+    /// When the socket was connected (seconds since the unix epoch, 0 when
+    /// never connected). Shared with the channel so reading metadata does not
+    /// wait on a request holding the connection
+    connected: Arc<AtomicU64>,
+}
+
+impl Connection {
+    fn connect(
+        &mut self,
+        serial: &str,
+        auth: &Option<Auth>,
+    ) -> Result<&zmq::Socket> {
+        if self.socket.is_none() || self.auth != *auth {
+            let socket = self.context.socket(zmq::DEALER)?;
+            socket.set_linger(0)?;
+            socket.set_rcvtimeo(TIMEOUT)?;
+            socket.set_sndtimeo(TIMEOUT)?;
+            // NOTE ZMTP has no notion of a bearer token so a token is sent as
+            //      the PLAIN password with an empty user name. And with CURVE
+            //      our key identifies us so credentials are not sent at all
+            let keys = [serial, self.endpoint.as_str()];
+            match (&self.curve, auth) {
+                (Some(curve), _) => curve.apply(&socket, &keys)?,
+                (None, Some(Auth::Basic { user, password })) => {
+                    socket.set_plain_username(Some(user))?;
+                    socket.set_plain_password(Some(password))?;
+                }
+                (None, Some(Auth::Token(token))) => {
+                    socket.set_plain_username(Some(""))?;
+                    socket.set_plain_password(Some(token))?;
+                }
+                (None, None) => {}
+            }
+            socket.connect(&self.endpoint)?;
+            self.socket = Some(socket);
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|x| x.as_secs())
+                .unwrap_or(0);
+            self.connected.store(now, Ordering::Relaxed);
+            self.auth = auth.clone();
+        }
+        Ok(self.socket.as_ref().unwrap())
+    }
+
+    fn request(&mut self, serial: &str, r: &Request) -> Result<Vec<u8>> {
+        let frames = request_frames(serial, r)?;
+        let socket = self.connect(serial, &r.auth)?;
+        let result = socket.send_multipart(frames, 0).and_then(|_| loop {
+            let frames = socket.recv_multipart(0)?;
+            match parse_response(&frames) {
+                Ok(Some((sid, e, body))) if sid == serial => {
+                    break Ok(Ok((e, body)));
+                }
+                Ok(_) => continue, // heartbeats, alerts, etc
+                Err(e) => break Ok(Err(e)),
+            }
+        });
+        match result {
+            Ok(Ok((0, body))) => translate_response(&r.path, None, body),
+            Ok(Ok((e, body))) => translate_response(&r.path, Some(e), body),
+            Ok(Err(e)) => Err(e),
+            Err(e) => {
+                // Drop the socket so we do not read a stale response later
+                self.socket = None;
+                Err(e.into())
+            }
+        }
+    }
+}
+
+/// A device reachable over ZMTP (IE: a device, or a linq-network server
+/// that routes requests to devices by serial number). When [curve] is given
+/// the connection is encrypted (zmtps)
+pub struct ZmtpChannel {
+    pub meta: ZmtpMetadata,
+    connection: Arc<Mutex<Connection>>,
+    connected: Arc<AtomicU64>,
+    credentials: Option<Arc<dyn CredentialProvider>>,
+    about: AboutCache,
+}
+
+impl ZmtpChannel {
+    pub fn new(
+        address: &str,
+        credentials: Option<Arc<dyn CredentialProvider>>,
+        curve: Option<Arc<CurveConfig>>,
+    ) -> Self {
+        let endpoint = endpoint(address);
+        let connected = Arc::new(AtomicU64::new(0));
+        let connection = Arc::new(Mutex::new(Connection {
+            context: zmq::Context::new(),
+            endpoint: endpoint.clone(),
+            socket: None,
+            auth: None,
+            curve,
+            connected: Arc::clone(&connected),
+        }));
+        let meta = ZmtpMetadata { endpoint };
+        ZmtpChannel {
+            meta,
+            connection,
+            connected,
+            credentials,
+            about: AboutCache::default(),
+        }
+    }
+
+    /// Read /ATX/about from the device with serial number [serial] in the
+    /// background. (The channel is usable right away)
+    pub fn open(self, serial: &str) -> Self {
+        self.about.read(self.sender(serial));
+        self
+    }
+
+    /// A blocking request to the device (for a thread of its own)
+    fn sender(
+        &self,
+        serial: &str,
+    ) -> impl FnOnce(Request) -> Result<Vec<u8>> + Send + 'static {
+        let connection = Arc::clone(&self.connection);
+        let credentials = self.credentials.clone();
+        let endpoint = self.meta.endpoint.clone();
+        let serial = serial.to_owned();
+        move |r| {
+            let keys = [serial.as_str(), endpoint.as_str()];
+            let provider = credentials.as_deref();
+            let mut connection = connection.lock().unwrap();
+            authenticate(provider, &keys, r, |r| connection.request(&serial, r))
+        }
+    }
+}
+
+impl Channel for ZmtpChannel {}
+impl AsyncRequester for ZmtpChannel {
+    fn request_bytes<'a>(
+        &'a self,
+        serial: &'a str,
+        r: Request,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<u8>>> + 'a>> {
+        // Sockets are blocking so we do the work on a thread of its own
+        let (tx, rx) = oneshot::channel::<Result<Vec<u8>>>();
+        let send = self.sender(serial);
+        std::thread::spawn(move || tx.send(send(r)));
+        Box::pin(async { rx.await.map_err(|_| IoError::Unknown)? })
+    }
+}
+
+impl Meta for ZmtpChannel {
+    fn describe(&self, serial: &str) -> DeviceMetadata {
+        let endpoint = self.meta.endpoint.clone();
+        let connected = match self.connected.load(Ordering::Relaxed) {
+            0 => None,
+            at => Some(at),
+        };
+        let location = Location::Zmtp {
+            endpoint,
+            connected,
+        };
+        DeviceMetadata::new(serial, location)
+    }
+
+    fn cache(&self) -> &AboutCache {
+        &self.about
+    }
+}
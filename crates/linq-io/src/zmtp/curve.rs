@@ -0,0 +1,76 @@
+use crate::error::*;
+use std::collections::HashMap;
+
+/// Keys for encrypted ZMTP (zmtps). CURVE requires us to know the public key
+/// of every device up front, so the server key also serves as the pin.
+#[derive(Clone, Default)]
+pub struct CurveConfig {
+    /// Our key pair (z85). A key pair is generated when not provided
+    keypair: Option<(String, String)>,
+    /// Public key of each device (z85) by serial or address
+    server_keys: HashMap<String, String>,
+}
+
+impl CurveConfig {
+    pub fn new() -> Self {
+        CurveConfig::default()
+    }
+
+    /// Use a key pair (z85) that the devices know about
+    pub fn keypair(mut self, public: &str, secret: &str) -> Result<Self> {
+        CurveConfig::check(public)?;
+        CurveConfig::check(secret)?;
+        self.keypair = Some((public.to_owned(), secret.to_owned()));
+        Ok(self)
+    }
+
+    /// Public key (z85) of a device found by serial or address
+    pub fn server_key(mut self, key: &str, public: &str) -> Result<Self> {
+        CurveConfig::check(public)?;
+        self.server_keys.insert(key.to_owned(), public.to_owned());
+        Ok(self)
+    }
+
+    /// Find the server key of a device
+    pub fn find(&self, keys: &[&str]) -> Result<&str> {
+        keys.iter()
+            .find_map(|key| self.server_keys.get(*key))
+            .map(|x| x.as_str())
+            .ok_or_else(|| {
+                let e = format!("no curve key for {}", keys.join(" or "));
+                IoError::Security(e)
+            })
+    }
+
+    /// Configure a socket to connect to a device with CURVE
+    pub fn apply(&self, socket: &zmq::Socket, keys: &[&str]) -> Result<()> {
+        if zmq::has("curve") != Some(true) {
+            let e = "libzmq was built without curve support";
+            return Err(IoError::Security(e.to_string()));
+        }
+        let server = self.find(keys)?;
+        let (public, secret) = match &self.keypair {
+            Some((public, secret)) => (decode(public)?, decode(secret)?),
+            None => {
+                let keypair = zmq::CurveKeyPair::new()?;
+                (keypair.public_key.to_vec(), keypair.secret_key.to_vec())
+            }
+        };
+        socket.set_curve_serverkey(&decode(server)?)?;
+        socket.set_curve_publickey(&public)?;
+        socket.set_curve_secretkey(&secret)?;
+        Ok(())
+    }
+
+    fn check(key: &str) -> Result<()> {
+        decode(key).map(|_| ())
+    }
+}
+
+/// Helper to decode a z85 key
+fn decode(key: &str) -> Result<Vec<u8>> {
+    match zmq::z85_decode(key) {
+        Ok(key) if key.len() == 32 => Ok(key),
+        _ => Err(IoError::Security(format!("bad curve key [{}]", key))),
+    }
+}
@@ -1 +1,8 @@
-// TODO
+#[cfg(test)]
+mod tests;
+
+mod channel;
+mod curve;
+
+pub use channel::*;
+pub use curve::*;
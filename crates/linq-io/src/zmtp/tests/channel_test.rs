@@ -0,0 +1,101 @@
+use crate::channel::AsyncRequester;
+use crate::error::*;
+use crate::request::Request;
+use crate::zmtp::{self, ZmtpChannel};
+use futures::executor::block_on;
+use std::thread::JoinHandle;
+
+/// Bind a ROUTER socket that answers requests with canned (error, body)
+/// responses. A heartbeat is sent ahead of every response to make sure the
+/// channel skips messages that are not responses.
+fn serve(
+    responses: Vec<(u16, &'static str)>,
+) -> (String, JoinHandle<Vec<Vec<Vec<u8>>>>) {
+    let context = zmq::Context::new();
+    let router = context.socket(zmq::ROUTER).unwrap();
+    router.bind("tcp://127.0.0.1:*").unwrap();
+    let endpoint = router.get_last_endpoint().unwrap().unwrap();
+    let handle = std::thread::spawn(move || {
+        let _context = context;
+        responses
+            .into_iter()
+            .map(|(error, body)| {
+                let mut frames = router.recv_multipart(0).unwrap();
+                let id = frames.remove(0);
+                let serial = frames[2].clone();
+                let heartbeat =
+                    vec![id.clone(), vec![0], vec![0], serial.clone()];
+                router.send_multipart(heartbeat, 0).unwrap();
+                let response = vec![
+                    id,
+                    vec![zmtp::VERSION],
+                    vec![zmtp::TYPE_RESPONSE],
+                    serial,
+                    error.to_be_bytes().to_vec(),
+                    body.as_bytes().to_vec(),
+                ];
+                router.send_multipart(response, 0).unwrap();
+                frames
+            })
+            .collect()
+    });
+    (endpoint, handle)
+}
+
+#[test]
+fn test_endpoint() {
+    assert_eq!(zmtp::endpoint("10.0.0.1:33455"), "tcp://10.0.0.1:33455");
+    assert_eq!(zmtp::endpoint("ipc:///tmp/linq"), "ipc:///tmp/linq");
+}
+
+#[test]
+fn test_request_frames() {
+    let request = Request::builder(crate::Method::Post, "/ATX/a")
+        .query("k", "v")
+        .text("{}")
+        .build();
+    let frames = zmtp::request_frames("SID", &request).unwrap();
+    assert_eq!(
+        frames,
+        vec![
+            vec![0],
+            vec![1],
+            b"SID".to_vec(),
+            b"POST /ATX/a?k=v".to_vec(),
+            b"{}".to_vec()
+        ]
+    );
+    let frames = zmtp::request_frames("SID", &Request::get("/a")).unwrap();
+    assert_eq!(frames.len(), 4);
+    assert!(zmtp::request_frames("SID", &Request::raw(&b"a"[..])).is_err());
+}
+
+#[test]
+fn test_parse_response() {
+    let frames = vec![vec![0], vec![2], b"SID".to_vec(), vec![0, 0]];
+    let response = zmtp::parse_response(&frames).unwrap();
+    assert_eq!(response, Some(("SID".to_string(), 0, vec![])));
+    let frames = vec![vec![0], vec![0], b"SID".to_vec()];
+    assert_eq!(zmtp::parse_response(&frames).unwrap(), None);
+    let frames = vec![vec![0], vec![2], b"SID".to_vec(), vec![0]];
+    assert!(zmtp::parse_response(&frames).is_err());
+}
+
+#[test]
+fn test_channel_request() {
+    let (endpoint, server) = serve(vec![(0, "{\"a\":1}"), (404, "")]);
+    let channel = ZmtpChannel::new(&endpoint, None, None);
+    let response = block_on(channel.request_raw("SID", Request::get("/a")));
+    assert_eq!(response.unwrap(), "{\"a\":1}");
+    match block_on(channel.request_raw("SID", Request::get("/b"))) {
+        Err(IoError::ApiError(e)) => {
+            assert_eq!(e.kind, ApiErrorKind::Linq404);
+            assert_eq!(e.path, "/b");
+        }
+        _ => panic!("expected 404"),
+    }
+    let requests = server.join().unwrap();
+    assert_eq!(requests[0][2], b"SID");
+    assert_eq!(requests[0][3], b"GET /a");
+    assert_eq!(requests[1][3], b"GET /b");
+}
@@ -0,0 +1,72 @@
+use crate::channel::AsyncRequester;
+use crate::error::*;
+use crate::request::Request;
+use crate::zmtp::{self, CurveConfig, ZmtpChannel};
+use futures::executor::block_on;
+use std::sync::Arc;
+
+fn z85(seed: u8) -> String {
+    zmq::z85_encode(&[seed; 32]).unwrap()
+}
+
+fn curve_supported() -> bool {
+    zmq::has("curve") == Some(true)
+}
+
+#[test]
+fn test_curve_config() {
+    let config = CurveConfig::new()
+        .keypair(&z85(1), &z85(2))
+        .unwrap()
+        .server_key("SID", &z85(3))
+        .unwrap();
+    assert_eq!(config.find(&["SID", "tcp://x"]).unwrap(), z85(3));
+    assert_eq!(config.find(&["OTHER", "SID"]).unwrap(), z85(3));
+    match config.find(&["OTHER"]) {
+        Err(IoError::Security(_)) => {}
+        _ => panic!("expected missing key"),
+    }
+    assert!(CurveConfig::new().keypair("bad", &z85(2)).is_err());
+    assert!(CurveConfig::new().server_key("SID", "short").is_err());
+}
+
+#[test]
+fn test_zmtps_request() {
+    let context = zmq::Context::new();
+    let router = context.socket(zmq::ROUTER).unwrap();
+    let server_keys = match curve_supported() {
+        true => Some(zmq::CurveKeyPair::new().unwrap()),
+        false => None,
+    };
+    if let Some(keys) = &server_keys {
+        router.set_curve_server(true).unwrap();
+        router.set_curve_secretkey(&keys.secret_key).unwrap();
+    }
+    router.bind("tcp://127.0.0.1:*").unwrap();
+    let endpoint = router.get_last_endpoint().unwrap().unwrap();
+    let public = server_keys
+        .as_ref()
+        .map_or(z85(3), |x| zmq::z85_encode(&x.public_key).unwrap());
+    let curve = CurveConfig::new().server_key("SID", &public).unwrap();
+    let channel = ZmtpChannel::new(&endpoint, None, Some(Arc::new(curve)));
+    let server = std::thread::spawn(move || {
+        if server_keys.is_some() {
+            let mut frames = router.recv_multipart(0).unwrap();
+            frames.truncate(4);
+            frames.push(vec![0, 0]);
+            frames.push(b"{}".to_vec());
+            frames[2] = vec![zmtp::TYPE_RESPONSE];
+            router.send_multipart(frames, 0).unwrap();
+        }
+    });
+    let response = block_on(channel.request_raw("SID", Request::get("/a")));
+    match curve_supported() {
+        true => assert_eq!(response.unwrap(), "{}"),
+        // Without curve we should fail cleanly (not send in the clear!)
+        false => match response {
+            Err(IoError::Security(_)) => {}
+            _ => panic!("expected security error"),
+        },
+    }
+    server.join().unwrap();
+}
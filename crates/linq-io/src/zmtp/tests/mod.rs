@@ -0,0 +1,2 @@
+mod channel_test;
+mod curve_test;
@@ -90,7 +90,8 @@ extern "C"
         LINQ_REQUEST_METHOD_RAW = 0,
         LINQ_REQUEST_METHOD_GET = 1,
         LINQ_REQUEST_METHOD_POST = 2,
-        LINQ_REQUEST_METHOD_DELETE = 3
+        LINQ_REQUEST_METHOD_DELETE = 3,
+        LINQ_REQUEST_METHOD_PUT = 4
     } E_LINQ_REQUEST_METHOD;
 
     // An error code
@@ -16,12 +16,6 @@
 #define IN 1 | LIBUSB_ENDPOINT_IN
 #define OUT 2 | LIBUSB_ENDPOINT_OUT
 
-// TODO we should have proper Vendor and product ID's.
-#define K64_VID 0x0461
-#define K64_PID 0x0020
-#define M5_VID 0x3333
-#define M5_PID 0x4444
-
 #define USBH_DEVICE_SUMMARY_FORMAT                                             \
     "{"                                                                        \
     "\"vendor\":%d,"                                                           \
@@ -168,8 +162,7 @@ usbh_create()
         if (!self->devices) usb_fatal("Failed to allocated hash map devices!");
         self->products = product_map_create();
         if (!self->products) usb_fatal("Failed to allocate hash map products!");
-        usbh_add_product(self, LINQ_TRANSPORT_USB_HID, K64_VID, K64_PID);
-        usbh_add_product(self, LINQ_TRANSPORT_USB_CDC, M5_VID, M5_PID);
+        // NOTE products are registered by the caller (see usbh_add_product)
     }
     return self;
 }
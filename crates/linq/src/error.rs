@@ -1,4 +1,4 @@
-use linq_io::error::IoError;
+pub use linq_io::error::{ApiErrorKind, IoError};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -9,6 +9,18 @@ pub enum LinqError {
     #[error("failed to parse => {0}")]
     Parser(String),
 
+    #[error("no device found")]
+    NoDevice,
+
+    #[error("more than one device found, pick one with --serial => {0}")]
+    AmbiguousDevice(String),
+
+    #[error("invalid argument => {0}")]
+    InvalidArgument(String),
+
+    #[error("device failed verification => {0}")]
+    Verify(String),
+
     #[error("io error => {0}")]
     StdIo(#[from] std::io::Error),
 
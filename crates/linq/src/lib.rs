@@ -7,5 +7,9 @@ extern crate serde_json;
 extern crate thiserror;
 
 pub mod error;
+pub use linq_db::k64::{About, PATHS};
 pub use linq_io::io;
 pub use linq_io::Request;
+pub use linq_io::{http, DashboardUpdatePackets};
+pub use linq_io::{capture, k64, CurveConfig};
+pub use linq_io::{DeviceMetadata, Location, UsbMetadata};